@@ -0,0 +1,279 @@
+use colored::Colorize;
+use log::info;
+
+use crate::{
+    bpr::BprAudit,
+    codeowners::{CodeownersAudit, TeamInCodeownersAudit},
+    compliance::ComplianceAudit,
+    config::Config,
+    deploy_key::DeployKeyAudit,
+    external_collaborator::ExternalCollaboratorAudit,
+    finding::Severity,
+    members::{AdminAudit, MembersAudit},
+    output::AuditResults,
+    preflight::{AuditKind, PreflightOptions},
+    teams::{EmptyTeamsAudit, TeamPermissionsAudit},
+    Bootstrap, Error, Findings, Skipped,
+};
+
+/// Something we can check about an organization. Each audit is also a subcommand, with the same name
+pub trait Audit {
+    /// The name of the subcommand that runs the audit, e.g., `deploy-keys`
+    fn name(&self) -> &'static str;
+
+    /// What the audit looks at, in one line
+    fn description(&self) -> &'static str;
+
+    /// The permissions a GitHub App or a fine-grained PAT needs to run the audit, e.g., `members: read`
+    fn required_permissions(&self) -> &'static [&'static str];
+
+    /// Which audit the preflight should check the token for
+    fn kind(&self) -> AuditKind;
+
+    /// What the preflight needs to know to check the endpoints this audit will use
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions::default()
+    }
+
+    /// Fill in the options that were not given on the command line with the defaults of the configuration file.
+    /// Flags can only turn options on: an option turned on in the file can't be turned off from the command line
+    fn apply_config(&mut self, _config: &mut Config) {}
+
+    /// Run the audit. Audits that only list things (e.g., members) record what they list as info findings
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error>;
+}
+
+/// The names of all the audits, in the order they are run by `all`
+pub const AUDIT_NAMES: [&str; 10] = [
+    "ec",
+    "deploy-keys",
+    "members",
+    "admins",
+    "bpr",
+    "team-perms",
+    "empty-teams",
+    "codeowners",
+    "compliance",
+    "team-in-codeowners",
+];
+
+/// Every audit, with its default options, in the order of `AUDIT_NAMES`.
+/// `repos` limits the audits that accept a list of repositories. The audits about a single team
+/// need a `team`: without one, they are left out
+pub fn registry(repos: Option<Vec<String>>, team: Option<String>) -> Vec<Box<dyn Audit>> {
+    let mut audits: Vec<Box<dyn Audit>> = vec![
+        Box::new(ExternalCollaboratorAudit::default()),
+        Box::new(DeployKeyAudit::default()),
+        Box::new(MembersAudit),
+        Box::new(AdminAudit {
+            repos: repos.clone(),
+        }),
+        Box::new(BprAudit {
+            repos: repos.clone(),
+        }),
+    ];
+    if let Some(team) = &team {
+        audits.push(Box::new(TeamPermissionsAudit { team: team.clone() }));
+    }
+    audits.push(Box::new(EmptyTeamsAudit));
+    audits.push(Box::new(CodeownersAudit {
+        repos: repos.clone(),
+        ..Default::default()
+    }));
+    audits.push(Box::new(ComplianceAudit {
+        repos: repos.clone(),
+        ..Default::default()
+    }));
+    if let Some(team) = team {
+        audits.push(Box::new(TeamInCodeownersAudit {
+            team,
+            repos,
+            search: false,
+        }));
+    }
+    audits
+}
+
+/// Run several audits one after the other. The org's repositories, members and teams are
+/// fetched once and shared by all of them.
+/// An audit that fails doesn't stop the others: the failed audits are reported at the end, and their errors returned
+/// along with the findings of the others
+pub fn run_audits(bootstrap: &Bootstrap, audits: &[Box<dyn Audit>]) -> AuditResults {
+    let mut results = AuditResults::new();
+    let mut failed = Skipped::default();
+    for (i, audit) in audits.iter().enumerate() {
+        info!(
+            "\n{} {}",
+            format!(
+                "[{}/{}] Running the {} audit:",
+                i + 1,
+                audits.len(),
+                audit.name()
+            )
+            .white()
+            .bold(),
+            audit.description().white()
+        );
+        let result = audit.run(bootstrap);
+        if let Err(e) = &result {
+            failed.record(audit.name(), e.clone());
+        }
+        results.push((audit.name(), result));
+    }
+
+    info!("\n{}", "All done!".white().bold());
+    failed.report();
+    let mut findings = Findings::default();
+    for audit_findings in results
+        .iter()
+        .filter_map(|(_, result)| result.as_ref().ok())
+    {
+        for finding in audit_findings.iter() {
+            findings.record_quietly(finding.clone());
+        }
+    }
+    findings.report();
+    results
+}
+
+/// How a run ended, which is the exit code of the process, so that CI pipelines can be blocked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Every audit looked at everything, and no finding is at or above `--fail-on`
+    Success,
+    /// An audit could not run at all, or the tool could not start (e.g., `GH_TOKEN` is missing)
+    Fatal,
+    /// This many findings are at or above `--fail-on`
+    Findings(usize),
+    /// This many targets (e.g., repositories) were skipped because of errors, so the findings may be incomplete
+    Partial(usize),
+}
+
+impl ExitStatus {
+    /// How a run with these results ended. When several apply, the first one in this order wins:
+    /// a failed audit, findings at or above `fail_on`, skipped targets
+    pub fn of(results: &AuditResults, fail_on: Option<Severity>) -> Self {
+        if results.iter().any(|(_, result)| result.is_err()) {
+            return ExitStatus::Fatal;
+        }
+        let findings = results
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().ok());
+        let failing = fail_on.map_or(0, |threshold| {
+            findings
+                .clone()
+                .flat_map(|findings| findings.iter())
+                .filter(|finding| finding.severity() >= threshold)
+                .count()
+        });
+        let skipped: usize = findings.map(|findings| findings.skipped().len()).sum();
+        match (failing, skipped) {
+            (0, 0) => ExitStatus::Success,
+            (0, skipped) => ExitStatus::Partial(skipped),
+            (failing, _) => ExitStatus::Findings(failing),
+        }
+    }
+
+    /// 2 is left out: it is what the command line parser exits with when the arguments are wrong
+    pub fn code(self) -> i32 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::Fatal => 1,
+            ExitStatus::Findings(_) => 3,
+            ExitStatus::Partial(_) => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::finding::{Finding, Target, ADMIN_USER, EC_ACCESS, EC_NEW_ACCESS};
+
+    /// The findings of an audit: one medium, one info, and `skipped` skipped repositories
+    fn findings(skipped: usize) -> Findings {
+        let target = Target::RepositoryUser {
+            repo: "api".to_string(),
+            login: "eve".to_string(),
+        };
+        let mut findings = Findings::default();
+        findings.record_quietly(Finding::new(&EC_NEW_ACCESS, target.clone(), "new"));
+        findings.record_quietly(Finding::new(&EC_ACCESS, target, "access"));
+        let mut skipped_targets = Skipped::default();
+        for i in 0..skipped {
+            skipped_targets.record(format!("repo-{i}"), Error::Config("nope".to_string()));
+        }
+        findings.with_skipped(skipped_targets)
+    }
+
+    fn failed() -> Result<Findings, Error> {
+        Err(Error::Config("boom".to_string()))
+    }
+
+    #[test]
+    fn success_without_failing_findings_or_skipped_targets() {
+        let results: AuditResults = vec![("ec", Ok(findings(0)))];
+        assert_eq!(ExitStatus::of(&results, None), ExitStatus::Success);
+        assert_eq!(
+            ExitStatus::of(&results, Some(Severity::High)),
+            ExitStatus::Success
+        );
+        assert_eq!(
+            ExitStatus::of(&vec![], Some(Severity::Info)),
+            ExitStatus::Success
+        );
+        assert_eq!(ExitStatus::Success.code(), 0);
+    }
+
+    #[test]
+    fn findings_at_or_above_fail_on() {
+        let results: AuditResults = vec![("ec", Ok(findings(0)))];
+        assert_eq!(
+            ExitStatus::of(&results, Some(Severity::Medium)),
+            ExitStatus::Findings(1)
+        );
+        assert_eq!(
+            ExitStatus::of(&results, Some(Severity::Info)),
+            ExitStatus::Findings(2)
+        );
+        assert_eq!(ExitStatus::Findings(1).code(), 3);
+    }
+
+    #[test]
+    fn skipped_targets_make_a_partial_run() {
+        let results: AuditResults = vec![("ec", Ok(findings(2))), ("admins", Ok(findings(1)))];
+        // Findings don't count without --fail-on, or below it
+        assert_eq!(ExitStatus::of(&results, None), ExitStatus::Partial(3));
+        assert_eq!(
+            ExitStatus::of(&results, Some(Severity::High)),
+            ExitStatus::Partial(3)
+        );
+        assert_eq!(ExitStatus::Partial(3).code(), 4);
+    }
+
+    #[test]
+    fn failing_findings_win_over_skipped_targets() {
+        let mut admins = findings(1);
+        admins.record_quietly(Finding::new(
+            &ADMIN_USER,
+            Target::User {
+                login: "mallory".to_string(),
+            },
+            "admin",
+        ));
+        let results: AuditResults = vec![("ec", Ok(findings(2))), ("admins", Ok(admins))];
+        assert_eq!(
+            ExitStatus::of(&results, Some(Severity::Medium)),
+            ExitStatus::Findings(3)
+        );
+    }
+
+    #[test]
+    fn a_failed_audit_wins_over_everything() {
+        let results: AuditResults = vec![("ec", Ok(findings(2))), ("admins", failed())];
+        for fail_on in [None, Some(Severity::Info), Some(Severity::Critical)] {
+            assert_eq!(ExitStatus::of(&results, fail_on), ExitStatus::Fatal);
+        }
+        assert_eq!(ExitStatus::Fatal.code(), 1);
+    }
+}
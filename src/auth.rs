@@ -0,0 +1,366 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use colored::Colorize;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use log::info;
+
+use crate::{with_retries, Bootstrap, Error, GitHubHttpResponse};
+
+/// We get a new installation token this long before the current one expires,
+/// so that a request never goes out with a token that expires on the way.
+const REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// GitHub rejects JWTs that are valid for more than 10 minutes
+const JWT_LIFETIME: Duration = Duration::from_secs(9 * 60);
+
+/// We backdate JWTs a little, in case our clock is ahead of GitHub's
+const JWT_BACKDATE: Duration = Duration::from_secs(60);
+
+/// How we authenticate to GitHub
+pub enum Auth {
+    /// A personal access token (classic or fine-grained)
+    Token(String),
+    /// A GitHub App installed on the organization
+    App(AppAuth),
+}
+
+/// Authentication as a GitHub App. The app's private key signs a short-lived JWT, which we
+/// exchange for an installation token that is valid for one hour. We get a new one
+/// when it's about to expire, so long runs keep working.
+/// For more info, see https://docs.github.com/en/apps/creating-github-apps/authenticating-with-a-github-app/authenticating-as-a-github-app-installation
+pub struct AppAuth {
+    app_id: String,
+    key: EncodingKey,
+    /// If not given by the user, we ask GitHub for the installation on the organization
+    installation_id: OnceLock<u64>,
+    /// Shared by all threads. Whoever finds it expired gets a new one while the others wait
+    token: Mutex<Option<InstallationToken>>,
+}
+
+struct InstallationToken {
+    token: String,
+    expires_at: SystemTime,
+    permissions: BTreeMap<String, String>,
+}
+
+#[derive(serde::Serialize)]
+struct Claims {
+    iat: u64,
+    exp: u64,
+    iss: String,
+}
+
+#[derive(serde::Deserialize)]
+struct AccessTokenResponse {
+    token: String,
+    expires_at: String,
+    /// E.g., `members: read`. Only what the installation was granted, which may be less than the app asks for
+    #[serde(default)]
+    permissions: BTreeMap<String, String>,
+}
+
+#[derive(serde::Deserialize)]
+struct InstallationResponse {
+    id: u64,
+}
+
+impl Auth {
+    /// The value of the `Authorization` header for the next request
+    pub(crate) fn authorization(&self, bootstrap: &Bootstrap) -> Result<String, Error> {
+        match self {
+            Auth::Token(token) => Ok(format!("Bearer {token}")),
+            Auth::App(app) => Ok(format!(
+                "Bearer {}",
+                app.installation_token(bootstrap, SystemTime::now())?
+            )),
+        }
+    }
+
+    /// Forget the current installation token, e.g., because GitHub told us it is not valid anymore.
+    /// Returns whether there was anything to forget, i.e., whether sending the request again could help.
+    pub(crate) fn invalidate(&self) -> bool {
+        match self {
+            Auth::Token(_) => false,
+            Auth::App(app) => app.token.lock().unwrap().take().is_some(),
+        }
+    }
+}
+
+impl AppAuth {
+    /// Args:
+    /// * `app_id` - The app's ID (or client ID)
+    /// * `private_key` - The app's private key, in PEM format
+    /// * `installation_id` - The installation of the app on the organization. If `None`, we ask GitHub
+    pub fn new(
+        app_id: String,
+        private_key: &[u8],
+        installation_id: Option<u64>,
+    ) -> Result<Self, Error> {
+        let key = EncodingKey::from_rsa_pem(private_key).map_err(|e| {
+            Error::Config(format!(
+                "The GitHub App private key is not a valid RSA key: {e}"
+            ))
+        })?;
+        let installation = OnceLock::new();
+        if let Some(id) = installation_id {
+            let _ = installation.set(id);
+        }
+        Ok(Self {
+            app_id,
+            key,
+            installation_id: installation,
+            token: Mutex::new(None),
+        })
+    }
+
+    pub fn app_id(&self) -> &str {
+        &self.app_id
+    }
+
+    /// The permissions of the current installation token. Empty if we haven't got one yet
+    pub fn permissions(&self) -> BTreeMap<String, String> {
+        self.token
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|t| t.permissions.clone())
+            .unwrap_or_default()
+    }
+
+    /// What the JWT of the app says, if created at `now`
+    fn claims(&self, now: SystemTime) -> Claims {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Claims {
+            iat: (now - JWT_BACKDATE).as_secs(),
+            exp: (now + JWT_LIFETIME).as_secs(),
+            iss: self.app_id.clone(),
+        }
+    }
+
+    /// Create a JWT to authenticate as the app itself
+    fn jwt(&self) -> Result<String, Error> {
+        let claims = self.claims(SystemTime::now());
+        jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.key)
+            .map_err(|e| Error::Config(format!("Could not sign the GitHub App JWT: {e}")))
+    }
+
+    /// Send a request authenticated as the app. These don't go through the rate limiter:
+    /// they are few, and they are not counted against the installation's rate limit.
+    fn app_request(
+        &self,
+        bootstrap: &Bootstrap,
+        method: reqwest::Method,
+        path: &str,
+    ) -> Result<String, Error> {
+        let url = bootstrap.api_endpoint(path);
+        let response = with_retries(&url, bootstrap.retries.unwrap_or(3), || {
+            let response = bootstrap
+                .client
+                .request(method.clone(), &url)
+                .header("User-Agent", "GitHub EC Audit")
+                .header("Accept", "application/vnd.github+json")
+                .header("X-GitHub-Api-Version", "2022-11-28")
+                .header("Authorization", format!("Bearer {}", self.jwt()?))
+                .send()?;
+            Ok(GitHubHttpResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: response.text()?,
+            })
+        })?;
+        Ok(response.body)
+    }
+
+    /// The installation of the app on the organization we are auditing
+    pub fn installation_id(&self, bootstrap: &Bootstrap) -> Result<u64, Error> {
+        if let Some(id) = self.installation_id.get() {
+            return Ok(*id);
+        }
+        let body = self
+            .app_request(
+                bootstrap,
+                reqwest::Method::GET,
+                &format!("/orgs/{}/installation", bootstrap.org),
+            )
+            .map_err(|e| {
+                Error::Config(format!(
+                    "Could not find the installation of GitHub App {} on {}: {e}",
+                    self.app_id, bootstrap.org
+                ))
+            })?;
+        let id = serde_json::from_str::<InstallationResponse>(&body)?.id;
+        Ok(*self.installation_id.get_or_init(|| id))
+    }
+
+    /// Return an installation token that is still valid at `now`, getting a new one if needed
+    fn installation_token(&self, bootstrap: &Bootstrap, now: SystemTime) -> Result<String, Error> {
+        let mut token = self.token.lock().unwrap();
+        if let Some(current) = token.as_ref() {
+            if now + REFRESH_MARGIN < current.expires_at {
+                return Ok(current.token.clone());
+            }
+        }
+
+        let installation_id = self.installation_id(bootstrap)?;
+        let body = self.app_request(
+            bootstrap,
+            reqwest::Method::POST,
+            &format!("/app/installations/{installation_id}/access_tokens"),
+        )?;
+        let response = serde_json::from_str::<AccessTokenResponse>(&body)?;
+        let expires_at = chrono::DateTime::parse_from_rfc3339(&response.expires_at)
+            .map_err(|e| {
+                Error::Decode(format!(
+                    "Invalid expiration date for the installation token: {e}"
+                ))
+            })?
+            .into();
+
+        if token.is_some() {
+            info!(
+                "{}",
+                "My GitHub App installation token is about to expire, I got a new one".yellow()
+            );
+        }
+        *token = Some(InstallationToken {
+            token: response.token.clone(),
+            expires_at,
+            permissions: response.permissions,
+        });
+        Ok(response.token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{DecodingKey, Validation};
+
+    use super::*;
+    use crate::mock_server::MockServer;
+
+    /// A key pair generated for these tests only
+    const PRIVATE_KEY: &[u8] = include_bytes!("../testdata/github-app.pem");
+    const PUBLIC_KEY: &[u8] = include_bytes!("../testdata/github-app.pub.pem");
+
+    #[derive(serde::Deserialize)]
+    struct DecodedClaims {
+        iat: u64,
+        exp: u64,
+        iss: String,
+    }
+
+    fn app(installation_id: Option<u64>) -> AppAuth {
+        AppAuth::new("12345".to_string(), PRIVATE_KEY, installation_id).unwrap()
+    }
+
+    fn at(time: &str) -> SystemTime {
+        chrono::DateTime::parse_from_rfc3339(time).unwrap().into()
+    }
+
+    fn unix(time: &str) -> u64 {
+        at(time).duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn access_token(token: &str, expires_at: &str) -> (u16, String) {
+        (
+            201,
+            serde_json::json!({
+                "token": token,
+                "expires_at": expires_at,
+                "permissions": { "members": "read", "metadata": "read" },
+            })
+            .to_string(),
+        )
+    }
+
+    /// Check the signature of the JWT in an `Authorization` header and return its claims
+    fn decode_jwt(authorization: &str) -> DecodedClaims {
+        let jwt = authorization.strip_prefix("Bearer ").unwrap();
+        let mut validation = Validation::new(Algorithm::RS256);
+        validation.set_required_spec_claims(&["iat", "exp", "iss"]);
+        jsonwebtoken::decode::<DecodedClaims>(
+            jwt,
+            &DecodingKey::from_rsa_pem(PUBLIC_KEY).unwrap(),
+            &validation,
+        )
+        .unwrap()
+        .claims
+    }
+
+    #[test]
+    fn jwt_is_backdated_and_valid_for_less_than_ten_minutes() {
+        let claims = app(None).claims(at("2030-01-01T00:00:00Z"));
+        assert_eq!(claims.iat, unix("2029-12-31T23:59:00Z"));
+        assert_eq!(claims.exp, unix("2030-01-01T00:09:00Z"));
+        assert_eq!(claims.iss, "12345");
+
+        let decoded = decode_jwt(&format!("Bearer {}", app(None).jwt().unwrap()));
+        assert_eq!(decoded.exp - decoded.iat, 10 * 60);
+        assert_eq!(decoded.iss, "12345");
+    }
+
+    #[test]
+    fn installation_is_looked_up_then_exchanged_for_a_token() {
+        let server = MockServer::start(vec![
+            (200, r#"{"id": 42}"#.to_string()),
+            access_token("ghs_first", "2030-01-01T01:00:00Z"),
+        ]);
+        let bootstrap = Bootstrap::for_tests(&server.url);
+        let app = app(None);
+        let now = at("2030-01-01T00:00:00Z");
+
+        assert_eq!(
+            app.installation_token(&bootstrap, now).unwrap(),
+            "ghs_first"
+        );
+        // Both the installation and the token are reused
+        assert_eq!(
+            app.installation_token(&bootstrap, now).unwrap(),
+            "ghs_first"
+        );
+        assert_eq!(app.installation_id(&bootstrap).unwrap(), 42);
+        assert_eq!(app.permissions()["members"], "read");
+
+        let requests = server.requests();
+        let sent = requests
+            .iter()
+            .map(|r| (r.method.as_str(), r.path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sent,
+            [
+                ("GET", "/orgs/my-org/installation"),
+                ("POST", "/app/installations/42/access_tokens"),
+            ]
+        );
+        for request in &requests {
+            assert_eq!(decode_jwt(&request.headers["authorization"]).iss, "12345");
+        }
+    }
+
+    #[test]
+    fn token_is_refreshed_five_minutes_before_it_expires() {
+        let server = MockServer::start(vec![
+            access_token("ghs_first", "2030-01-01T01:00:00Z"),
+            access_token("ghs_second", "2030-01-01T02:00:00Z"),
+        ]);
+        let bootstrap = Bootstrap::for_tests(&server.url);
+        let app = app(Some(42));
+
+        let token = |time| app.installation_token(&bootstrap, at(time)).unwrap();
+        assert_eq!(token("2030-01-01T00:00:00Z"), "ghs_first");
+        assert_eq!(token("2030-01-01T00:54:59Z"), "ghs_first");
+        assert_eq!(token("2030-01-01T00:55:00Z"), "ghs_second");
+        assert_eq!(token("2030-01-01T01:54:59Z"), "ghs_second");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|r| r.path == "/app/installations/42/access_tokens"));
+    }
+}
@@ -2,36 +2,37 @@ use std::fmt::Display;
 
 use colored::Colorize;
 
-use crate::{make_github_request, Bootstrap};
+use crate::{
+    audit::Audit,
+    finding::{self, Target},
+    make_github_request, output,
+    preflight::{AuditKind, PreflightOptions},
+    Bootstrap, Error, Finding, Findings, Skipped,
+};
 
-fn get_default_branch(bootstrap: &Bootstrap, repo: impl Display) -> String {
-    match make_github_request(
-        &bootstrap.token,
+fn get_default_branch(bootstrap: &Bootstrap, repo: impl Display) -> Result<String, Error> {
+    let res = make_github_request(
+        bootstrap,
         &format!("/repos/{}/{repo}", bootstrap.org),
         3,
         None,
-    ) {
-        Ok(res) => {
-            res.get("default_branch")
-                .unwrap()
-                .as_str()
-                .unwrap()
-                .to_string() // unwraps OK: required field in GH response
-        }
-        Err(e) => {
-            panic!(
-                "{} for repo {}: {}",
-                "I couldn't fetch the repo's default branch".red(),
-                repo,
-                e
-            );
-        }
-    }
+    )?;
+    res.get("default_branch")
+        .and_then(|b| b.as_str())
+        .map(|b| b.to_string())
+        .ok_or(Error::Decode(format!(
+            "The repo {repo} has no default branch"
+        )))
 }
 
-fn get_bprs(bootstrap: &Bootstrap, repo: impl Display, branch: impl Display) -> String {
+/// Returns `None` if the branch is not protected
+fn get_bprs(
+    bootstrap: &Bootstrap,
+    repo: impl Display,
+    branch: impl Display,
+) -> Result<Option<serde_json::Value>, Error> {
     match make_github_request(
-        &bootstrap.token,
+        bootstrap,
         &format!(
             "/repos/{}/{repo}/branches/{branch}/protection",
             bootstrap.org
@@ -39,66 +40,144 @@ fn get_bprs(bootstrap: &Bootstrap, repo: impl Display, branch: impl Display) ->
         3,
         None,
     ) {
-        Ok(res) => {
-            if res.get("status").is_some() && res.get("status").unwrap() == "404" {
-                return "Empty".to_string();
-            }
-            serde_json::to_string_pretty(&res).unwrap()
-        }
-        Err(e) => {
-            panic!(
-                "{} for repo {}: {}",
-                "I couldn't fetch the repo's BPRs".red(),
-                repo,
-                e
-            );
-        }
+        Ok(res) => Ok(Some(res)),
+        // GitHub answers 404 when the branch is not protected
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
     }
 }
 
-fn get_rulesets(bootstrap: &Bootstrap, repo: impl Display, branch: impl Display) -> String {
-    match make_github_request(
-        &bootstrap.token,
+fn get_rulesets(
+    bootstrap: &Bootstrap,
+    repo: impl Display,
+    branch: impl Display,
+) -> Result<serde_json::Value, Error> {
+    make_github_request(
+        bootstrap,
         &format!("/repos/{}/{repo}/rules/branches/{branch}", bootstrap.org),
         3,
         None,
-    ) {
-        Ok(res) => serde_json::to_string_pretty(&res).unwrap(),
-        Err(e) => {
-            panic!(
-                "{} for repo {}: {}",
-                "I couldn't fetch the repo's rulesets".red(),
-                repo,
-                e
-            );
+    )
+}
+
+/// The branch protection rules and rulesets of each repository's default branch
+pub struct BprAudit {
+    /// Only these repositories. If `None`, all of them
+    pub repos: Option<Vec<String>>,
+}
+
+impl Audit for BprAudit {
+    fn name(&self) -> &'static str {
+        "bpr"
+    }
+
+    fn description(&self) -> &'static str {
+        "Branch protection rules and rulesets of each default branch"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["administration: read", "metadata: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::BranchProtection
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            repos: self.repos.clone(),
+            ..Default::default()
         }
     }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_audit(bootstrap, self.repos.clone())
+    }
 }
 
-pub fn run_audit(bootstrap: Bootstrap, repos: Option<Vec<String>>) {
-    let repos = repos.unwrap_or_else(|| {
-        bootstrap
-            .fetch_all_repositories(75, false)
-            .unwrap()
+pub fn run_audit(bootstrap: &Bootstrap, repos: Option<Vec<String>>) -> Result<Findings, Error> {
+    let repos = match repos {
+        Some(repos) => repos,
+        None => bootstrap
+            .fetch_all_repositories(75, false)?
             .into_iter()
             .map(|r| r.name)
-            .collect::<Vec<String>>()
-    });
+            .collect::<Vec<String>>(),
+    };
 
+    let mut skipped = Skipped::default();
+    let mut findings = Findings::default();
     for repo in repos {
-        let default_branch = get_default_branch(&bootstrap, &repo);
-        println!(
+        let default_branch = match get_default_branch(bootstrap, &repo) {
+            Ok(b) => b,
+            Err(e) => {
+                skipped.record(repo, e);
+                continue;
+            }
+        };
+        let (bprs, rulesets) = match get_bprs(bootstrap, &repo, &default_branch)
+            .and_then(|b| get_rulesets(bootstrap, &repo, &default_branch).map(|r| (b, r)))
+        {
+            Ok(res) => res,
+            Err(e) => {
+                skipped.record(repo, e);
+                continue;
+            }
+        };
+
+        output::line(format!(
             "{} {}\n{} {}\n",
             "          Repo:".yellow(),
             repo.white(),
             "Default branch:".yellow(),
             default_branch.white()
-        );
-
-        let bprs = get_bprs(&bootstrap, &repo, &default_branch);
-        println!("{} {bprs}\n", "          BPRs:".yellow());
+        ));
+        let has_rulesets = rulesets.as_array().is_some_and(|r| !r.is_empty());
+        output::line(format!(
+            "{} {}\n",
+            "          BPRs:".yellow(),
+            match &bprs {
+                Some(bprs) => serde_json::to_string_pretty(bprs)?,
+                None => "Empty".to_string(),
+            }
+        ));
+        output::line(format!(
+            "{} {}\n\n",
+            "      Rulesets:".yellow(),
+            serde_json::to_string_pretty(&rulesets)?
+        ));
 
-        let rulesets = get_rulesets(&bootstrap, &repo, &default_branch);
-        println!("{} {rulesets}\n\n", "      Rulesets:".yellow());
+        findings.record_quietly(
+            Finding::new(
+                &finding::BRANCH_PROTECTION,
+                Target::Repository { repo: repo.clone() },
+                format!(
+                    "The default branch {default_branch} of {repo} has {} and {} rulesets",
+                    if bprs.is_some() {
+                        "a branch protection rule"
+                    } else {
+                        "no branch protection rule"
+                    },
+                    rulesets.as_array().map_or(0, |r| r.len())
+                ),
+            )
+            .with("branch", &default_branch)
+            .with("branch_protection", bprs.is_some())
+            .with("rulesets", rulesets.as_array().map_or(0, |r| r.len())),
+        );
+        if bprs.is_none() && !has_rulesets {
+            findings.record(
+                Finding::new(
+                    &finding::BRANCH_UNPROTECTED,
+                    Target::Repository { repo: repo.clone() },
+                    format!("The default branch {default_branch} of {repo} is not protected"),
+                )
+                .with("branch", &default_branch),
+            );
+        }
     }
+
+    skipped.report();
+    findings.report();
+    Ok(findings.with_skipped(skipped))
 }
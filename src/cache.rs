@@ -0,0 +1,182 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+
+/// How long cached responses are kept when nothing else is configured.
+/// A week leaves plenty of margin for audits that run daily or weekly.
+pub const DEFAULT_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// A response we got from GitHub in a previous run, together with what we need
+/// to ask GitHub whether it is still current.
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct CachedResponse {
+    /// The URL the response was fetched from. Only kept to make the cache easier to inspect
+    pub url: String,
+    /// The `ETag` GitHub sent with the response, which we send back in `If-None-Match`
+    pub etag: String,
+    /// The `Link` header of the response, so we can still paginate when GitHub answers 304
+    pub link: Option<String>,
+    pub body: String,
+    /// Unix timestamp (in seconds) at which the response was stored
+    pub stored_at: u64,
+}
+
+/// An on-disk cache of GitHub responses, keyed by URL.
+/// We don't trust cached responses blindly: every time, we send a conditional request with
+/// `If-None-Match` and only reuse the cached body if GitHub answers `304 Not Modified`.
+/// These answers don't count against the rate limit, so re-running an audit on an
+/// org that hasn't changed much is a lot cheaper.
+/// For more info, see https://docs.github.com/en/rest/using-the-rest-api/best-practices-for-using-the-rest-api#use-conditional-requests-if-appropriate
+pub struct ResponseCache {
+    dir: PathBuf,
+    /// Entries older than this are ignored and replaced
+    ttl: Duration,
+    /// Used to give a unique name to the temporary files we write entries to
+    writes: AtomicU64,
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl ResponseCache {
+    /// Open (and create, if needed) a cache in the given directory
+    pub fn open(dir: impl Into<PathBuf>, ttl: Duration) -> std::io::Result<Self> {
+        let dir = dir.into();
+        create_private_dir(&dir)?;
+        Ok(Self {
+            dir,
+            ttl,
+            writes: AtomicU64::new(0),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn entry_path(&self, url: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        let name = digest
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        self.dir.join(format!("{name}.json"))
+    }
+
+    /// Return the cached response for a URL, if we have one and it has not expired.
+    /// A missing or unreadable entry is simply a cache miss.
+    pub fn get(&self, url: &str) -> Option<CachedResponse> {
+        let content = fs::read_to_string(self.entry_path(url)).ok()?;
+        let entry = serde_json::from_str::<CachedResponse>(&content).ok()?;
+        if entry.url != url || now_unix().saturating_sub(entry.stored_at) > self.ttl.as_secs() {
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Store a response. Failing to write to the cache never fails a request:
+    /// at worst, the next run will have to download the response again.
+    pub fn put(&self, url: &str, etag: &str, link: Option<&str>, body: &str) {
+        let entry = CachedResponse {
+            url: url.to_string(),
+            etag: etag.to_string(),
+            link: link.map(|l| l.to_string()),
+            body: body.to_string(),
+            stored_at: now_unix(),
+        };
+        let Ok(content) = serde_json::to_string(&entry) else {
+            return;
+        };
+
+        // Write to a temporary file first and then rename it, so that concurrent workers
+        // (or an interrupted run) never leave a half-written entry behind
+        let path = self.entry_path(url);
+        let tmp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            self.writes.fetch_add(1, Ordering::Relaxed)
+        ));
+        if fs::write(&tmp, content).is_err() || fs::rename(&tmp, &path).is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+    }
+}
+
+/// The cached responses contain private information about the organization,
+/// so only the current user should be able to read them.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
+/// Where the cache lives when nothing else is configured, following the XDG conventions
+pub fn default_dir() -> Option<PathBuf> {
+    match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir).join("gh-ec-audit")),
+        _ => std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache/gh-ec-audit")),
+    }
+}
+
+/// Parse a TTL such as `3600`, `90m`, `12h` or `7d` (a number without a unit is in seconds)
+pub fn parse_ttl(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number = number.parse::<u64>().map_err(|_| {
+        format!("Invalid TTL [{value}]: expected something like 3600, 90m, 12h or 7d")
+    })?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("Invalid TTL unit [{unit}]: use s, m, h or d")),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("Invalid TTL [{value}]: too long"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ttl_units() {
+        assert_eq!(parse_ttl("3600"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_ttl("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_ttl("90m"), Ok(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_ttl(" 12h "), Ok(Duration::from_secs(12 * 60 * 60)));
+        assert_eq!(parse_ttl("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_ttl("0"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn invalid_ttls() {
+        for value in ["", "h", "-5m", "1.5h", "12 h", "3w", "10hours"] {
+            assert!(parse_ttl(value).is_err(), "{value} should be invalid");
+        }
+        assert_eq!(
+            parse_ttl("99999999999999999d"),
+            Err("Invalid TTL [99999999999999999d]: too long".to_string())
+        );
+    }
+}
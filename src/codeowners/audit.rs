@@ -1,9 +1,10 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::{make_github_request, Bootstrap, Member, Team};
+use crate::{finding, make_github_request, Bootstrap, Error, Finding, Findings, Member, Team};
 
-use super::{CodeownersFile, CodeownersFileProblem};
+use super::CodeownersFile;
 use colored::Colorize;
+use log::{info, log, warn, Level};
 
 /// Run the audit on a given list of CO files
 pub fn audit_co_files(
@@ -13,44 +14,59 @@ pub fn audit_co_files(
     org_teams: &HashMap<String, Team>,
     also_gh_api: bool,
     verbose: bool,
-) {
+) -> Findings {
     // Keep a growing cache of empty and non-empty teams to make checks more efficient.
     let mut non_empty_teams = HashSet::new();
     let mut empty_teams = HashSet::new();
+    let mut findings = Findings::default();
 
     for co_file in codeowners_files {
         // Check if all the users mentioned in the CO file are in the org
         let mut co_problems = co_file
             .users
             .iter()
-            .filter_map(|user| {
+            .filter_map(|(user, &line)| {
                 if !org_members.contains_key(user) {
-                    Some(CodeownersFileProblem::UserNotInOrg {
-                        user: user.to_string(),
-                        co_file_url: co_file.url.clone(),
-                    })
+                    Some(
+                        Finding::new(
+                            &finding::CODEOWNERS_USER_NOT_IN_ORG,
+                            co_file.target(Some(line)),
+                            format!(
+                                "User {user} in CODEOWNERS file {} is not in the org",
+                                co_file.url
+                            ),
+                        )
+                        .with("user", user),
+                    )
                 } else {
                     None
                 }
             })
-            .collect::<Vec<CodeownersFileProblem>>();
+            .collect::<Vec<Finding>>();
 
         // Check if all the teams mentioned in the CO file exist and alert if a team is empty.
         co_problems.extend(
             co_file
                 .teams
                 .iter()
-                .filter_map(|team| {
+                .filter_map(|(team, &line)| {
                     if !org_teams.contains_key(team) {
-                        Some(CodeownersFileProblem::TeamNotInOrg {
-                            team: team.to_string(),
-                            co_file_url: co_file.url.clone(),
-                        })
+                        Some(
+                            Finding::new(
+                                &finding::CODEOWNERS_TEAM_NOT_IN_ORG,
+                                co_file.target(Some(line)),
+                                format!(
+                                    "Team {team} in CODEOWNERS file {} is not in the org",
+                                    co_file.url
+                                ),
+                            )
+                            .with("team", team),
+                        )
                     } else {
                         None
                     }
                 })
-                .collect::<Vec<CodeownersFileProblem>>(),
+                .collect::<Vec<Finding>>(),
         );
 
         // See if we have any warnings
@@ -58,7 +74,7 @@ pub fn audit_co_files(
             co_file
                 .teams
                 .iter()
-                .filter_map(|team| {
+                .filter_map(|(team, &line)| {
                     // Check if the team is empty, by first looking into the cache: if
                     // it's not there, we call the GH API and update the cache accordingly.
                     // Note - the || operator short-circuits, so we are making the call to GH API
@@ -72,13 +88,13 @@ pub fn audit_co_files(
                                         // For some reason, we could not establish if the team is empty. Log a warning and return false (i.e., not empty),
                                         // which is equivalent to ignoring it (because we are not alerting on non-empty teams).
                                         // This should anyway be a very rare circumstance.
-                                        println!(
+                                        warn!(
                                             "{} {} {} {}",
                                             "Warning! I could not determine if team".yellow(),
                                             t.slug.white(),
                                             "is empty. I am going to ignore it. The error was"
                                                 .yellow(),
-                                            e.white()
+                                            e.to_string().white()
                                         );
                                         false
                                     }
@@ -86,10 +102,17 @@ pub fn audit_co_files(
                             } {
                                 // The team is empty
                                 empty_teams.insert(t.slug.clone());
-                                Some(CodeownersFileProblem::EmptyTeam {
-                                    team: team.to_string(),
-                                    co_file_url: co_file.url.clone(),
-                                })
+                                Some(
+                                    Finding::new(
+                                        &finding::CODEOWNERS_EMPTY_TEAM,
+                                        co_file.target(Some(line)),
+                                        format!(
+                                            "CODEOWNERS file {} contains an empty team {team}",
+                                            co_file.url
+                                        ),
+                                    )
+                                    .with("team", team),
+                                )
                             } else {
                                 // The team is not empty. Let's insert it into the non-empty cache (repeated
                                 // insertions don't matter because it's a HashSet).
@@ -104,58 +127,69 @@ pub fn audit_co_files(
                         }
                     }
                 })
-                .collect::<Vec<CodeownersFileProblem>>(),
+                .collect::<Vec<Finding>>(),
         );
 
         // Print all the errors and warnings, if any
         if co_problems.is_empty() {
-            // By default, we print nothing if there was nothing wrong. But we can print if a verbose flag was passed.
-            if verbose {
-                println!(
-                    "{} {}",
-                    "No problems detected in CODEOWNERS file for repo".green(),
-                    co_file.repo.white()
-                );
-            }
+            // By default, we only say there was nothing wrong with -v, or if the verbose option was set in the configuration file
+            let level = if verbose { Level::Info } else { Level::Debug };
+            log!(
+                level,
+                "{} {}",
+                "No problems detected in CODEOWNERS file for repo".green(),
+                co_file.repo.white(),
+            );
         } else {
             for problem in co_problems {
-                println!("{problem}");
+                findings.record(problem);
             }
         }
 
         // If we were told to also use the GH API, we do it here
         if also_gh_api {
-            println!("This is what the GitHub API has to say about this CODEOWNERS file...");
-            audit_co_files_with_gh_api(bootstrap, &co_file.repo);
+            info!("This is what the GitHub API has to say about this CODEOWNERS file...");
+            if let Some(finding) = audit_co_files_with_gh_api(bootstrap, co_file) {
+                findings.record(finding);
+            }
         }
     }
+    findings
 }
 
 /// Audit CODEOWNERS files for errors by asking the GH REST API.  
+/// Returns a finding if GitHub reports any error.
 /// For more info, see https://docs.github.com/en/rest/repos/repos?apiVersion=2022-11-28#list-codeowners-errors
-fn audit_co_files_with_gh_api(bootstrap: &Bootstrap, repo: &str) {
+fn audit_co_files_with_gh_api(bootstrap: &Bootstrap, co_file: &CodeownersFile) -> Option<Finding> {
+    let repo = &co_file.repo;
     // Call the GH API
-    match get_codeowners_errors(&bootstrap, &repo) {
-        Ok(Some(kinds)) => {
+    match get_codeowners_errors(bootstrap, repo) {
+        Ok(Some(errors)) => {
             // A CODEOWNERS file was found and a (possibly empty) Vec of errors was returned
-            if kinds.is_empty() {
-                println!(
+            if errors.is_empty() {
+                info!(
                     "{} {}",
                     "No errors detected in CODEOWNERS file for repo".green(),
-                    repo.white()
+                    repo.white(),
                 );
             } else {
-                println!(
-                    "{} {}: {:?}",
-                    "Errors detected in CODEOWNERS file for repo".red(),
-                    repo.white(),
-                    kinds
+                let kinds: Vec<&str> = errors.iter().map(|e| e.kind.as_str()).collect();
+                let lines: Vec<String> = errors.iter().map(|e| e.line.to_string()).collect();
+                return Some(
+                    Finding::new(
+                        &finding::CODEOWNERS_GITHUB_ERRORS,
+                        // We point to the first error, the others are in the evidence
+                        co_file.target(errors.iter().map(|e| e.line).min()),
+                        format!("Errors detected in CODEOWNERS file for repo {repo}: {kinds:?}"),
+                    )
+                    .with("errors", kinds.join(", "))
+                    .with("lines", lines.join(", ")),
                 );
             }
         }
         Ok(None) => {
             // A CODEOWNERS file was not found
-            println!(
+            warn!(
                 "{} {}",
                 "CODEOWNERS file not found for repository".yellow(),
                 repo.white()
@@ -163,39 +197,48 @@ fn audit_co_files_with_gh_api(bootstrap: &Bootstrap, repo: &str) {
         }
         Err(e) => {
             // The call to GH failed
-            println!(
+            warn!(
                 "{} {} {} {}",
                 "Warning! Call to GitHub API failed with error".yellow(),
-                e.white(),
+                e.to_string().white(),
                 "for repo".yellow(),
                 repo.white()
             );
         }
     }
+    None
+}
+
+/// An error GitHub found in a CODEOWNERS file
+#[derive(serde::Deserialize)]
+struct CodeownersError {
+    /// e.g., `Unknown owner`
+    kind: String,
+    /// Starting from 1
+    line: usize,
+}
+
+#[derive(serde::Deserialize)]
+struct CodeownersErrors {
+    errors: Vec<CodeownersError>,
 }
 
 /// Call the GH API and retrieve errors detected in the CODEOWNERS file.
-fn get_codeowners_errors(bootstrap: &Bootstrap, repo: &str) -> Result<Option<Vec<String>>, String> {
+fn get_codeowners_errors(
+    bootstrap: &Bootstrap,
+    repo: &str,
+) -> Result<Option<Vec<CodeownersError>>, Error> {
     let url = format!("/repos/{}/{repo}/codeowners/errors", bootstrap.org);
-    let res = make_github_request(&bootstrap.token, &url, 3, None)?;
-    match res.get("errors") {
-        None => {
-            // If this field is not present, it means a CO file has not been found
-            return Ok(None);
-        }
-        Some(errors) => {
-            let kinds: Vec<String> = errors
-                .as_array()
-                .ok_or("Unexpected format received: it's not an array".to_string())?
-                .iter()
-                .map(|e| {
-                    e.get("kind")
-                        .and_then(|k| k.as_str())
-                        .and_then(|s| Some(s.to_string()))
-                        .unwrap_or("Unknown".to_string())
-                })
-                .collect();
-            Ok(Some(kinds))
-        }
+    let res = match make_github_request(bootstrap, &url, 3, None) {
+        Ok(res) => res,
+        // GitHub answers 404 when there is no CODEOWNERS file
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if res.get("errors").is_none() {
+        // If this field is not present, it means a CO file has not been found
+        return Ok(None);
     }
+    let res: CodeownersErrors = serde_json::from_value(res)?;
+    Ok(Some(res.errors))
 }
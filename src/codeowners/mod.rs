@@ -4,13 +4,26 @@ mod iterate;
 /// Leverage the GH search API to find relevant information
 mod search;
 
-use std::{collections::HashSet, fmt::Display};
+use std::collections::BTreeMap;
 
-use crate::{members::get_indexed_org_members, teams::get_indexed_org_teams, Bootstrap};
+use crate::{
+    audit::Audit,
+    config::Config,
+    finding::Target,
+    members::get_indexed_org_members,
+    preflight::{AuditKind, PreflightOptions},
+    teams::get_indexed_org_teams,
+    Bootstrap, Error, Findings, Skipped,
+};
 use colored::Colorize;
 use lazy_static::lazy_static;
+use log::{error, info};
 use regex::Regex;
 
+/// Locations where a CODEOWNERS file can be placed, sorted by priority.
+/// For more info, see https://docs.github.com/en/repositories/managing-your-repositorys-settings-and-features/customizing-your-repository/about-code-owners#codeowners-file-location
+pub const CO_LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
 lazy_static! {
     /// Regex used to find matches in CO files that look like @something.
     static ref co_regex: Regex = Regex::new(r"@\S+").unwrap();
@@ -20,29 +33,42 @@ lazy_static! {
 struct CodeownersFile {
     /// Repository this CO file belongs to
     repo: String,
+    /// Where the file is in the repository, e.g., `.github/CODEOWNERS`
+    path: String,
     /// HTML URL, to give the user a quick way to reach the file
     url: String,
-    /// List of all users mentioned in the file, for further analysis
-    users: HashSet<String>,
-    /// List of all teams mentioned in the file, for further analysis
-    teams: HashSet<String>,
+    /// All users mentioned in the file, for further analysis, with the first line where they are mentioned
+    users: BTreeMap<String, usize>,
+    /// All teams mentioned in the file, for further analysis, with the first line where they are mentioned
+    teams: BTreeMap<String, usize>,
 }
 
 impl CodeownersFile {
+    /// What findings about this file (or one of its lines) point to
+    fn target(&self, line: Option<usize>) -> Target {
+        Target::File {
+            repo: self.repo.clone(),
+            path: self.path.clone(),
+            url: self.url.clone(),
+            line,
+        }
+    }
+
     /// Process the content of a CO file and turn it into a CodeownersFile struct
     fn parse_from_content(
         bootstrap: &Bootstrap,
         content: String,
         html_url: &str,
         repo: &str,
+        path: &str,
     ) -> CodeownersFile {
-        let mut users = HashSet::new();
-        let mut teams = HashSet::new();
+        let mut users = BTreeMap::new();
+        let mut teams = BTreeMap::new();
 
         // The prefix that teams have in CO files
         let team_prefix = format!("@{}/", bootstrap.org);
 
-        for line in content.split('\n') {
+        for (number, line) in (1..).zip(content.split('\n')) {
             if line.trim().starts_with('#') {
                 // Skip comments
                 continue;
@@ -52,16 +78,21 @@ impl CodeownersFile {
                 let matched = m.as_str();
                 if matched.starts_with(&team_prefix) {
                     // It's a team
-                    teams.insert(matched.trim_start_matches(&team_prefix).to_string());
+                    teams
+                        .entry(matched.trim_start_matches(&team_prefix).to_string())
+                        .or_insert(number);
                 } else {
                     // It's a user
-                    users.insert(matched.trim_start_matches("@").to_string());
+                    users
+                        .entry(matched.trim_start_matches("@").to_string())
+                        .or_insert(number);
                 }
             }
         }
 
         CodeownersFile {
             repo: repo.to_string(),
+            path: path.to_string(),
             url: html_url.to_string(),
             users,
             teams,
@@ -69,45 +100,106 @@ impl CodeownersFile {
     }
 }
 
-/// The problems we can encounter while auditing a CODEOWNERS file
-enum CodeownersFileProblem {
-    UserNotInOrg { user: String, co_file_url: String },
-    TeamNotInOrg { team: String, co_file_url: String },
-    EmptyTeam { team: String, co_file_url: String },
+/// Users and teams in CODEOWNERS files that are not in the org, or empty
+#[derive(Default)]
+pub struct CodeownersAudit {
+    /// Only these repositories. If `None`, all of them
+    pub repos: Option<Vec<String>>,
+    /// Find the files with the code search API instead of looking into every repository
+    pub search: bool,
+    /// Also ask GitHub for the errors it finds in the files
+    pub also_gh_api: bool,
+    /// Also say so when a file has no problems
+    pub verbose: bool,
 }
 
-impl Display for CodeownersFileProblem {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let msg = match self {
-            CodeownersFileProblem::EmptyTeam { team, co_file_url } => format!(
-                "{} {} {} {}",
-                "Warning! CODEOWNERS file".yellow(),
-                co_file_url.white(),
-                "contains an empty team".yellow(),
-                team.white(),
-            ),
-            CodeownersFileProblem::UserNotInOrg { user, co_file_url } => {
-                format!(
-                    "{} {} {} {} {}",
-                    "Error in CODEOWNERS file".red(),
-                    co_file_url.white(),
-                    "User".red(),
-                    user.white(),
-                    "is not in the org".red()
-                )
-            }
-            CodeownersFileProblem::TeamNotInOrg { team, co_file_url } => {
-                format!(
-                    "{} {} {} {} {}",
-                    "Error in CODEOWNERS file".red(),
-                    co_file_url.white(),
-                    "Team".red(),
-                    team.white(),
-                    "is not in the org".red()
-                )
-            }
-        };
-        write!(f, "{msg}")
+impl Audit for CodeownersAudit {
+    fn name(&self) -> &'static str {
+        "codeowners"
+    }
+
+    fn description(&self) -> &'static str {
+        "Users and teams in CODEOWNERS files that are not in the org, or empty"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["contents: read", "members: read", "metadata: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::Codeowners
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            repos: self.repos.clone(),
+            search: self.search,
+            also_gh_api: self.also_gh_api,
+            ..Default::default()
+        }
+    }
+
+    fn apply_config(&mut self, config: &mut Config) {
+        // Searching is org-wide, so it makes no sense with repositories given on the command line
+        self.search |= config.codeowners.search && self.repos.is_none();
+        self.also_gh_api |= config.codeowners.also_gh_api;
+        self.verbose |= config.codeowners.verbose;
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_codeowners_audit(
+            bootstrap,
+            self.repos.clone(),
+            self.search,
+            self.also_gh_api,
+            self.verbose,
+        )
+    }
+}
+
+/// Where a team is mentioned in CODEOWNERS files
+pub struct TeamInCodeownersAudit {
+    /// The slug of the team
+    pub team: String,
+    /// Only these repositories. If `None`, all of them
+    pub repos: Option<Vec<String>>,
+    /// Find the files with the code search API instead of looking into every repository
+    pub search: bool,
+}
+
+impl Audit for TeamInCodeownersAudit {
+    fn name(&self) -> &'static str {
+        "team-in-codeowners"
+    }
+
+    fn description(&self) -> &'static str {
+        "Find where a team is mentioned in CODEOWNERS files"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["contents: read", "metadata: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::TeamInCodeowners
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            repos: self.repos.clone(),
+            team: Some(self.team.clone()),
+            search: self.search,
+            ..Default::default()
+        }
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_team_in_codeowners_audit(
+            bootstrap,
+            self.team.clone(),
+            self.repos.clone(),
+            self.search,
+        )
     }
 }
 
@@ -117,78 +209,90 @@ impl Display for CodeownersFileProblem {
 ///
 /// We will also alert if a team is empty.
 pub fn run_codeowners_audit(
-    bootstrap: Bootstrap,
+    bootstrap: &Bootstrap,
     repos: Option<Vec<String>>,
     search: bool,
     also_gh_api: bool,
     verbose: bool,
-) {
+) -> Result<Findings, Error> {
     // Immediately stop if we received incompatible options
     if search && repos.is_some() {
-        panic!("{}", "Using --search assumes an org-wide search, and it is not supported in conjunction with a list of repos (i.e., --repos).".red());
+        return Err(Error::Config("Using --search assumes an org-wide search, and it is not supported in conjunction with a list of repos (i.e., --repos).".to_string()));
     }
 
-    println!("{}", "I am fetching CODEOWNERS files...".yellow());
+    info!("{}", "I am fetching CODEOWNERS files...".yellow());
 
     // Build a list of CO files we will audit
+    let mut skipped = Skipped::default();
     let codeowners_files = match search {
-        true => search::find_codeowners_in_org(&bootstrap),
-        false => iterate::find_codeowners_in_org(&bootstrap, repos.clone()),
+        true => search::find_codeowners_in_org(bootstrap, &mut skipped),
+        false => iterate::find_codeowners_in_org(bootstrap, repos.clone(), &mut skipped),
     }
-    .expect(&format!(
-        "{}",
-        "Error while fetching CODEOWNERS file: I cannot continue".red()
-    ));
+    .inspect_err(|_| {
+        error!(
+            "{}",
+            "Error while fetching CODEOWNERS file: I cannot continue".red()
+        )
+    })?;
 
-    println!(
+    info!(
         "{} {} {}",
         "Done! I found".green(),
         codeowners_files.len().to_string().white(),
         "CODEOWNERS files".green()
     );
 
-    println!(
+    info!(
         "{}",
         "Preparing to analyze these CODEOWNERS files...".yellow()
     );
 
     // Get all members and teams in the org, so that we can efficiently
     // cross-check the content of all the CODEOWNERS files we have found.
-    let org_members = get_indexed_org_members(&bootstrap);
-    let org_teams = get_indexed_org_teams(&bootstrap);
+    let org_members = get_indexed_org_members(bootstrap)?;
+    let org_teams = get_indexed_org_teams(bootstrap)?;
 
-    audit::audit_co_files(
-        &bootstrap,
+    let findings = audit::audit_co_files(
+        bootstrap,
         &codeowners_files,
         &org_members,
         &org_teams,
         also_gh_api,
         verbose,
     );
+
+    skipped.report();
+    findings.report();
+    Ok(findings.with_skipped(skipped))
 }
 
 /// Look for all occurrences of that team in CODEOWNERS files across the org.
 /// This is useful to estimate the impact on CODEOWNERS that removing or renaming a team would have.
 pub fn run_team_in_codeowners_audit(
-    bootstrap: Bootstrap,
+    bootstrap: &Bootstrap,
     team: String,
     repos: Option<Vec<String>>,
     search: bool,
-) {
+) -> Result<Findings, Error> {
     // Immediately stop if we received incompatible options
     if search && repos.is_some() {
-        panic!("{}", "Using --search assumes an org-wide search, and it is not supported in conjunction with a list of repos (i.e., --repos).".red());
+        return Err(Error::Config("Using --search assumes an org-wide search, and it is not supported in conjunction with a list of repos (i.e., --repos).".to_string()));
     }
 
-    println!(
+    info!(
         "{} {} {}",
         "Searching for occurrences of team".yellow(),
         team.white(),
         "in CODEOWNERS files...".yellow()
     );
+    let mut findings = Findings::default();
     if search {
-        search::find_team_in_codeowners(&bootstrap, team)
+        search::find_team_in_codeowners(bootstrap, team, &mut findings)?;
+        Ok(findings)
     } else {
-        iterate::find_team_in_codeowners(&bootstrap, team, repos)
+        let mut skipped = Skipped::default();
+        iterate::find_team_in_codeowners(bootstrap, team, repos, &mut skipped, &mut findings)?;
+        skipped.report();
+        Ok(findings.with_skipped(skipped))
     }
 }
@@ -1,117 +1,111 @@
 use colored::Colorize;
 
-use crate::{make_github_request, Bootstrap};
+use crate::{
+    finding::{self, Target},
+    make_paginated_github_request_from_field, output, Bootstrap, Error, Finding, Findings, Skipped,
+};
 
 use super::CodeownersFile;
 
+/// A file matched by the code search API
+#[derive(Debug, serde::Deserialize)]
+struct SearchCodeItem {
+    name: String,
+    /// From the root of the repository
+    path: String,
+    /// API URL of the file, which we can use to fetch its content
+    url: String,
+    html_url: String,
+    repository: SearchCodeRepository,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SearchCodeRepository {
+    name: String,
+}
+
+/// Search for files named CODEOWNERS matching the given query, across all pages of results
+fn search_codeowners(bootstrap: &Bootstrap, query: &str) -> Result<Vec<SearchCodeItem>, Error> {
+    let query = urlencoding::encode(query).to_string();
+
+    // !!! NOTE - This endpoint has a custom rate limitation !!!
+    // https://docs.github.com/en/rest/search/search?apiVersion=2022-11-28#rate-limit
+    let items: Vec<SearchCodeItem> = make_paginated_github_request_from_field(
+        bootstrap,
+        100,
+        "/search/code",
+        3,
+        Some(&format!("q={query}")),
+        "items",
+    )?;
+
+    // Filter out all files that are not called exactly CODEOWNERS
+    Ok(items
+        .into_iter()
+        .filter(|item| item.name == "CODEOWNERS")
+        .collect())
+}
+
 /// Find all the codeowners files in an organization
-pub fn find_codeowners_in_org(bootstrap: &Bootstrap) -> Result<Vec<CodeownersFile>, String> {
+pub fn find_codeowners_in_org(
+    bootstrap: &Bootstrap,
+    skipped: &mut Skipped,
+) -> Result<Vec<CodeownersFile>, Error> {
     let query = format!("org:{} filename:CODEOWNERS", bootstrap.org);
-    let query = urlencoding::encode(&query).to_string();
 
-    let mut page = 1;
-    let mut all_results = vec![];
+    let items = search_codeowners(bootstrap, &query)?;
+    let items = bootstrap.filter_repositories(items, |item| &item.repository.name);
 
-    loop {
-        // !!! NOTE - This endpoint has a custom rate limitation !!!
-        // https://docs.github.com/en/rest/search/search?apiVersion=2022-11-28#rate-limit
-        let address = format!("/search/code?q={query}&per_page=100&page={page}");
-        let res = make_github_request(&bootstrap.token, &address, 3, None)?;
-        let items = res
-            .get("items")
-            .and_then(|i| i.as_array())
-            .and_then(|a| Some(a.clone()))
-            .unwrap_or_default();
-        if items.is_empty() {
-            // We are past the last page
-            break;
-        }
-        for item in items {
-            // Filter out all files that are not called exactly CODEOWNERS
-            let name = item
-                .get("name")
-                .and_then(|n| n.as_str())
-                .unwrap_or("Not available");
-            if name != "CODEOWNERS" {
-                continue;
-            }
-
-            let repo = item
-                .get("repository")
-                .and_then(|r| r.get("name"))
-                .and_then(|n| n.as_str())
-                .unwrap_or("Not available");
-            let html_url = item
-                .get("html_url")
-                .and_then(|u| u.as_str())
-                .unwrap_or("Not available");
-            let api_url = item
-                .get("url")
-                .and_then(|u| u.as_str())
-                .unwrap_or("Not available");
-            if let Ok(content) = crate::utils::fetch_file_content(&bootstrap, api_url) {
-                all_results.push(CodeownersFile::parse_from_content(
-                    bootstrap, content, html_url, repo,
-                ));
-            }
+    let mut all_results = vec![];
+    for item in items {
+        match crate::utils::fetch_file_content(bootstrap, &item.url) {
+            Ok(content) => all_results.push(CodeownersFile::parse_from_content(
+                bootstrap,
+                content,
+                &item.html_url,
+                &item.repository.name,
+                &item.path,
+            )),
+            Err(e) => skipped.record(item.repository.name, e),
         }
-        page += 1;
     }
 
     Ok(all_results)
 }
 
 /// Find all occurrences of a given team in an organization's codeowners files
-pub fn find_team_in_codeowners(bootstrap: &Bootstrap, team: String) {
+pub fn find_team_in_codeowners(
+    bootstrap: &Bootstrap,
+    team: String,
+    findings: &mut Findings,
+) -> Result<(), Error> {
     let query = format!(
         "org:{} filename:CODEOWNERS @{}/{}",
         bootstrap.org, bootstrap.org, team
     );
-    let query = urlencoding::encode(&query).to_string();
-
-    let mut page = 1;
 
-    loop {
-        // !!! NOTE - This endpoint has a custom rate limitation !!!
-        // https://docs.github.com/en/rest/search/search?apiVersion=2022-11-28#rate-limit
-        let address = format!("/search/code?q={query}&per_page=100&page={page}");
-        let res = make_github_request(&bootstrap.token, &address, 3, None).unwrap();
-        let items = res
-            .get("items")
-            .and_then(|i| i.as_array())
-            .and_then(|a| Some(a.clone()))
-            .unwrap_or_default();
-        if items.is_empty() {
-            break;
-        }
-        for item in items {
-            // Filter out all files that are not called exactly CODEOWNERS
-            let name = item
-                .get("name")
-                .and_then(|n| n.as_str())
-                .unwrap_or("Not available");
-            if name != "CODEOWNERS" {
-                continue;
-            }
-
-            let repo = item
-                .get("repository")
-                .and_then(|r| r.get("name"))
-                .and_then(|n| n.as_str())
-                .unwrap_or("Not available");
-            let html_url = item
-                .get("html_url")
-                .and_then(|u| u.as_str())
-                .unwrap_or("Not available");
-
-            println!(
-                "{} {:<50} - {} {}",
-                "Repository:".yellow(),
-                repo.white(),
-                "URL:".yellow(),
-                html_url.white()
+    for item in search_codeowners(bootstrap, &query)? {
+        output::line(format!(
+            "{} {:<50} - {} {}",
+            "Repository:".yellow(),
+            item.repository.name.white(),
+            "URL:".yellow(),
+            item.html_url.white()
+        ));
+        findings.record_quietly(
+            Finding::new(
+                &finding::CODEOWNERS_TEAM_MENTION,
+                // The search doesn't tell us on which line
+                Target::File {
+                    repo: item.repository.name.clone(),
+                    path: item.path.clone(),
+                    url: item.html_url.clone(),
+                    line: None,
+                },
+                format!("Team {team} is mentioned in {}", item.html_url),
             )
-        }
-        page += 1;
+            .with("team", &team),
+        );
     }
+    Ok(())
 }
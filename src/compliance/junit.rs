@@ -0,0 +1,290 @@
+//! JUnit XML for the compliance audit, so that CI systems show the checks of each repository as tests:
+//! each repository is a test suite, and each check a test case that passes, fails or is skipped
+
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    compliance::{check_csv_value_named, RepoReport},
+    output::escape,
+    Error,
+};
+
+/// What we could read from one of the sources of the checks
+#[derive(Debug, Default)]
+pub(super) enum Source {
+    /// The selected checks don't need it
+    #[default]
+    Unused,
+    /// We read it, e.g., "the branch protection rule" or "3 rules from rulesets"
+    Read(String),
+    /// It doesn't exist, e.g., the branch is not protected
+    Missing,
+    /// We could not read it
+    Failed(Error),
+}
+
+impl Source {
+    fn describe(&self, what: &str) -> String {
+        match self {
+            Source::Unused => format!("{what} not inspected"),
+            Source::Read(details) => details.clone(),
+            Source::Missing => format!("no {what}"),
+            Source::Failed(Error::Forbidden { .. }) => format!("{what} not readable (403)"),
+            Source::Failed(e) => format!("{what} not readable ({e})"),
+        }
+    }
+}
+
+/// Where the results of the checks of a repository come from, to explain them
+#[derive(Debug, Default)]
+pub(super) struct Sources {
+    /// The branch protection rule of the default branch
+    pub bpr: Source,
+    /// The rules that rulesets apply to the default branch
+    pub rulesets: Source,
+    /// The errors GitHub reports in the CODEOWNERS file
+    pub codeowners: Source,
+}
+
+impl Sources {
+    /// What we looked at to evaluate the check `name` on `branch`
+    fn inspected(&self, name: &str, branch: &str) -> String {
+        match name {
+            "codeowners_valid" => {
+                format!("Inspected: {}", self.codeowners.describe("CODEOWNERS file"))
+            }
+            _ => format!(
+                "Inspected on {branch}: {}, and {}",
+                self.bpr.describe("branch protection rule"),
+                self.rulesets.describe("ruleset")
+            ),
+        }
+    }
+}
+
+enum Outcome {
+    Passed,
+    Failed {
+        rule_id: &'static str,
+        message: String,
+        remediation: &'static str,
+    },
+    Skipped(String),
+    Error(String),
+}
+
+struct TestCase {
+    name: &'static str,
+    outcome: Outcome,
+}
+
+pub(super) struct TestSuite {
+    repo: String,
+    /// Shown as `<properties>`, e.g., the default branch and the score
+    properties: Vec<(&'static str, String)>,
+    cases: Vec<TestCase>,
+}
+
+impl TestSuite {
+    fn count(&self, matches: impl Fn(&Outcome) -> bool) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| matches(&case.outcome))
+            .count()
+    }
+}
+
+/// The test suite of a repository whose checks ran. Checks that were not selected, or that GitHub
+/// didn't let us evaluate (403), are skipped
+pub(super) fn suite(
+    repo: &str,
+    report: &RepoReport,
+    selected: Option<&HashSet<String>>,
+    (score, max): (u32, u32),
+) -> TestSuite {
+    let cases = report
+        .checks
+        .with_rules()
+        .into_iter()
+        .map(|(name, check, rule)| {
+            let outcome = match check_csv_value_named(check, name, selected).as_str() {
+                "pass" => Outcome::Passed,
+                "fail" => Outcome::Failed {
+                    rule_id: rule.id,
+                    message: format!(
+                        "{}. {}",
+                        rule.description,
+                        report.sources.inspected(name, &report.branch)
+                    ),
+                    remediation: rule.remediation,
+                },
+                "n/a" => Outcome::Skipped("Not selected with --checks".to_string()),
+                _ => Outcome::Skipped(format!(
+                    "GitHub didn't let us evaluate this check. {}",
+                    report.sources.inspected(name, &report.branch)
+                )),
+            };
+            TestCase { name, outcome }
+        })
+        .collect();
+    TestSuite {
+        repo: repo.to_string(),
+        properties: vec![
+            ("default_branch", report.branch.clone()),
+            ("visibility", report.visibility.clone()),
+            ("score", format!("{score}/{max}")),
+        ],
+        cases,
+    }
+}
+
+/// The test suite of a repository we could not check at all
+pub(super) fn error_suite(repo: &str, error: &Error) -> TestSuite {
+    TestSuite {
+        repo: repo.to_string(),
+        properties: vec![],
+        cases: vec![TestCase {
+            name: "repository",
+            outcome: Outcome::Error(error.to_string()),
+        }],
+    }
+}
+
+fn render(suites: &[TestSuite]) -> Result<String, std::fmt::Error> {
+    let total = |matches: fn(&Outcome) -> bool| -> usize {
+        suites.iter().map(|suite| suite.count(matches)).sum()
+    };
+    let failed = |o: &Outcome| matches!(o, Outcome::Failed { .. });
+    let errored = |o: &Outcome| matches!(o, Outcome::Error(_));
+    let skipped = |o: &Outcome| matches!(o, Outcome::Skipped(_));
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    writeln!(
+        xml,
+        r#"<testsuites name="{} compliance" tests="{}" failures="{}" errors="{}" skipped="{}">"#,
+        env!("CARGO_PKG_NAME"),
+        total(|_| true),
+        total(failed),
+        total(errored),
+        total(skipped),
+    )?;
+    for suite in suites {
+        let repo = escape(&suite.repo);
+        writeln!(
+            xml,
+            r#"  <testsuite name="{repo}" tests="{}" failures="{}" errors="{}" skipped="{}">"#,
+            suite.cases.len(),
+            suite.count(failed),
+            suite.count(errored),
+            suite.count(skipped),
+        )?;
+        if !suite.properties.is_empty() {
+            writeln!(xml, "    <properties>")?;
+            for (name, value) in &suite.properties {
+                writeln!(
+                    xml,
+                    r#"      <property name="{name}" value="{}"/>"#,
+                    escape(value)
+                )?;
+            }
+            writeln!(xml, "    </properties>")?;
+        }
+        for case in &suite.cases {
+            let start = format!(
+                r#"    <testcase name="{}" classname="compliance.{repo}""#,
+                case.name
+            );
+            match &case.outcome {
+                Outcome::Passed => writeln!(xml, "{start}/>")?,
+                Outcome::Failed {
+                    rule_id,
+                    message,
+                    remediation,
+                } => writeln!(
+                    xml,
+                    r#"{start}><failure type="{rule_id}" message="{}">{}</failure></testcase>"#,
+                    escape(message),
+                    escape(remediation)
+                )?,
+                Outcome::Skipped(message) => writeln!(
+                    xml,
+                    r#"{start}><skipped message="{}"/></testcase>"#,
+                    escape(message)
+                )?,
+                Outcome::Error(message) => writeln!(
+                    xml,
+                    r#"{start}><error message="{}"/></testcase>"#,
+                    escape(message)
+                )?,
+            }
+        }
+        writeln!(xml, "  </testsuite>")?;
+    }
+    writeln!(xml, "</testsuites>")?;
+    Ok(xml)
+}
+
+/// Write the test suites to `path`, replacing the file if it exists
+pub(super) fn write(path: &str, suites: &[TestSuite]) -> Result<(), Error> {
+    let xml = render(suites)
+        .map_err(|e| Error::Config(format!("Could not generate the JUnit report: {e}")))?;
+    std::fs::write(path, xml)
+        .map_err(|e| Error::Config(format!("Could not write the JUnit report to {path}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_escapes_names_and_messages() {
+        let suites = [
+            TestSuite {
+                repo: r#"a<b>&"c'"#.to_string(),
+                properties: vec![
+                    ("default_branch", "main & <dev>".to_string()),
+                    ("score", "3/5".to_string()),
+                ],
+                cases: vec![
+                    TestCase {
+                        name: "pr_one_approval",
+                        outcome: Outcome::Passed,
+                    },
+                    TestCase {
+                        name: "pr_dismiss_stale",
+                        outcome: Outcome::Failed {
+                            rule_id: "CMP002",
+                            message: r#"Stale "approvals" <are> kept & counted"#.to_string(),
+                            remediation: "Dismiss them & try again",
+                        },
+                    },
+                    TestCase {
+                        name: "disable_deletion",
+                        outcome: Outcome::Skipped("Not selected with --checks".to_string()),
+                    },
+                ],
+            },
+            error_suite("svc", &Error::Config("x < y & z".to_string())),
+        ];
+
+        assert_eq!(
+            render(&suites).unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="gh-ec-audit compliance" tests="4" failures="1" errors="1" skipped="1">
+  <testsuite name="a&lt;b&gt;&amp;&quot;c&#39;" tests="3" failures="1" errors="0" skipped="1">
+    <properties>
+      <property name="default_branch" value="main &amp; &lt;dev&gt;"/>
+      <property name="score" value="3/5"/>
+    </properties>
+    <testcase name="pr_one_approval" classname="compliance.a&lt;b&gt;&amp;&quot;c&#39;"/>
+    <testcase name="pr_dismiss_stale" classname="compliance.a&lt;b&gt;&amp;&quot;c&#39;"><failure type="CMP002" message="Stale &quot;approvals&quot; &lt;are&gt; kept &amp; counted">Dismiss them &amp; try again</failure></testcase>
+    <testcase name="disable_deletion" classname="compliance.a&lt;b&gt;&amp;&quot;c&#39;"><skipped message="Not selected with --checks"/></testcase>
+  </testsuite>
+  <testsuite name="svc" tests="1" failures="0" errors="1" skipped="0">
+    <testcase name="repository" classname="compliance.svc"><error message="x &lt; y &amp; z"/></testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+    }
+}
@@ -1,26 +1,41 @@
+mod junit;
 mod repo;
 mod rules;
 mod utils;
 
 use colored::Colorize;
-use indicatif::{ProgressBar, ProgressStyle};
+use log::error;
 use serde::Deserialize;
 use std::collections::HashSet;
 use std::fs::OpenOptions;
 use std::io::BufWriter;
 use std::path::Path;
 
-use crate::compliance::utils::{check_csv_value_named, check_symbol};
-use crate::Bootstrap;
+use crate::audit::Audit;
+use crate::compliance::junit::{Source, Sources};
+use crate::compliance::utils::check_csv_value_named;
+pub(crate) use crate::compliance::utils::{check_from_csv_value, check_symbol};
+use crate::config::Config;
+use crate::finding::{self, Rule, Target};
+use crate::graphql::{self, RepoSnapshot};
+use crate::pool::{for_each_ordered, for_each_ordered_batched, progress_bar};
+use crate::preflight::{AuditKind, PreflightOptions};
+use crate::{output, Backend, Bootstrap, Error, Finding, Findings, Skipped};
 
 /// `Some(true)` means passing, `Some(false)` means failing, `None` means undetermined
 type Check = Option<bool>;
 
-#[derive(PartialEq)]
-enum Errors {
-    NoAccess403,
-    MissingOrError,
-}
+/// The checks that can be selected with `--checks`, in report order
+pub const CHECK_NAMES: [&str; 8] = [
+    "pr_one_approval",
+    "pr_dismiss_stale",
+    "pr_require_code_owner",
+    "disable_force_push",
+    "disable_deletion",
+    "require_signed_commits",
+    "require_status_checks",
+    "codeowners_valid",
+];
 
 #[derive(Debug, Clone)]
 struct ProtectionChecks {
@@ -58,9 +73,59 @@ impl Default for ProtectionChecks {
     }
 }
 
-/// Weights for the different checks (in case some are more important than others)
-#[derive(Clone)]
-struct Weights {
+impl ProtectionChecks {
+    /// Each check with its name and the rule it breaks when failing, in report order
+    fn with_rules(&self) -> [(&'static str, Check, &'static Rule); 8] {
+        [
+            (
+                "pr_one_approval",
+                self.pr_one_approval,
+                &finding::COMPLIANCE_PR_ONE_APPROVAL,
+            ),
+            (
+                "pr_dismiss_stale",
+                self.pr_dismiss_stale,
+                &finding::COMPLIANCE_PR_DISMISS_STALE,
+            ),
+            (
+                "pr_require_code_owner",
+                self.pr_require_code_owner,
+                &finding::COMPLIANCE_PR_REQUIRE_CODE_OWNER,
+            ),
+            (
+                "disable_force_push",
+                self.disable_force_push,
+                &finding::COMPLIANCE_DISABLE_FORCE_PUSH,
+            ),
+            (
+                "disable_deletion",
+                self.disable_deletion,
+                &finding::COMPLIANCE_DISABLE_DELETION,
+            ),
+            (
+                "require_signed_commits",
+                self.require_signed_commits,
+                &finding::COMPLIANCE_REQUIRE_SIGNED_COMMITS,
+            ),
+            (
+                "require_status_checks",
+                self.require_status_checks,
+                &finding::COMPLIANCE_REQUIRE_STATUS_CHECKS,
+            ),
+            (
+                "codeowners_valid",
+                self.codeowners_valid,
+                &finding::COMPLIANCE_CODEOWNERS_VALID,
+            ),
+        ]
+    }
+}
+
+/// Weights for the different checks (in case some are more important than others).
+/// They can be set in the `[compliance.weights]` section of the configuration file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
     pr_one_approval: u32,
     pr_dismiss_stale: u32,
     pr_require_code_owner: u32,
@@ -138,13 +203,75 @@ enum CodeownersStatus {
     Missing,
 }
 
+/// How well the default branch of each repository is protected
+#[derive(Clone, Default)]
+pub struct ComplianceAudit {
+    /// Only these repositories. If `None`, all of them
+    pub repos: Option<Vec<String>>,
+    /// Export the results to this CSV file instead of printing them
+    pub csv: Option<String>,
+    /// Also write the result of every check to this JUnit XML file
+    pub junit: Option<String>,
+    /// Repositories with a lower score get a `CMP010` finding
+    pub min_score: Option<u32>,
+    /// Only non-archived, non-disabled repositories
+    pub active_repo_only: bool,
+    /// The checks to run. If `None`, all of them
+    pub checks: Option<Vec<String>>,
+    pub weights: Weights,
+}
+
+impl Audit for ComplianceAudit {
+    fn name(&self) -> &'static str {
+        "compliance"
+    }
+
+    fn description(&self) -> &'static str {
+        "Score how well the default branch of each repository is protected"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["administration: read", "contents: read", "metadata: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::Compliance
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            repos: self.repos.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn apply_config(&mut self, config: &mut Config) {
+        self.csv = self.csv.take().or(config.compliance.csv.take());
+        self.junit = self.junit.take().or(config.compliance.junit.take());
+        self.min_score = self.min_score.or(config.compliance.min_score);
+        self.checks = self.checks.take().or(config.compliance.checks.take());
+        self.active_repo_only |= config.compliance.active_repo_only;
+        self.weights = std::mem::take(&mut config.compliance.weights);
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_compliance_audit(bootstrap, self)
+    }
+}
+
 pub fn run_compliance_audit(
-    bootstrap: Bootstrap,
-    repos: Option<Vec<String>>,
-    csv_path: Option<String>,
-    active_repo_only: bool,
-    selected_checks: Option<Vec<String>>,
-) {
+    bootstrap: &Bootstrap,
+    audit: &ComplianceAudit,
+) -> Result<Findings, Error> {
+    let ComplianceAudit {
+        repos,
+        csv: csv_path,
+        junit: junit_path,
+        active_repo_only,
+        checks: selected_checks,
+        weights,
+        min_score,
+    } = audit.clone();
     // When performing a compliance audit, we can choose to report only on some checks, like
     // whether signed commits are required or whether PRs require at least 1 review, etc.
     // Here, we select checks we are interested in: if we were passed some `selected_checks`, then we
@@ -154,27 +281,20 @@ pub fn run_compliance_audit(
             .map(|s| s.to_lowercase())
             .collect::<HashSet<String>>()
     });
-    // Simple function we will use later to determine if a check has been selected.
-    // If the user passed no selection, meaning `selected_set` is `None`, then
-    // all checks are selected by default.
-    let is_selected = |name: &str| -> bool {
-        match &selected_set {
-            None => true,
-            Some(s) => s.contains(&name.to_lowercase()),
+    // All the repositories we will check
+    let repos = match repos {
+        Some(repos) => repos,
+        None => {
+            let mut list = bootstrap
+                .fetch_all_repositories(75, active_repo_only)?
+                .into_iter()
+                .filter(|r| !active_repo_only || (!r.archived && !r.disabled))
+                .map(|r| r.name)
+                .collect::<Vec<String>>();
+            list.sort();
+            list
         }
     };
-    // All the repositories we will check
-    let repos = repos.unwrap_or_else(|| {
-        let mut list = bootstrap
-            .fetch_all_repositories(75, active_repo_only)
-            .unwrap()
-            .into_iter()
-            .filter(|r| !active_repo_only || (!r.archived && !r.disabled))
-            .map(|r| r.name)
-            .collect::<Vec<String>>();
-        list.sort();
-        list
-    });
 
     // Prepare CSV writer if requested; support appending and skipping already-processed repos
     let mut already_processed: HashSet<String> = HashSet::new();
@@ -191,7 +311,7 @@ pub fn run_compliance_audit(
                 .create(true)
                 .append(true)
                 .open(path)
-                .expect("Unable to open CSV file for append");
+                .map_err(|e| csv_error(path, &e))?;
             let buf = BufWriter::new(file);
             let mut wtr = csv::WriterBuilder::new()
                 .has_headers(false)
@@ -211,8 +331,8 @@ pub fn run_compliance_audit(
                     "codeowners_valid",
                     "codeowners_path",
                 ])
-                .expect("Unable to write CSV header");
-                wtr.flush().ok();
+                .map_err(|e| csv_error(path, &e))?;
+                wtr.flush().map_err(|e| csv_error(path, &e))?;
             }
             Some(wtr)
         }
@@ -229,299 +349,480 @@ pub fn run_compliance_audit(
         repos
     };
 
-    // Progress bar
-    let pb = ProgressBar::new(repos.len() as u64);
-    pb.set_style(
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}",
-        )
-        .unwrap()
-        .progress_chars("=>-"),
-    );
-
-    for repo in repos {
+    let export_csv = csv_writer.is_some();
+    let pb = progress_bar(repos.len());
+    let mut skipped = Skipped::default();
+    let mut findings = Findings::default();
+    let mut test_suites = vec![];
+    let mut csv_result = Ok(());
+    let sink = |repo: String, report: Result<RepoReport, Error>| {
         pb.set_message(repo.clone());
+        let report = match report {
+            Ok(report) => report,
+            Err(e) => {
+                if junit_path.is_some() {
+                    test_suites.push(junit::error_suite(&repo, &e));
+                }
+                pb.suspend(|| skipped.record(repo, e));
+                return;
+            }
+        };
 
-        let (default_branch, visibility_opt) = match repo::get_default_branch(&bootstrap, &repo, 3)
-        {
-            Ok(info) => (info.branch, info.visibility),
-            Err(Errors::NoAccess403) => {
-                println!(
-                    "{} {}: {}",
-                    "Skipping repo".yellow(),
-                    repo.white(),
-                    "default branch not accessible (403)".red()
-                );
-                pb.inc(1);
-                continue;
+        // The report shows the result of every check, so the findings are not printed again
+        let (score, max) = compute_selected_score(&report.checks, selected_set.as_ref(), &weights);
+        if junit_path.is_some() {
+            test_suites.push(junit::suite(
+                &repo,
+                &report,
+                selected_set.as_ref(),
+                (score, max),
+            ));
+        }
+        let mut summary = Finding::new(
+            &finding::COMPLIANCE_SCORE,
+            Target::Repository { repo: repo.clone() },
+            format!("{repo} has a compliance score of {score}/{max}"),
+        )
+        .with("branch", &report.branch)
+        .with("visibility", &report.visibility)
+        .with("score", score)
+        .with("max_score", max);
+        for (name, check, _) in report.checks.with_rules() {
+            if selected_set.as_ref().is_none_or(|s| s.contains(name)) {
+                summary = summary.with(name, utils::check_csv_value(check));
             }
-            Err(Errors::MissingOrError) => {
-                println!(
-                    "{} {}: {}",
-                    "Skipping repo".yellow(),
-                    repo.white(),
-                    "could not determine default branch".red()
+        }
+        findings.record_quietly(summary);
+        if let Some(min_score) = min_score.filter(|min_score| score < *min_score) {
+            findings.record_quietly(
+                Finding::new(
+                    &finding::COMPLIANCE_SCORE_BELOW_MINIMUM,
+                    Target::Repository { repo: repo.clone() },
+                    format!("{repo} has a compliance score of {score}/{max}, below the minimum of {min_score}"),
+                )
+                .with("score", score)
+                .with("max_score", max)
+                .with("min_score", min_score),
+            );
+        }
+        for (name, check, rule) in report.checks.with_rules() {
+            let is_selected = selected_set.as_ref().is_none_or(|s| s.contains(name));
+            if is_selected && check == Some(false) {
+                findings.record_quietly(
+                    Finding::new(
+                        rule,
+                        Target::Repository { repo: repo.clone() },
+                        format!("{repo}: {}", rule.description),
+                    )
+                    .with("branch", &report.branch),
                 );
-                pb.inc(1);
-                continue;
             }
+        }
+
+        // If CSV export is enabled, write a row; otherwise, print report.
+        // After a failed write, we stop writing: the rows so far let the next run resume from there
+        if let Some(wtr) = csv_writer.as_mut() {
+            if csv_result.is_err() {
+                return;
+            }
+            csv_result = write_csv_row(wtr, &repo, &report, selected_set.as_ref()).map_err(|e| {
+                let e = csv_error(csv_path.as_deref().unwrap_or_default(), &e);
+                pb.suspend(|| error!("{}", e.to_string().red()));
+                e
+            });
+        } else {
+            pb.suspend(|| {
+                print_report(
+                    &repo,
+                    &report.branch,
+                    &report.visibility,
+                    report.checks,
+                    selected_set.as_ref(),
+                    &weights,
+                )
+            });
+        }
+    };
+
+    match bootstrap.backend {
+        Backend::Rest => for_each_ordered(
+            repos,
+            bootstrap.concurrency,
+            &pb,
+            |repo| check_repo(bootstrap, repo, selected_set.as_ref(), export_csv, None),
+            sink,
+        ),
+        Backend::GraphQl => {
+            let fields = graphql::RepoFields {
+                protection: true,
+                codeowners: selected_set
+                    .as_ref()
+                    .is_none_or(|s| s.contains("codeowners_valid")),
+                ..Default::default()
+            };
+            for_each_ordered_batched(
+                repos,
+                graphql::BATCH_SIZE,
+                bootstrap.concurrency,
+                &pb,
+                |repos| {
+                    graphql::fetch_repositories(bootstrap, repos, fields)
+                        .into_iter()
+                        .zip(repos)
+                        .map(|(snapshot, repo)| {
+                            snapshot.and_then(|snapshot| {
+                                check_repo(
+                                    bootstrap,
+                                    repo,
+                                    selected_set.as_ref(),
+                                    export_csv,
+                                    Some(snapshot),
+                                )
+                            })
+                        })
+                        .collect()
+                },
+                sink,
+            )
+        }
+    }
+
+    // Flush CSV if used
+    if let Some(mut wtr) = csv_writer {
+        if csv_result.is_ok() {
+            csv_result = wtr
+                .flush()
+                .map_err(|e| csv_error(csv_path.as_deref().unwrap_or_default(), &e));
+        }
+    }
+    pb.finish_with_message("done");
+    csv_result?;
+
+    if let Some(path) = &junit_path {
+        junit::write(path, &test_suites)?;
+    }
+    skipped.report();
+    findings.report();
+    Ok(findings.with_skipped(skipped))
+}
+
+/// Write the row of a repository to the compliance CSV
+fn write_csv_row(
+    wtr: &mut csv::Writer<BufWriter<std::fs::File>>,
+    repo: &str,
+    report: &RepoReport,
+    selected: Option<&HashSet<String>>,
+) -> Result<(), csv::Error> {
+    wtr.write_record([
+        repo,
+        report.branch.as_str(),
+        report.visibility.as_str(),
+        check_csv_value_named(report.checks.pr_one_approval, "pr_one_approval", selected).as_str(),
+        check_csv_value_named(report.checks.pr_dismiss_stale, "pr_dismiss_stale", selected)
+            .as_str(),
+        check_csv_value_named(
+            report.checks.pr_require_code_owner,
+            "pr_require_code_owner",
+            selected,
+        )
+        .as_str(),
+        check_csv_value_named(
+            report.checks.disable_force_push,
+            "disable_force_push",
+            selected,
+        )
+        .as_str(),
+        check_csv_value_named(report.checks.disable_deletion, "disable_deletion", selected)
+            .as_str(),
+        check_csv_value_named(
+            report.checks.require_signed_commits,
+            "require_signed_commits",
+            selected,
+        )
+        .as_str(),
+        check_csv_value_named(
+            report.checks.require_status_checks,
+            "require_status_checks",
+            selected,
+        )
+        .as_str(),
+        check_csv_value_named(report.checks.codeowners_valid, "codeowners_valid", selected)
+            .as_str(),
+        report.codeowners_path.as_str(),
+    ])?;
+    // Flush after every write to ensure durability on long runs
+    wtr.flush()?;
+    Ok(())
+}
+
+/// The error for a compliance CSV that we can't open or write to
+fn csv_error(path: &str, e: &dyn std::fmt::Display) -> Error {
+    Error::Config(format!("Could not write the compliance CSV {path}: {e}"))
+}
+
+/// Everything we found out about a single repository
+struct RepoReport {
+    branch: String,
+    visibility: String,
+    checks: ProtectionChecks,
+    /// Only looked up when exporting to CSV
+    codeowners_path: String,
+    /// What we looked at to evaluate the checks
+    sources: Sources,
+}
+
+/// Run the selected checks against the default branch of a repository.
+/// This only talks to GitHub, so it can run on any worker thread.
+/// If we already fetched a `snapshot` of the repository with GraphQL, we only use the REST API
+/// for what GraphQL doesn't expose (i.e., rulesets and CODEOWNERS errors).
+fn check_repo(
+    bootstrap: &Bootstrap,
+    repo: &str,
+    selected: Option<&HashSet<String>>,
+    with_codeowners_path: bool,
+    snapshot: Option<RepoSnapshot>,
+) -> Result<RepoReport, Error> {
+    // Simple function we will use later to determine if a check has been selected.
+    // If the user passed no selection, meaning `selected` is `None`, then
+    // all checks are selected by default.
+    let is_selected = |name: &str| -> bool {
+        match selected {
+            None => true,
+            Some(s) => s.contains(&name.to_lowercase()),
+        }
+    };
+
+    let (info, protection, snapshot_codeowners_path) = match snapshot {
+        Some(snapshot) => (
+            RepoInfo {
+                branch: snapshot.default_branch.ok_or(Error::Decode(format!(
+                    "The repo {repo} has no default branch"
+                )))?,
+                visibility: snapshot.visibility,
+            },
+            Some(snapshot.protection),
+            Some(snapshot.codeowners_path),
+        ),
+        None => (repo::get_default_branch(bootstrap, repo, 3)?, None, None),
+    };
+    let default_branch = info.branch;
+
+    // Initialize default (all failing) checks, which we will update as we scan through BPRs and rulesets
+    let mut checks = ProtectionChecks::default();
+    let mut sources = Sources::default();
+
+    // Determine which sources are needed based on selected checks: do we need to check BPRs?
+    let need_bpr = is_selected("disable_force_push")
+        || is_selected("disable_deletion")
+        || is_selected("require_signed_commits")
+        || is_selected("pr_one_approval")
+        || is_selected("pr_dismiss_stale")
+        || is_selected("pr_require_code_owner")
+        || is_selected("require_status_checks");
+
+    if need_bpr {
+        // 1) Classic BPR with retries on transient errors
+        let bpr_fetch = match protection {
+            // Like the REST API, we consider an unprotected branch as not found
+            Some(protection) => protection.and_then(|rule| {
+                rule.map(BprResponse::from).ok_or(Error::NotFound {
+                    url: format!("/graphql ({repo})"),
+                })
+            }),
+            None => rules::get_bpr(bootstrap, repo, &default_branch, 3),
         };
 
-        // Initialize default (all failing) checks, which we will update as we scan through BPRs and rulesets
-        let mut checks = ProtectionChecks::default();
-
-        // Determine which sources are needed based on selected checks: do we need to check BPRs?
-        let need_bpr = is_selected("disable_force_push")
-            || is_selected("disable_deletion")
-            || is_selected("require_signed_commits")
-            || is_selected("pr_one_approval")
-            || is_selected("pr_dismiss_stale")
-            || is_selected("pr_require_code_owner")
-            || is_selected("require_status_checks");
-
-        if need_bpr {
-            // 1) Classic BPR with retries on transient errors
-            let bpr_fetch = rules::get_bpr(&bootstrap, &repo, &default_branch, 3);
-
-            // Handle the OK case. Errors are handled separately
-            if let Ok(ref bpr) = bpr_fetch {
-                if is_selected("disable_force_push")
-                    && !(bpr
-                        .allow_force_pushes
-                        .as_ref()
-                        .map(|f| f.enabled)
-                        .unwrap_or(true))
-                {
-                    checks.disable_force_push = Some(true);
-                }
-                if is_selected("disable_deletion")
-                    && !(bpr
-                        .allow_deletions
-                        .as_ref()
-                        .map(|f| f.enabled)
-                        .unwrap_or(true))
-                {
-                    checks.disable_deletion = Some(true);
+        sources.bpr = match &bpr_fetch {
+            Ok(_) => Source::Read("the branch protection rule".to_string()),
+            Err(Error::NotFound { .. }) => Source::Missing,
+            Err(e) => Source::Failed(e.clone()),
+        };
+
+        // Handle the OK case. Errors are handled separately
+        if let Ok(ref bpr) = bpr_fetch {
+            if is_selected("disable_force_push")
+                && !(bpr
+                    .allow_force_pushes
+                    .as_ref()
+                    .map(|f| f.enabled)
+                    .unwrap_or(true))
+            {
+                checks.disable_force_push = Some(true);
+            }
+            if is_selected("disable_deletion")
+                && !(bpr
+                    .allow_deletions
+                    .as_ref()
+                    .map(|f| f.enabled)
+                    .unwrap_or(true))
+            {
+                checks.disable_deletion = Some(true);
+            }
+            if is_selected("require_signed_commits")
+                && bpr
+                    .required_signatures
+                    .as_ref()
+                    .map(|f| f.enabled)
+                    .unwrap_or(false)
+            {
+                checks.require_signed_commits = Some(true);
+            }
+            if let Some(pr) = &bpr.required_pull_request_reviews {
+                if is_selected("pr_one_approval") && pr.required_approving_review_count > 0 {
+                    checks.pr_one_approval = Some(true)
                 }
-                if is_selected("require_signed_commits")
-                    && bpr
-                        .required_signatures
-                        .as_ref()
-                        .map(|f| f.enabled)
-                        .unwrap_or(false)
-                {
-                    checks.require_signed_commits = Some(true);
+                if is_selected("pr_dismiss_stale") && pr.dismiss_stale_reviews {
+                    checks.pr_dismiss_stale = Some(true);
                 }
-                if let Some(pr) = &bpr.required_pull_request_reviews {
-                    if is_selected("pr_one_approval") && pr.required_approving_review_count > 0 {
-                        checks.pr_one_approval = Some(true)
-                    }
-                    if is_selected("pr_dismiss_stale") && pr.dismiss_stale_reviews {
-                        checks.pr_dismiss_stale = Some(true);
-                    }
-                    if is_selected("pr_require_code_owner") && pr.require_code_owner_reviews {
-                        checks.pr_require_code_owner = Some(true);
-                    }
+                if is_selected("pr_require_code_owner") && pr.require_code_owner_reviews {
+                    checks.pr_require_code_owner = Some(true);
                 }
-                if is_selected("require_status_checks") {
-                    if let Some(rsc) = &bpr.required_status_checks {
-                        if !(rsc.checks.is_empty()) {
-                            checks.require_status_checks = Some(true);
-                        }
+            }
+            if is_selected("require_status_checks") {
+                if let Some(rsc) = &bpr.required_status_checks {
+                    if !(rsc.checks.is_empty()) {
+                        checks.require_status_checks = Some(true);
                     }
                 }
             }
+        }
 
-            // 2) New Rulesets with retries on transient errors
-            let rules_fetch = rules::get_rules(&bootstrap, &repo, &default_branch, 3);
-
-            // Handle the OK case. Errors are handled separately
-            if let Ok(ref rules) = rules_fetch {
-                for rule in rules {
-                    match rule.type_.as_str() {
-                        // The presence of this rule means deletion is disabled
-                        "deletion" => {
-                            if is_selected("disable_deletion") {
-                                checks.disable_deletion = Some(true);
-                            }
-                        }
-                        // The presence of this rule means signed commits are required
-                        "required_signatures" => {
-                            if is_selected("require_signed_commits") {
-                                checks.require_signed_commits = Some(true);
+        // 2) New Rulesets with retries on transient errors
+        let rules_fetch = rules::get_rules(bootstrap, repo, &default_branch, 3);
+        sources.rulesets = match &rules_fetch {
+            Ok(rules) if rules.is_empty() => Source::Missing,
+            Ok(rules) if rules.len() == 1 => Source::Read("1 rule from rulesets".to_string()),
+            Ok(rules) => Source::Read(format!("{} rules from rulesets", rules.len())),
+            Err(e) => Source::Failed(e.clone()),
+        };
+
+        // Handle the OK case. Errors are handled separately
+        if let Ok(ref rules) = rules_fetch {
+            for rule in rules {
+                match rule.type_.as_str() {
+                    // The presence of this rule means deletion is disabled
+                    "deletion" if is_selected("disable_deletion") => {
+                        checks.disable_deletion = Some(true);
+                    }
+                    // The presence of this rule means signed commits are required
+                    "required_signatures" if is_selected("require_signed_commits") => {
+                        checks.require_signed_commits = Some(true);
+                    }
+                    // The presence of this rule means force push is disabled
+                    "non_fast_forward" if is_selected("disable_force_push") => {
+                        checks.disable_force_push = Some(true);
+                    }
+                    // The presence of this rule means a PR is needed. Now we check the rule's params
+                    "pull_request" => {
+                        if let Some(params) = rule.parameters.as_ref() {
+                            if is_selected("pr_one_approval")
+                                && params
+                                    .get("required_approving_review_count")
+                                    .and_then(|v| v.as_u64())
+                                    .unwrap_or(0)
+                                    > 0
+                            {
+                                checks.pr_one_approval = Some(true);
                             }
-                        }
-                        // The presence of this rule means force push is disabled
-                        "non_fast_forward" => {
-                            if is_selected("disable_force_push") {
-                                checks.disable_force_push = Some(true);
+                            if is_selected("pr_dismiss_stale")
+                                && params
+                                    .get("dismiss_stale_reviews_on_push")
+                                    .and_then(|v| v.as_bool())
+                                    .unwrap_or(false)
+                            {
+                                checks.pr_dismiss_stale = Some(true);
                             }
-                        }
-                        // The presence of this rule means a PR is needed. Now we check the rule's params
-                        "pull_request" => {
-                            if let Some(params) = rule.parameters.as_ref() {
-                                if is_selected("pr_one_approval")
-                                    && params
-                                        .get("required_approving_review_count")
-                                        .and_then(|v| v.as_u64())
-                                        .unwrap_or(0)
-                                        > 0
-                                {
-                                    checks.pr_one_approval = Some(true);
-                                }
-                                if is_selected("pr_dismiss_stale")
-                                    && params
-                                        .get("dismiss_stale_reviews_on_push")
-                                        .and_then(|v| v.as_bool())
-                                        .unwrap_or(false)
-                                {
-                                    checks.pr_dismiss_stale = Some(true);
-                                }
-                                if is_selected("pr_require_code_owner")
-                                    && params
-                                        .get("require_code_owner_review")
-                                        .and_then(|v| v.as_bool())
-                                        .unwrap_or(false)
-                                {
-                                    checks.pr_require_code_owner = Some(true);
-                                }
+                            if is_selected("pr_require_code_owner")
+                                && params
+                                    .get("require_code_owner_review")
+                                    .and_then(|v| v.as_bool())
+                                    .unwrap_or(false)
+                            {
+                                checks.pr_require_code_owner = Some(true);
                             }
                         }
-                        // The presence of this rule means the "require status checks" checkbox is ticked. Now we check the rule's params
-                        "required_status_checks" => {
-                            if is_selected("require_status_checks") {
-                                if let Some(params) = rule.parameters.as_ref() {
-                                    if params
-                                        .get("required_status_checks")
-                                        .and_then(|v| v.as_array())
-                                        .map(|a| !a.is_empty())
-                                        .unwrap_or(false)
-                                    {
-                                        checks.require_status_checks = Some(true);
-                                    }
-                                }
+                    }
+                    // The presence of this rule means the "require status checks" checkbox is ticked. Now we check the rule's params
+                    "required_status_checks" if is_selected("require_status_checks") => {
+                        if let Some(params) = rule.parameters.as_ref() {
+                            if params
+                                .get("required_status_checks")
+                                .and_then(|v| v.as_array())
+                                .map(|a| !a.is_empty())
+                                .unwrap_or(false)
+                            {
+                                checks.require_status_checks = Some(true);
                             }
                         }
-                        _ => {}
                     }
+                    _ => {}
                 }
             }
+        }
 
-            // Propagate 403 status for checks unresolved by either source
-            match (&bpr_fetch, &rules_fetch) {
-                (Err(Errors::NoAccess403), _) | (_, Err(Errors::NoAccess403)) => {
-                    // Function that sets a check to None if nothing else had already set it to passed
-                    let mark_na = |c: &mut Check| {
-                        if *c != Some(true) {
-                            *c = None
-                        }
-                    };
-                    mark_na(&mut checks.pr_one_approval);
-                    mark_na(&mut checks.pr_dismiss_stale);
-                    mark_na(&mut checks.pr_require_code_owner);
-                    mark_na(&mut checks.disable_force_push);
-                    mark_na(&mut checks.disable_deletion);
-                    mark_na(&mut checks.require_signed_commits);
-                    mark_na(&mut checks.require_status_checks);
-                }
-                _ => {}
+        // Propagate 403 status for checks unresolved by either source
+        match (&bpr_fetch, &rules_fetch) {
+            (Err(Error::Forbidden { .. }), _) | (_, Err(Error::Forbidden { .. })) => {
+                // Function that sets a check to None if nothing else had already set it to passed
+                let mark_na = |c: &mut Check| {
+                    if *c != Some(true) {
+                        *c = None
+                    }
+                };
+                mark_na(&mut checks.pr_one_approval);
+                mark_na(&mut checks.pr_dismiss_stale);
+                mark_na(&mut checks.pr_require_code_owner);
+                mark_na(&mut checks.disable_force_push);
+                mark_na(&mut checks.disable_deletion);
+                mark_na(&mut checks.require_signed_commits);
+                mark_na(&mut checks.require_status_checks);
             }
+            _ => {}
         }
+    }
 
-        // 3) CODEOWNERS exists and is valid
-        if is_selected("codeowners_valid") {
-            match utils::codeowners_exists_and_is_valid(&bootstrap, &repo) {
-                Ok(CodeownersStatus::Valid) => checks.codeowners_valid = Some(true),
-                Ok(CodeownersStatus::Invalid) | Ok(CodeownersStatus::Missing) => {}
-                Err(Errors::NoAccess403) => checks.codeowners_valid = None,
-                Err(Errors::MissingOrError) => {}
+    // 3) CODEOWNERS exists and is valid
+    if is_selected("codeowners_valid") {
+        let status = match &snapshot_codeowners_path {
+            // GitHub can only report errors for a file that exists
+            Some(None) => Ok(CodeownersStatus::Missing),
+            _ => utils::codeowners_exists_and_is_valid(bootstrap, repo),
+        };
+        sources.codeowners = match &status {
+            Ok(CodeownersStatus::Valid) => {
+                Source::Read("a CODEOWNERS file without errors".to_string())
+            }
+            Ok(CodeownersStatus::Invalid) => {
+                Source::Read("a CODEOWNERS file with errors reported by GitHub".to_string())
             }
+            Ok(CodeownersStatus::Missing) => Source::Missing,
+            Err(e) => Source::Failed(e.clone()),
+        };
+        match status {
+            Ok(CodeownersStatus::Valid) => checks.codeowners_valid = Some(true),
+            Ok(CodeownersStatus::Invalid) | Ok(CodeownersStatus::Missing) => {}
+            Err(Error::Forbidden { .. }) => checks.codeowners_valid = None,
+            Err(_) => {}
         }
+    }
 
-        // Determine repository visibility (reuse from repo metadata if present)
-        let visibility = visibility_opt.unwrap_or_else(|| "unknown".to_string());
-
-        // If CSV export is enabled, write a row; otherwise, print report
-        if let Some(wtr) = csv_writer.as_mut() {
-            let co_path = if is_selected("codeowners_valid") {
-                utils::find_codeowners_path(&bootstrap, &repo).unwrap_or_else(|| "".to_string())
-            } else {
-                "".to_string()
-            };
-            wtr.write_record([
-                repo.as_str(),
-                default_branch.as_str(),
-                visibility.as_str(),
-                check_csv_value_named(
-                    checks.pr_one_approval,
-                    "pr_one_approval",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.pr_dismiss_stale,
-                    "pr_dismiss_stale",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.pr_require_code_owner,
-                    "pr_require_code_owner",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.disable_force_push,
-                    "disable_force_push",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.disable_deletion,
-                    "disable_deletion",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.require_signed_commits,
-                    "require_signed_commits",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.require_status_checks,
-                    "require_status_checks",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                check_csv_value_named(
-                    checks.codeowners_valid,
-                    "codeowners_valid",
-                    selected_set.as_ref(),
-                )
-                .as_str(),
-                co_path.as_str(),
-            ])
-            .expect("Unable to write CSV row");
-            // Flush after every write to ensure durability on long runs
-            wtr.flush().ok();
-        } else {
-            print_report(
-                &repo,
-                &default_branch,
-                &visibility,
-                checks,
-                selected_set.as_ref(),
-            );
+    let codeowners_path = if with_codeowners_path && is_selected("codeowners_valid") {
+        match snapshot_codeowners_path {
+            Some(path) => path.unwrap_or_default(),
+            None => utils::find_codeowners_path(bootstrap, repo).unwrap_or_default(),
         }
-        pb.inc(1);
-    }
+    } else {
+        "".to_string()
+    };
 
-    // Flush CSV if used
-    if let Some(mut wtr) = csv_writer {
-        wtr.flush().expect("Unable to flush CSV writer");
-    }
-    pb.finish_with_message("done");
+    Ok(RepoReport {
+        branch: default_branch,
+        // Determine repository visibility (reuse from repo metadata if present)
+        visibility: info.visibility.unwrap_or_else(|| "unknown".to_string()),
+        checks,
+        codeowners_path,
+        sources,
+    })
 }
 
 fn print_report(
@@ -530,9 +831,10 @@ fn print_report(
     visibility: &str,
     checks: ProtectionChecks,
     selected: Option<&HashSet<String>>,
+    weights: &Weights,
 ) {
-    let (score, max) = compute_selected_score(&checks, selected);
-    println!(
+    let (score, max) = compute_selected_score(&checks, selected, weights);
+    output::line(format!(
         "{} {}  {} {}  {} {}  {} {}/{}",
         "Repo:".yellow(),
         repo.white(),
@@ -543,7 +845,7 @@ fn print_report(
         "Score:".yellow(),
         score.to_string().white(),
         max.to_string().white()
-    );
+    ));
     let show = |name: &str, sel: Option<&HashSet<String>>| -> bool {
         match sel {
             None => true,
@@ -552,60 +854,60 @@ fn print_report(
     };
 
     if show("pr_one_approval", selected) {
-        println!(
+        output::line(format!(
             "  - PR requires one approval: {}",
             check_symbol(checks.pr_one_approval)
-        );
+        ));
     }
     if show("pr_dismiss_stale", selected) {
-        println!(
+        output::line(format!(
             "  - PR: dismiss stale reviews: {}",
             check_symbol(checks.pr_dismiss_stale)
-        );
+        ));
     }
     if show("pr_require_code_owner", selected) {
-        println!(
+        output::line(format!(
             "  - PR requires code owners approval: {}",
             check_symbol(checks.pr_require_code_owner)
-        );
+        ));
     }
     if show("disable_force_push", selected) {
-        println!(
+        output::line(format!(
             "  - Force-push disabled: {}",
             check_symbol(checks.disable_force_push)
-        );
+        ));
     }
     if show("disable_deletion", selected) {
-        println!(
+        output::line(format!(
             "  - Deletion disabled: {}",
             check_symbol(checks.disable_deletion)
-        );
+        ));
     }
     if show("require_signed_commits", selected) {
-        println!(
+        output::line(format!(
             "  - Require signed commits: {}",
             check_symbol(checks.require_signed_commits)
-        );
+        ));
     }
     if show("require_status_checks", selected) {
-        println!(
+        output::line(format!(
             "  - Require status checks: {}",
             check_symbol(checks.require_status_checks)
-        );
+        ));
     }
     if show("codeowners_valid", selected) {
-        println!(
+        output::line(format!(
             "  - CODEOWNERS exists and is valid: {}",
             check_symbol(checks.codeowners_valid)
-        );
+        ));
     }
 }
 
 fn compute_selected_score(
     checks: &ProtectionChecks,
     selected: Option<&HashSet<String>>,
+    weights: &Weights,
 ) -> (u32, u32) {
-    let weights = Weights::default();
     let mut items: Vec<(&Check, u32)> = Vec::new();
     let include = |name: &str, sel: Option<&HashSet<String>>| -> bool {
         match sel {
@@ -1,56 +1,27 @@
-use std::{thread, time::Duration};
-
 use crate::{
-    compliance::{BprResponse, Errors, RulesetRule},
-    make_github_request, Bootstrap,
+    compliance::{BprResponse, EnabledFlag, PullRequestReviews, RequiredStatusChecks, RulesetRule},
+    graphql::BranchProtection,
+    make_github_request, Bootstrap, Error,
 };
 
-/// Get Branch Protection Rules for a repo's given branch
+/// Get Branch Protection Rules for a repo's given branch.
+/// GitHub answers 404 (i.e., `Error::NotFound`) when the branch is not protected.
 pub fn get_bpr(
     bootstrap: &Bootstrap,
     repo: &str,
     branch: &str,
-    max_attempts: u32,
-) -> Result<BprResponse, Errors> {
-    let mut attempts = 0;
-    loop {
-        attempts += 1;
-        match make_github_request(
-            &bootstrap.token,
-            &format!(
-                "/repos/{}/{repo}/branches/{branch}/protection",
-                bootstrap.org
-            ),
-            3,
-            None,
-        ) {
-            Ok(res) => {
-                if res.get("status").and_then(|v| v.as_str()) == Some("404") {
-                    return Err(Errors::MissingOrError);
-                }
-                if res.get("status").and_then(|v| v.as_str()) == Some("403")
-                    || res
-                        .get("message")
-                        .and_then(|v| v.as_str())
-                        .map(|m| m.contains("Resource not accessible"))
-                        .unwrap_or(false)
-                {
-                    return Err(Errors::NoAccess403);
-                }
-                return serde_json::from_value::<BprResponse>(res)
-                    .map_err(|_| Errors::MissingOrError);
-            }
-            Err(_) => {
-                // See if we should retry
-                if attempts < max_attempts {
-                    thread::sleep(Duration::from_millis(500));
-                    continue;
-                } else {
-                    return Err(Errors::MissingOrError);
-                }
-            }
-        }
-    }
+    retries: u8,
+) -> Result<BprResponse, Error> {
+    let res = make_github_request(
+        bootstrap,
+        &format!(
+            "/repos/{}/{repo}/branches/{branch}/protection",
+            bootstrap.org
+        ),
+        retries,
+        None,
+    )?;
+    Ok(serde_json::from_value::<BprResponse>(res)?)
 }
 
 /// Get Rulesets for a repo's given branch
@@ -58,33 +29,50 @@ pub fn get_rules(
     bootstrap: &Bootstrap,
     repo: &str,
     branch: &str,
-    max_attempts: u32,
-) -> Result<Vec<RulesetRule>, Errors> {
-    let mut attempts = 0;
-    loop {
-        attempts += 1;
-        match make_github_request(
-            &bootstrap.token,
-            &format!("/repos/{}/{repo}/rules/branches/{branch}", bootstrap.org),
-            3,
-            None,
-        ) {
-            Ok(res) => {
-                if res.get("status").and_then(|v| v.as_str()) == Some("403") {
-                    return Err(Errors::NoAccess403);
-                }
-                return serde_json::from_value::<Vec<RulesetRule>>(res)
-                    .map_err(|_| Errors::MissingOrError);
-            }
-            Err(_) => {
-                // See if we should retry
-                if attempts < max_attempts {
-                    thread::sleep(Duration::from_millis(500));
-                    continue;
-                } else {
-                    return Err(Errors::MissingOrError);
-                }
-            }
+    retries: u8,
+) -> Result<Vec<RulesetRule>, Error> {
+    let res = make_github_request(
+        bootstrap,
+        &format!("/repos/{}/{repo}/rules/branches/{branch}", bootstrap.org),
+        retries,
+        None,
+    )?;
+    Ok(serde_json::from_value::<Vec<RulesetRule>>(res)?)
+}
+
+/// Turn a branch protection rule fetched with GraphQL into what the REST API would have returned
+impl From<BranchProtection> for BprResponse {
+    fn from(rule: BranchProtection) -> Self {
+        let required_status_checks = rule.requires_status_checks.then(|| RequiredStatusChecks {
+            checks: rule
+                .required_status_checks
+                .unwrap_or_default()
+                .into_iter()
+                .map(|check| serde_json::json!({ "context": check.context }))
+                .collect(),
+        });
+        // These settings only exist when pull requests are required
+        let requires_pull_request = rule.requires_approving_reviews
+            || rule.requires_code_owner_reviews
+            || rule.dismisses_stale_reviews;
+        let required_pull_request_reviews = requires_pull_request.then(|| PullRequestReviews {
+            required_approving_review_count: rule.required_approving_review_count.unwrap_or(0),
+            dismiss_stale_reviews: rule.dismisses_stale_reviews,
+            require_code_owner_reviews: rule.requires_code_owner_reviews,
+        });
+
+        BprResponse {
+            allow_force_pushes: Some(EnabledFlag {
+                enabled: rule.allows_force_pushes,
+            }),
+            allow_deletions: Some(EnabledFlag {
+                enabled: rule.allows_deletions,
+            }),
+            required_signatures: Some(EnabledFlag {
+                enabled: rule.requires_commit_signatures,
+            }),
+            required_status_checks,
+            required_pull_request_reviews,
         }
     }
 }
@@ -1,20 +1,17 @@
 use std::collections::HashSet;
 
 use crate::{
-    compliance::{Check, CodeownersStatus, Errors},
-    make_github_request, Bootstrap,
+    codeowners::CO_LOCATIONS,
+    compliance::{Check, CodeownersStatus},
+    make_github_request, Bootstrap, Error,
 };
 
 // Try the common CODEOWNERS locations and return the repository-relative path if found
 pub fn find_codeowners_path(bootstrap: &Bootstrap, repo: &str) -> Option<String> {
-    const CO_LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
     for location in CO_LOCATIONS {
         let url = format!("/repos/{}/{}/contents/{}", bootstrap.org, repo, location);
-        match make_github_request(&bootstrap.token, &url, 2, None) {
+        match make_github_request(bootstrap, &url, 2, None) {
             Ok(v) => {
-                if v.get("status").and_then(|s| s.as_str()) == Some("404") {
-                    continue;
-                }
                 // If GitHub returned an object for this path, it exists
                 if v.get("path").and_then(|p| p.as_str()).is_some() {
                     return Some(location.to_string());
@@ -37,11 +34,9 @@ pub fn read_existing_csv_repos(path: &str) -> HashSet<String> {
         Err(_) => return set,
     };
     let repo_idx = headers.iter().position(|h| h == "repository").unwrap_or(0);
-    for result in rdr.records() {
-        if let Ok(record) = result {
-            if let Some(repo) = record.get(repo_idx) {
-                set.insert(repo.to_string());
-            }
+    for record in rdr.records().flatten() {
+        if let Some(repo) = record.get(repo_idx) {
+            set.insert(repo.to_string());
         }
     }
     set
@@ -51,23 +46,22 @@ pub fn read_existing_csv_repos(path: &str) -> HashSet<String> {
 pub fn codeowners_exists_and_is_valid(
     bootstrap: &Bootstrap,
     repo: &str,
-) -> Result<CodeownersStatus, Errors> {
+) -> Result<CodeownersStatus, Error> {
     let url = format!("/repos/{}/{repo}/codeowners/errors", bootstrap.org);
-    match make_github_request(&bootstrap.token, &url, 3, None) {
-        Ok(res) => {
-            if res.get("status").and_then(|v| v.as_str()) == Some("403") {
-                return Err(Errors::NoAccess403);
-            }
-            match res.get("errors") {
-                None => Ok(CodeownersStatus::Missing),
-                Some(errors) => match errors.as_array() {
-                    Some(arr) if arr.is_empty() => Ok(CodeownersStatus::Valid),
-                    Some(_) => Ok(CodeownersStatus::Invalid),
-                    None => Err(Errors::MissingOrError),
-                },
-            }
-        }
-        Err(_) => Err(Errors::MissingOrError),
+    match make_github_request(bootstrap, &url, 3, None) {
+        Ok(res) => match res.get("errors") {
+            None => Ok(CodeownersStatus::Missing),
+            Some(errors) => match errors.as_array() {
+                Some(arr) if arr.is_empty() => Ok(CodeownersStatus::Valid),
+                Some(_) => Ok(CodeownersStatus::Invalid),
+                None => Err(Error::Decode(
+                    "The CODEOWNERS errors are not an array".to_string(),
+                )),
+            },
+        },
+        // GitHub answers 404 when there is no CODEOWNERS file
+        Err(e) if e.is_not_found() => Ok(CodeownersStatus::Missing),
+        Err(e) => Err(e),
     }
 }
 
@@ -87,6 +81,15 @@ pub fn check_csv_value(v: Check) -> String {
     }
 }
 
+/// The other way around, for checks read back from the CSV or from the evidence of a finding
+pub fn check_from_csv_value(v: &str) -> Check {
+    match v {
+        "pass" => Some(true),
+        "fail" => Some(false),
+        _ => None,
+    }
+}
+
 pub fn check_csv_value_named(v: Check, name: &str, selected: Option<&HashSet<String>>) -> String {
     match selected {
         None => check_csv_value(v),
@@ -0,0 +1,308 @@
+use std::{collections::HashSet, path::Path};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    compliance::{Weights, CHECK_NAMES},
+    external_collaborator::{ACCESS_EVIDENCE, BUILT_IN_COLUMNS},
+    finding::Severity,
+    output::Format,
+    Backend, Error,
+};
+
+/// Settings read from a TOML file passed with `--config`. Everything is optional.
+/// When the same setting can be given in several ways, the first one found wins:
+/// command line flags, then environment variables, then this file, then the built-in defaults.
+///
+/// ```toml
+/// org = "my-org"
+/// concurrency = 8
+/// format = "json"
+/// fail_on = "high"
+///
+/// [requests]
+/// page_size = 100
+/// retries = 5
+///
+/// [repos]
+/// exclude = ["sandbox-*", "archive-*"]
+///
+/// [compliance]
+/// checks = ["pr_one_approval", "codeowners_valid"]
+///
+/// [compliance.weights]
+/// pr_one_approval = 3
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The organization to audit, if `GH_ORG` is not set
+    pub org: Option<String>,
+    /// The GitHub API base URL, if neither `--api-url` nor `GH_API_URL` is set
+    pub api_url: Option<String>,
+    pub concurrency: Option<usize>,
+    pub backend: Option<Backend>,
+    /// How the results are written, if `--format` is not given
+    pub format: Option<Format>,
+    /// The severity from which findings fail the run, if `--fail-on` is not given
+    pub fail_on: Option<Severity>,
+    pub requests: RequestsConfig,
+    pub repos: RepoFilter,
+    pub ec: EcConfig,
+    pub deploy_keys: DeployKeysConfig,
+    pub codeowners: CodeownersConfig,
+    pub compliance: ComplianceConfig,
+}
+
+/// How we talk to GitHub
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestsConfig {
+    /// How many items we ask for in each page of a list endpoint (1 to 100).
+    /// If `None`, each endpoint uses its own default
+    pub page_size: Option<u8>,
+    /// How many times a request is sent before giving up on a transient failure.
+    /// If `None`, each endpoint uses its own default
+    pub retries: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EcConfig {
+    /// The previous run CSV file, if `--previous` is not given
+    pub previous: Option<String>,
+    /// Where to write the access changes since the previous run, if `--diff` is not given
+    pub diff: Option<String>,
+    /// Where to write the CSV, if `--csv` is not given
+    pub csv: Option<String>,
+    /// The metadata columns of the CSV, e.g., `["ServiceNow Request", "Business Owner"]`.
+    /// If `None`, the JIRA ticket and quorum proposal columns
+    pub metadata_columns: Option<Vec<String>>,
+    pub include_invitations: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeployKeysConfig {
+    pub previous: Option<String>,
+    pub all: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CodeownersConfig {
+    /// Ignored when repositories are given with `--repos`
+    pub search: bool,
+    pub also_gh_api: bool,
+    pub verbose: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComplianceConfig {
+    /// The checks to run, if `--checks` is not given. If `None`, all of them
+    pub checks: Option<Vec<String>>,
+    pub weights: Weights,
+    pub active_repo_only: bool,
+    pub csv: Option<String>,
+    /// The JUnit XML file to write, if `--junit` is not given
+    pub junit: Option<String>,
+    /// The lowest acceptable score, if `--min-score` is not given
+    pub min_score: Option<u32>,
+}
+
+impl Config {
+    /// Read and validate a configuration file
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            Error::Config(format!(
+                "Could not read the configuration file {}: {e}",
+                path.display()
+            ))
+        })?;
+        let config = toml::from_str::<Config>(&content).map_err(|e| {
+            Error::Config(format!(
+                "Invalid configuration file {}: {e}",
+                path.display()
+            ))
+        })?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Catch the mistakes that can't be expressed with types, so they don't surface halfway through an audit
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(page_size) = self.requests.page_size {
+            if !(1..=100).contains(&page_size) {
+                return Err(Error::Config(format!(
+                    "Invalid page_size [{page_size}] in the configuration file: GitHub accepts 1 to 100"
+                )));
+            }
+        }
+        if self.requests.retries == Some(0) {
+            return Err(Error::Config(
+                "Invalid retries [0] in the configuration file: requests are sent at least once"
+                    .to_string(),
+            ));
+        }
+        if self.concurrency == Some(0) {
+            return Err(Error::Config(
+                "The concurrency must be at least 1".to_string(),
+            ));
+        }
+        for check in self.compliance.checks.iter().flatten() {
+            if !CHECK_NAMES.contains(&check.to_lowercase().as_str()) {
+                return Err(Error::Config(format!(
+                    "Unknown compliance check [{check}] in the configuration file. Available: {}",
+                    CHECK_NAMES.join(", ")
+                )));
+            }
+        }
+        let mut metadata_columns = HashSet::new();
+        for column in self.ec.metadata_columns.iter().flatten() {
+            if column.trim().is_empty() {
+                return Err(Error::Config(
+                    "Empty metadata column in the configuration file".to_string(),
+                ));
+            }
+            // The metadata columns are also evidence keys, next to the ones of the access
+            if BUILT_IN_COLUMNS.contains(&column.as_str())
+                || ACCESS_EVIDENCE.contains(&column.as_str())
+            {
+                return Err(Error::Config(format!(
+                    "Invalid metadata column [{column}] in the configuration file: the audit already fills it in"
+                )));
+            }
+            if !metadata_columns.insert(column) {
+                return Err(Error::Config(format!(
+                    "Duplicate metadata column [{column}] in the configuration file"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which repositories the audits look at when they enumerate the whole organization.
+/// Patterns are repository names where `*` matches any sequence of characters, compared case-insensitively
+/// (like GitHub does). An empty `include` means all repositories, and `exclude` wins over `include`.
+/// Repositories given explicitly with `--repos` are never filtered.
+#[derive(Debug, Default, Deserialize)]
+#[serde(try_from = "RepoPatterns")]
+pub struct RepoFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RepoPatterns {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl TryFrom<RepoPatterns> for RepoFilter {
+    type Error = String;
+
+    fn try_from(patterns: RepoPatterns) -> Result<Self, Self::Error> {
+        let compile = |patterns: Vec<String>| {
+            patterns
+                .iter()
+                .map(|p| {
+                    let regex = format!("(?i)^{}$", regex::escape(p).replace(r"\*", ".*"));
+                    Regex::new(&regex).map_err(|e| format!("Invalid repository pattern [{p}]: {e}"))
+                })
+                .collect::<Result<Vec<Regex>, String>>()
+        };
+        Ok(Self {
+            include: compile(patterns.include)?,
+            exclude: compile(patterns.exclude)?,
+        })
+    }
+}
+
+impl RepoFilter {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether a repository should be audited
+    pub fn matches(&self, repo: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|r| r.is_match(repo)))
+            && !self.exclude.iter().any(|r| r.is_match(repo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse and validate a configuration file, keeping only the error message
+    fn parse(content: &str) -> Result<Config, String> {
+        let config = toml::from_str::<Config>(content).map_err(|e| e.to_string())?;
+        match config.validate() {
+            Ok(()) => Ok(config),
+            Err(Error::Config(message)) => Err(message),
+            Err(e) => panic!("unexpected error: {e}"),
+        }
+    }
+
+    #[test]
+    fn page_size_is_between_1_and_100() {
+        for page_size in [1, 50, 100] {
+            let config = parse(&format!("[requests]\npage_size = {page_size}")).unwrap();
+            assert_eq!(config.requests.page_size, Some(page_size));
+        }
+        for page_size in [0, 101, 255] {
+            let err = parse(&format!("[requests]\npage_size = {page_size}")).unwrap_err();
+            assert!(err.contains("Invalid page_size"), "{err}");
+        }
+        assert!(parse("[requests]\npage_size = 256").is_err());
+    }
+
+    #[test]
+    fn requests_are_sent_at_least_once() {
+        assert_eq!(
+            parse("[requests]\nretries = 1").unwrap().requests.retries,
+            Some(1)
+        );
+        let err = parse("[requests]\nretries = 0").unwrap_err();
+        assert!(err.contains("Invalid retries [0]"), "{err}");
+    }
+
+    #[test]
+    fn concurrency_and_checks_are_validated() {
+        assert!(parse("concurrency = 0").is_err());
+        assert!(parse("[compliance]\nchecks = [\"PR_ONE_APPROVAL\"]").is_ok());
+        let err = parse("[compliance]\nchecks = [\"nope\"]").unwrap_err();
+        assert!(err.contains("Unknown compliance check [nope]"), "{err}");
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(parse("").is_ok());
+        assert!(parse("[requests]\npagesize = 10").is_err());
+    }
+
+    #[test]
+    fn metadata_columns_are_validated() {
+        let config =
+            parse("[ec]\nmetadata_columns = [\"ServiceNow Request\", \"Business Owner\"]").unwrap();
+        assert_eq!(
+            config.ec.metadata_columns.unwrap(),
+            ["ServiceNow Request", "Business Owner"]
+        );
+        assert!(parse("[ec]\nmetadata_columns = []").is_ok());
+
+        let err = parse("[ec]\nmetadata_columns = [\" \"]").unwrap_err();
+        assert!(err.contains("Empty metadata column"), "{err}");
+        // Built-in columns, and the evidence keys of the access
+        for column in ["Approver", "GitHub User ID", "access", "user_id"] {
+            let err = parse(&format!("[ec]\nmetadata_columns = [\"{column}\"]")).unwrap_err();
+            assert!(err.contains("the audit already fills it in"), "{err}");
+        }
+        let err = parse("[ec]\nmetadata_columns = [\"Owner\", \"Owner\"]").unwrap_err();
+        assert!(err.contains("Duplicate metadata column [Owner]"), "{err}");
+    }
+}
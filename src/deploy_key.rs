@@ -1,10 +1,15 @@
-use std::collections::{HashMap, HashSet};
-
 use colored::Colorize;
+use log::info;
 
 use crate::{
-    make_paginated_github_request, make_paginated_github_request_with_index, Bootstrap, Member,
-    Repository,
+    audit::Audit,
+    config::Config,
+    finding::{self, Target},
+    make_paginated_github_request,
+    members::get_indexed_org_members,
+    pool::{for_each_ordered, progress_bar},
+    preflight::AuditKind,
+    Bootstrap, Error, Finding, Findings, Repository, Skipped,
 };
 
 #[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
@@ -21,99 +26,123 @@ struct DeployKey {
     enabled: bool,
 }
 
-pub fn run_audit(bootstrap: Bootstrap, _previous_csv: Option<String>, all: bool) {
-    println!("{}", "GitHub Deploy Key Audit".white().bold());
-
-    println!("{}", "Fetching all organization members".yellow());
-    let members: HashMap<String, Member> = match make_paginated_github_request_with_index(
-        &bootstrap.token,
-        75,
-        &format!("/orgs/{}/members", &bootstrap.org),
-        3,
-        None,
-    ) {
-        Ok(members) => members,
-        Err(e) => {
-            panic!(
-                "{}: {}",
-                "I couldn't fetch the organization members".red(),
-                e
-            );
-        }
-    };
-
-    println!("{} {}", "Success! I found: ".green(), members.len());
-
-    let repositories: HashSet<Repository> = bootstrap.fetch_all_repositories(75, false).unwrap();
-
-    println!("{}", "Finally the big one, I'm going to check each repository one by one to find deploy keys and their access. This is going to take a while...".yellow());
-
-    let one_percent = (repositories.len() as f64 * 0.01).ceil() as usize;
-    let mut progress = 0;
-
-    for repository in repositories {
-        let deploy_keys: HashSet<DeployKey> = match make_paginated_github_request(
-            &bootstrap.token,
-            25,
-            &format!("/repos/{}/{}/keys", &bootstrap.org, repository.name),
-            3,
-            None,
-        ) {
-            Ok(dks) => dks,
-            Err(e) => {
-                panic!(
-                    "{} {}: {e}",
-                    repository.name.white(),
-                    "I couldn't fetch the repository deploy keys".red()
-                );
-            }
-        };
-
-        for deploy_key in deploy_keys {
-            match (all, members.contains_key(&deploy_key.added_by)) {
-                (true, is_member) => {
-                    println!(
-                        "{} has deploy key {} {}: {}",
-                        repository.name.white(),
-                        deploy_key.title.yellow(),
-                        if is_member {
-                            "added by member".yellow()
-                        } else {
-                            "added by non-member".red()
-                        },
-                        deploy_key.added_by.white()
-                    );
+/// Deploy keys added by people who are not members of the org
+#[derive(Default)]
+pub struct DeployKeyAudit {
+    pub previous: Option<String>,
+    /// Also report the keys added by members
+    pub all: bool,
+}
+
+impl Audit for DeployKeyAudit {
+    fn name(&self) -> &'static str {
+        "deploy-keys"
+    }
+
+    fn description(&self) -> &'static str {
+        "Deploy keys added by people who are not members of the org"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["members: read", "administration: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::DeployKeys
+    }
+
+    fn apply_config(&mut self, config: &mut Config) {
+        self.previous = self.previous.take().or(config.deploy_keys.previous.take());
+        self.all |= config.deploy_keys.all;
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_audit(bootstrap, self.previous.clone(), self.all)
+    }
+}
+
+pub fn run_audit(
+    bootstrap: &Bootstrap,
+    _previous_csv: Option<String>,
+    all: bool,
+) -> Result<Findings, Error> {
+    info!("{}", "GitHub Deploy Key Audit".white().bold());
+
+    info!("{}", "Fetching all organization members".yellow());
+    let members = get_indexed_org_members(bootstrap)?;
+
+    info!("{} {}", "Success! I found: ".green(), members.len());
+
+    let repositories: Vec<Repository> = bootstrap.fetch_all_repositories(75, false)?;
+
+    info!("{}", "Finally the big one, I'm going to check each repository one by one to find deploy keys and their access. This is going to take a while...".yellow());
+
+    let mut skipped = Skipped::default();
+    let mut findings = Findings::default();
+
+    let pb = progress_bar(repositories.len());
+    for_each_ordered(
+        repositories,
+        bootstrap.concurrency,
+        &pb,
+        |repository| {
+            make_paginated_github_request::<DeployKey>(
+                bootstrap,
+                25,
+                &format!("/repos/{}/{}/keys", &bootstrap.org, repository.name),
+                3,
+                None,
+            )
+        },
+        |repository, deploy_keys| {
+            let deploy_keys = match deploy_keys {
+                Ok(dks) => dks,
+                Err(e) => {
+                    pb.suspend(|| skipped.record(repository.name, e));
+                    return;
                 }
-                // We don't want all, and they are not a member so we
-                // log
-                (false, false) => {
-                    println!(
-                        "{} has deploy key {} {}: {}",
-                        repository.name.white(),
-                        deploy_key.title.yellow(),
-                        "added by non-member".red(),
-                        deploy_key.added_by.white()
+            };
+
+            pb.suspend(|| {
+                for deploy_key in deploy_keys {
+                    let is_member = members.contains_key(&deploy_key.added_by);
+                    // Keys added by members are only reported if we want all of them
+                    if is_member && !all {
+                        continue;
+                    }
+                    let (rule, added_by) = if is_member {
+                        (&finding::DEPLOY_KEY_MEMBER, "member")
+                    } else {
+                        (&finding::DEPLOY_KEY_NON_MEMBER, "non-member")
+                    };
+                    findings.record(
+                        Finding::new(
+                            rule,
+                            Target::DeployKey {
+                                repo: repository.name.clone(),
+                                id: deploy_key.id,
+                                title: deploy_key.title.clone(),
+                            },
+                            format!(
+                                "{} has deploy key {} added by {added_by}: {}",
+                                repository.name, deploy_key.title, deploy_key.added_by
+                            ),
+                        )
+                        .with("added_by", &deploy_key.added_by)
+                        .with("read_only", deploy_key.read_only)
+                        .with("created_at", &deploy_key.created_at)
+                        .with(
+                            "last_used",
+                            deploy_key.last_used.as_deref().unwrap_or("never"),
+                        ),
                     );
                 }
-                // We don't want all, and they are members so we can skip
-                // this deploy key
-                (false, true) => (),
-            }
-
-            if !members.contains_key(&deploy_key.added_by) {
-                println!(
-                    "{} has deploy key {} {}: {}",
-                    repository.name.white(),
-                    deploy_key.title.yellow(),
-                    "added by a non-member".red(),
-                    deploy_key.added_by.white()
-                );
-            }
-        }
-
-        progress += 1;
-        if progress % one_percent == 0 {
-            println!("Processed {} reposistories", progress.to_string().blue());
-        }
-    }
+            });
+        },
+    );
+    pb.finish_and_clear();
+
+    skipped.report();
+    findings.report();
+    Ok(findings.with_skipped(skipped))
 }
@@ -0,0 +1,152 @@
+use std::fmt::Display;
+
+use colored::Colorize;
+use log::warn;
+use reqwest::StatusCode;
+
+/// Errors that can happen while auditing an organization
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// The tool was invoked or configured incorrectly (e.g., a missing environment variable)
+    Config(String),
+    /// GitHub answered 404: the resource does not exist, or the token cannot see it
+    NotFound { url: String },
+    /// GitHub answered 401 or 403: the token lacks the permissions needed for this resource
+    Forbidden { url: String, message: String },
+    /// GitHub answered 429 and didn't tell us how long to wait
+    RateLimited { url: String },
+    /// GitHub answered with any other unexpected HTTP status
+    Http {
+        url: String,
+        status: u16,
+        message: String,
+    },
+    /// We could not reach GitHub or read its response
+    Transport(String),
+    /// GitHub's response did not look like what we expected
+    Decode(String),
+    /// GitHub's GraphQL API returned an error that doesn't match any of the above
+    GraphQl(String),
+}
+
+impl Error {
+    /// Build an error from an HTTP status that is not a success
+    pub(crate) fn from_status(url: &str, status: StatusCode, message: String) -> Self {
+        let url = url.to_string();
+        match status {
+            StatusCode::NOT_FOUND => Error::NotFound { url },
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Error::Forbidden { url, message },
+            StatusCode::TOO_MANY_REQUESTS => Error::RateLimited { url },
+            _ => Error::Http {
+                url,
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+
+    /// Whether sending the same request again could succeed, e.g., for network issues or 5xx errors
+    pub(crate) fn is_transient(&self) -> bool {
+        match self {
+            Error::Transport(_) | Error::RateLimited { .. } => true,
+            Error::Http { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::NotFound { .. })
+    }
+
+    pub fn is_forbidden(&self) -> bool {
+        matches!(self, Error::Forbidden { .. })
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Config(msg) => write!(f, "{msg}"),
+            Error::NotFound { url } => write!(f, "Not found (404): {url}"),
+            Error::Forbidden { url, message } => write!(f, "Forbidden (403): {url}: {message}"),
+            Error::RateLimited { url } => write!(f, "Rate limited (429): {url}"),
+            Error::Http {
+                url,
+                status,
+                message,
+            } => write!(f, "HTTP {status}: {url}: {message}"),
+            Error::Transport(msg) => write!(f, "Could not communicate with GitHub: {msg}"),
+            Error::Decode(msg) => write!(f, "Unexpected response from GitHub: {msg}"),
+            Error::GraphQl(msg) => write!(f, "GraphQL error: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Transport(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e.to_string())
+    }
+}
+
+/// Keeps track of the targets (e.g., repositories or teams) an audit had to skip because of an error,
+/// so that a long run can go on and report all of them at the end.
+#[derive(Default)]
+pub struct Skipped {
+    entries: Vec<(String, Error)>,
+}
+
+impl Skipped {
+    /// Record that a target was skipped, and log it right away
+    pub fn record(&mut self, target: impl Into<String>, error: Error) {
+        let target = target.into();
+        warn!(
+            "{} {}: {}. {}",
+            "Skipping".yellow(),
+            target.white(),
+            error.to_string().red(),
+            "I will continue with the others.".yellow()
+        );
+        self.entries.push((target, error));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add the targets skipped by another audit, without logging them again
+    pub fn extend(&mut self, other: Skipped) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(String, Error)> {
+        self.entries.iter()
+    }
+
+    /// Print a summary of everything that was skipped, if anything
+    pub fn report(&self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        warn!(
+            "{} {} {}",
+            "Warning! I had to skip".yellow(),
+            self.entries.len().to_string().white(),
+            "targets because of errors:".yellow()
+        );
+        for (target, error) in &self.entries {
+            warn!("  - {}: {}", target.white(), error.to_string().red());
+        }
+    }
+}
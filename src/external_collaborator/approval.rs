@@ -0,0 +1,255 @@
+//! Whether the access of an external collaborator is approved, and for how long, from the approval columns of the CSV
+
+use chrono::NaiveDate;
+
+use crate::{
+    finding::{self, Rule, Target},
+    Finding,
+};
+
+use super::ExternalCollaboratorPermission;
+
+/// The only status that counts as an approval, whatever its case
+const APPROVED: &str = "Approved";
+
+/// How dates are written in the CSV
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// What is wrong with the approval of an access
+#[derive(Debug, PartialEq)]
+enum Problem {
+    Missing,
+    Unrecognized(String),
+    Expired(NaiveDate),
+}
+
+/// A date column, if it is filled in. `Err` with a description of the problem if it is not a date
+fn date(column: &str, value: &Option<String>) -> Result<Option<NaiveDate>, String> {
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => NaiveDate::parse_from_str(value, DATE_FORMAT)
+            .map(Some)
+            .map_err(|_| format!("the {column} {value} is not a YYYY-MM-DD date")),
+    }
+}
+
+/// An approval needs a status, a date and an approver. The expiry date is optional, for accesses that are
+/// not re-certified
+fn problem(permission: &ExternalCollaboratorPermission, today: NaiveDate) -> Option<Problem> {
+    let status = permission
+        .status
+        .as_deref()
+        .map(str::trim)
+        .unwrap_or_default();
+    if status.is_empty() {
+        return Some(Problem::Missing);
+    }
+    if !status.eq_ignore_ascii_case(APPROVED) {
+        return Some(Problem::Unrecognized(format!(
+            "the status {status} is not {APPROVED}"
+        )));
+    }
+    match date("approval date", &permission.approval_date) {
+        Err(e) => return Some(Problem::Unrecognized(e)),
+        Ok(None) => return Some(Problem::Unrecognized("it has no approval date".to_string())),
+        Ok(Some(_)) => {}
+    }
+    if permission
+        .approver
+        .as_deref()
+        .map(str::trim)
+        .unwrap_or_default()
+        .is_empty()
+    {
+        return Some(Problem::Unrecognized("it has no approver".to_string()));
+    }
+    match date("expiry date", &permission.expiry_date) {
+        Err(e) => Some(Problem::Unrecognized(e)),
+        Ok(Some(expiry)) if expiry < today => Some(Problem::Expired(expiry)),
+        Ok(_) => None,
+    }
+}
+
+/// A finding if the approval of `permission` is missing, unrecognized or expired on `today`.
+/// An approval without an expiry date never expires
+pub fn check(permission: &ExternalCollaboratorPermission, today: NaiveDate) -> Option<Finding> {
+    let (rule, message): (&Rule, String) = match problem(permission, today)? {
+        Problem::Missing => (
+            &finding::EC_APPROVAL_MISSING,
+            format!(
+                "The {} access of {} to {} is not approved",
+                permission.access, permission.login, permission.repository
+            ),
+        ),
+        Problem::Unrecognized(reason) => (
+            &finding::EC_APPROVAL_UNRECOGNIZED,
+            format!(
+                "I don't recognize the approval of the access of {} to {}: {reason}",
+                permission.login, permission.repository
+            ),
+        ),
+        Problem::Expired(expiry) => (
+            &finding::EC_APPROVAL_EXPIRED,
+            format!(
+                "The approval of the access of {} to {} expired on {expiry}",
+                permission.login, permission.repository
+            ),
+        ),
+    };
+    Some(
+        Finding::new(
+            rule,
+            Target::RepositoryUser {
+                repo: permission.repository.clone(),
+                login: permission.login.clone(),
+            },
+            message,
+        )
+        .with("access", &permission.access)
+        .with("status", permission.status.as_deref().unwrap_or_default())
+        .with(
+            "approval_date",
+            permission.approval_date.as_deref().unwrap_or_default(),
+        )
+        .with(
+            "approver",
+            permission.approver.as_deref().unwrap_or_default(),
+        )
+        .with(
+            "expiry_date",
+            permission.expiry_date.as_deref().unwrap_or_default(),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 16).unwrap()
+    }
+
+    /// An access approved by alice on 2026-01-02, until `expiry_date`
+    fn approved(expiry_date: Option<&str>) -> ExternalCollaboratorPermission {
+        let mut permission = ExternalCollaboratorPermission::new(
+            ("eve".to_string(), Some(9)),
+            ("alpha".to_string(), Some(100)),
+            "push".to_string(),
+        );
+        permission.status = Some("Approved".to_string());
+        permission.approval_date = Some("2026-01-02".to_string());
+        permission.approver = Some("alice".to_string());
+        permission.expiry_date = expiry_date.map(str::to_string);
+        permission
+    }
+
+    fn unrecognized(reason: &str) -> Option<Problem> {
+        Some(Problem::Unrecognized(reason.to_string()))
+    }
+
+    #[test]
+    fn approved_until_a_later_date() {
+        assert_eq!(problem(&approved(Some("2027-01-01")), today()), None);
+    }
+
+    #[test]
+    fn approved_without_expiry_never_expires() {
+        assert_eq!(problem(&approved(None), today()), None);
+        assert_eq!(problem(&approved(Some(" ")), today()), None);
+    }
+
+    #[test]
+    fn approved_until_today_is_not_expired_yet() {
+        assert_eq!(problem(&approved(Some("2026-10-16")), today()), None);
+    }
+
+    #[test]
+    fn status_is_case_insensitive() {
+        let mut permission = approved(None);
+        permission.status = Some(" approved ".to_string());
+        assert_eq!(problem(&permission, today()), None);
+    }
+
+    #[test]
+    fn missing_status() {
+        let mut permission = approved(None);
+        permission.status = None;
+        assert_eq!(problem(&permission, today()), Some(Problem::Missing));
+        permission.status = Some("  ".to_string());
+        assert_eq!(problem(&permission, today()), Some(Problem::Missing));
+    }
+
+    #[test]
+    fn other_status() {
+        let mut permission = approved(None);
+        permission.status = Some("Pending".to_string());
+        assert_eq!(
+            problem(&permission, today()),
+            unrecognized("the status Pending is not Approved")
+        );
+    }
+
+    #[test]
+    fn missing_approval_date() {
+        let mut permission = approved(None);
+        permission.approval_date = None;
+        assert_eq!(
+            problem(&permission, today()),
+            unrecognized("it has no approval date")
+        );
+    }
+
+    #[test]
+    fn invalid_approval_date() {
+        let mut permission = approved(None);
+        permission.approval_date = Some("02/01/2026".to_string());
+        assert_eq!(
+            problem(&permission, today()),
+            unrecognized("the approval date 02/01/2026 is not a YYYY-MM-DD date")
+        );
+    }
+
+    #[test]
+    fn missing_approver() {
+        let mut permission = approved(None);
+        permission.approver = Some(" ".to_string());
+        assert_eq!(
+            problem(&permission, today()),
+            unrecognized("it has no approver")
+        );
+    }
+
+    #[test]
+    fn invalid_expiry_date() {
+        assert_eq!(
+            problem(&approved(Some("soon")), today()),
+            unrecognized("the expiry date soon is not a YYYY-MM-DD date")
+        );
+    }
+
+    #[test]
+    fn expired() {
+        assert_eq!(
+            problem(&approved(Some("2026-10-15")), today()),
+            Some(Problem::Expired(
+                NaiveDate::from_ymd_opt(2026, 10, 15).unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn check_reports_the_rule_of_the_problem() {
+        let mut permission = approved(Some("2026-01-01"));
+        let finding = check(&permission, today()).unwrap();
+        assert_eq!(finding.rule_id(), finding::EC_APPROVAL_EXPIRED.id);
+        permission.status = None;
+        let finding = check(&permission, today()).unwrap();
+        assert_eq!(finding.rule_id(), finding::EC_APPROVAL_MISSING.id);
+        permission.status = Some("Approved".to_string());
+        permission.approver = None;
+        let finding = check(&permission, today()).unwrap();
+        assert_eq!(finding.rule_id(), finding::EC_APPROVAL_UNRECOGNIZED.id);
+        assert!(check(&approved(None), today()).is_none());
+    }
+}
@@ -0,0 +1,497 @@
+//! What changed in the access of outside collaborators since the previous run, for reviewers to sign off on
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+};
+
+use serde::Serialize;
+
+use crate::{output::SCHEMA_VERSION, Error};
+
+use super::{ExternalCollaboratorPermission, ExternalCollaboratorPermissions, OutsideCollaborator};
+
+/// How the access of a collaborator to a repository changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    /// Access to a repository the collaborator had no access to, including through an invitation they accepted
+    Granted,
+    /// More access than before, e.g., from pull to push
+    Escalated,
+    /// Less access than before, e.g., from admin to push
+    Downgraded,
+    /// Different access that we can't rank against the previous one
+    Changed,
+    /// No more access to a repository, although the collaborator is still an outside collaborator
+    Revoked,
+    /// No more access to a repository, because the collaborator is not an outside collaborator anymore
+    /// (they were removed from the org, or became a member)
+    LeftOrg,
+    /// A pending invitation to a repository, with `--include-invitations`
+    Invited,
+    /// A pending invitation that is gone without being accepted: it was declined, cancelled or deleted
+    InvitationClosed,
+}
+
+impl Change {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::Granted => "granted",
+            Change::Escalated => "escalated",
+            Change::Downgraded => "downgraded",
+            Change::Changed => "changed",
+            Change::Revoked => "revoked",
+            Change::LeftOrg => "left_org",
+            Change::Invited => "invited",
+            Change::InvitationClosed => "invitation_closed",
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A line of the diff. The approval columns are the ones of the previous run, since any change clears them
+#[derive(Clone, Debug, Serialize)]
+pub struct AccessChange {
+    pub change: Change,
+    pub login: String,
+    pub repository: String,
+    /// The IDs GitHub gives the user and the repository, when we know them
+    pub user_id: Option<u64>,
+    pub repository_id: Option<u64>,
+    /// `None` for granted access
+    pub previous_access: Option<String>,
+    /// `None` for revoked access
+    pub access: Option<String>,
+    pub previous_status: Option<String>,
+    pub previous_approval_date: Option<String>,
+    pub previous_approver: Option<String>,
+    pub previous_expiry_date: Option<String>,
+    /// The metadata columns of the previous run that were filled in, by column name
+    pub previous_metadata: BTreeMap<String, String>,
+}
+
+impl AccessChange {
+    fn new(
+        change: Change,
+        previous: Option<&ExternalCollaboratorPermission>,
+        current: Option<&ExternalCollaboratorPermission>,
+    ) -> Self {
+        let (login, repository) = previous
+            .or(current)
+            .map(|p| (p.login.clone(), p.repository.clone()))
+            .expect("a change has a previous or a current access");
+        Self {
+            change,
+            login,
+            repository,
+            user_id: current.or(previous).and_then(|p| p.user_id),
+            repository_id: current.or(previous).and_then(|p| p.repository_id),
+            previous_access: previous.map(|p| p.access.clone()),
+            access: current.map(|p| p.access.clone()),
+            previous_status: previous.and_then(|p| p.status.clone()),
+            previous_approval_date: previous.and_then(|p| p.approval_date.clone()),
+            previous_approver: previous.and_then(|p| p.approver.clone()),
+            previous_expiry_date: previous.and_then(|p| p.expiry_date.clone()),
+            previous_metadata: previous.map(|p| p.metadata.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// How much a permission allows, to tell escalations from downgrades. `None` for permissions we don't know
+fn rank(access: &str) -> Option<u8> {
+    match access {
+        "none" => Some(0),
+        "pull" => Some(1),
+        "triage" => Some(2),
+        "push" => Some(3),
+        "maintain" => Some(4),
+        "admin" => Some(5),
+        _ => None,
+    }
+}
+
+/// Compare the access of the previous run with the current one, ordered by login and repository.
+/// A previous row is only compared with current access that has the same IDs: when another account took the login
+/// (or another repository the name), the old one lost its access and the new one was granted it.
+/// The `replaced` rows of the previous run, whose names are now used by others, are always lost access.
+/// Access to the `skipped_repos`, whose collaborators we could not look at, is not reported as revoked,
+/// and invitations to the `skipped_invitations` repositories are not reported as closed
+pub fn diff(
+    previous: &ExternalCollaboratorPermissions,
+    replaced: &[ExternalCollaboratorPermission],
+    current: &ExternalCollaboratorPermissions,
+    outside_collaborators: &HashMap<String, OutsideCollaborator>,
+    skipped_repos: &HashSet<String>,
+    skipped_invitations: &HashSet<String>,
+) -> Vec<AccessChange> {
+    let mut changes = vec![];
+    for (key, now) in current {
+        let before = previous
+            .get(key)
+            .filter(|before| before.has_ids(now.user_id, now.repository_id));
+        let change = match before {
+            None if now.is_invitation() => Change::Invited,
+            None => Change::Granted,
+            Some(before) if before.is_invitation() && !now.is_invitation() => Change::Granted,
+            Some(before) if before.access == now.access => continue,
+            Some(before) => match (rank(&before.access), rank(&now.access)) {
+                (Some(before), Some(now)) if now > before => Change::Escalated,
+                (Some(before), Some(now)) if now < before => Change::Downgraded,
+                _ => Change::Changed,
+            },
+        };
+        changes.push(AccessChange::new(change, before, Some(now)));
+    }
+    let mut lost = vec![];
+    for (key, before) in previous {
+        match current.get(key) {
+            Some(now) if before.has_ids(now.user_id, now.repository_id) => {}
+            // Another account or repository has the name now
+            Some(_) => lost.push(before),
+            None => {
+                let skipped = if before.is_invitation() {
+                    skipped_invitations
+                } else {
+                    skipped_repos
+                };
+                if !skipped.contains(&before.repository) {
+                    lost.push(before);
+                }
+            }
+        }
+    }
+    for before in lost.into_iter().chain(replaced) {
+        // By ID when we know it, since another account may have the login now
+        let is_outside_collaborator = match before.user_id {
+            Some(id) => outside_collaborators.values().any(|c| c.id == id),
+            None => outside_collaborators.contains_key(&before.login),
+        };
+        let change = if before.is_invitation() {
+            Change::InvitationClosed
+        } else if is_outside_collaborator {
+            Change::Revoked
+        } else {
+            Change::LeftOrg
+        };
+        changes.push(AccessChange::new(change, Some(before), None));
+    }
+    changes.sort_by(|a, b| {
+        (&a.login, &a.repository, a.change).cmp(&(&b.login, &b.repository, b.change))
+    });
+    changes
+}
+
+/// The JSON version of the diff
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    org: &'a str,
+    /// RFC 3339, in UTC
+    generated_at: String,
+    /// The CSV of the previous run, as given with `--previous`
+    previous: &'a str,
+    changes: &'a [AccessChange],
+}
+
+/// Write the diff to `path`, replacing the file if it exists: as JSON if its name ends with `.json`, as CSV otherwise.
+/// In the CSV, each of the `metadata_columns` has a `previous <column>` column
+pub fn write(
+    path: &str,
+    org: &str,
+    previous: &str,
+    metadata_columns: &[&str],
+    changes: &[AccessChange],
+) -> Result<(), Error> {
+    let write_error = |e: &dyn Display| {
+        Error::Config(format!("Could not write the access changes to {path}: {e}"))
+    };
+    let file = std::fs::File::create(path).map_err(|e| write_error(&e))?;
+    if path.ends_with(".json") {
+        let document = Document {
+            schema_version: SCHEMA_VERSION,
+            org,
+            generated_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            previous,
+            changes,
+        };
+        serde_json::to_writer_pretty(file, &document).map_err(|e| write_error(&e))
+    } else {
+        // Written by hand, so that there is a header even when nothing changed
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(file);
+        let header = [
+            "change",
+            "login",
+            "repository",
+            "user_id",
+            "repository_id",
+            "previous_access",
+            "access",
+            "previous_status",
+            "previous_approval_date",
+            "previous_approver",
+            "previous_expiry_date",
+        ]
+        .map(str::to_string)
+        .into_iter()
+        .chain(
+            metadata_columns
+                .iter()
+                .map(|column| format!("previous {column}")),
+        );
+        writer.write_record(header).map_err(|e| write_error(&e))?;
+        for change in changes {
+            let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+            let id = |value: Option<u64>| value.map_or(String::new(), |id| id.to_string());
+            let record = [
+                change.change.to_string(),
+                change.login.clone(),
+                change.repository.clone(),
+                id(change.user_id),
+                id(change.repository_id),
+                optional(&change.previous_access),
+                optional(&change.access),
+                optional(&change.previous_status),
+                optional(&change.previous_approval_date),
+                optional(&change.previous_approver),
+                optional(&change.previous_expiry_date),
+            ]
+            .into_iter()
+            .chain(metadata_columns.iter().map(|column| {
+                change
+                    .previous_metadata
+                    .get(*column)
+                    .cloned()
+                    .unwrap_or_default()
+            }));
+            writer.write_record(record).map_err(|e| write_error(&e))?;
+        }
+        writer.flush().map_err(|e| write_error(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::external_collaborator::invitation::INVITED;
+
+    fn access(login: &str, repository: &str, access: &str) -> ExternalCollaboratorPermission {
+        ExternalCollaboratorPermission::new(
+            (login.to_string(), None),
+            (repository.to_string(), None),
+            access.to_string(),
+        )
+    }
+
+    fn invitation(login: &str, repository: &str, access: &str) -> ExternalCollaboratorPermission {
+        ExternalCollaboratorPermission {
+            status: Some(INVITED.to_string()),
+            ..self::access(login, repository, access)
+        }
+    }
+
+    fn permissions(
+        permissions: impl IntoIterator<Item = ExternalCollaboratorPermission>,
+    ) -> ExternalCollaboratorPermissions {
+        permissions
+            .into_iter()
+            .map(|p| ((p.login.clone(), p.repository.clone()), p))
+            .collect()
+    }
+
+    /// The outside collaborators, with their IDs
+    fn outside_collaborators(
+        collaborators: &[(&str, u64)],
+    ) -> HashMap<String, OutsideCollaborator> {
+        collaborators
+            .iter()
+            .map(|&(login, id)| {
+                let login = login.to_string();
+                (login.clone(), OutsideCollaborator { login, id })
+            })
+            .collect()
+    }
+
+    /// The changes as `(change, login, repository)`, with eve as the only outside collaborator left
+    fn changes(
+        previous: &ExternalCollaboratorPermissions,
+        current: &ExternalCollaboratorPermissions,
+        skipped_repos: &[&str],
+        skipped_invitations: &[&str],
+    ) -> Vec<(Change, String, String)> {
+        let skipped = |repos: &[&str]| repos.iter().map(|r| r.to_string()).collect();
+        diff(
+            previous,
+            &[],
+            current,
+            &outside_collaborators(&[("eve", 9)]),
+            &skipped(skipped_repos),
+            &skipped(skipped_invitations),
+        )
+        .into_iter()
+        .map(|c| (c.change, c.login, c.repository))
+        .collect()
+    }
+
+    fn change(change: Change, login: &str, repository: &str) -> (Change, String, String) {
+        (change, login.to_string(), repository.to_string())
+    }
+
+    #[test]
+    fn access_changes_are_classified() {
+        let previous = permissions([
+            access("eve", "alpha", "pull"),
+            access("eve", "beta", "admin"),
+            access("eve", "delta", "push"),
+            access("eve", "epsilon", "pull"),
+            access("eve", "gamma", "pull"),
+            access("mallory", "alpha", "pull"),
+        ]);
+        let current = permissions([
+            access("eve", "alpha", "push"),
+            access("eve", "beta", "maintain"),
+            access("eve", "delta", "custom-role"),
+            access("eve", "gamma", "pull"),
+            access("eve", "zeta", "triage"),
+        ]);
+
+        assert_eq!(
+            changes(&previous, &current, &[], &[]),
+            [
+                change(Change::Escalated, "eve", "alpha"),
+                change(Change::Downgraded, "eve", "beta"),
+                change(Change::Changed, "eve", "delta"),
+                change(Change::Revoked, "eve", "epsilon"),
+                change(Change::Granted, "eve", "zeta"),
+                change(Change::LeftOrg, "mallory", "alpha"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invitation_changes_are_classified() {
+        let previous = permissions([
+            invitation("eve", "alpha", "push"),
+            invitation("zed", "beta", "pull"),
+            invitation("zed", "gamma", "pull"),
+        ]);
+        let current = permissions([
+            // Accepted
+            access("eve", "alpha", "push"),
+            // Still pending
+            invitation("zed", "gamma", "pull"),
+            invitation("zed", "delta", "push"),
+        ]);
+
+        assert_eq!(
+            changes(&previous, &current, &[], &[]),
+            [
+                change(Change::Granted, "eve", "alpha"),
+                change(Change::InvitationClosed, "zed", "beta"),
+                change(Change::Invited, "zed", "delta"),
+            ]
+        );
+    }
+
+    #[test]
+    fn another_account_with_the_same_login_is_granted_access() {
+        let previous = permissions([ExternalCollaboratorPermission {
+            status: Some("Approved".to_string()),
+            ..ExternalCollaboratorPermission::new(
+                ("eve".to_string(), Some(9)),
+                ("alpha".to_string(), Some(100)),
+                "push".to_string(),
+            )
+        }]);
+        let current = permissions([ExternalCollaboratorPermission::new(
+            ("eve".to_string(), Some(55)),
+            ("alpha".to_string(), Some(100)),
+            "push".to_string(),
+        )]);
+
+        let changes = diff(
+            &previous,
+            &[],
+            &current,
+            &outside_collaborators(&[("eve", 55)]),
+            &HashSet::new(),
+            &HashSet::new(),
+        );
+        assert_eq!(changes.len(), 2);
+        // The new account has no previous access, and its approval is not the one of the old account
+        assert_eq!(changes[0].change, Change::Granted);
+        assert_eq!(changes[0].user_id, Some(55));
+        assert_eq!(changes[0].previous_access, None);
+        assert_eq!(changes[0].previous_status, None);
+        // The old account is not an outside collaborator anymore
+        assert_eq!(changes[1].change, Change::LeftOrg);
+        assert_eq!(changes[1].user_id, Some(9));
+        assert_eq!(changes[1].previous_access.as_deref(), Some("push"));
+        assert_eq!(changes[1].previous_status.as_deref(), Some("Approved"));
+        assert_eq!(changes[1].access, None);
+    }
+
+    #[test]
+    fn replaced_rows_are_lost_access() {
+        let replaced = [
+            ExternalCollaboratorPermission::new(
+                ("mallory".to_string(), Some(55)),
+                ("alpha".to_string(), Some(100)),
+                "pull".to_string(),
+            ),
+            // The repository was deleted, and another one has its name
+            ExternalCollaboratorPermission::new(
+                ("eve".to_string(), Some(9)),
+                ("beta".to_string(), Some(101)),
+                "push".to_string(),
+            ),
+        ];
+        let changes = diff(
+            &ExternalCollaboratorPermissions::new(),
+            &replaced,
+            &ExternalCollaboratorPermissions::new(),
+            &outside_collaborators(&[("eve", 9), ("mallory", 56)]),
+            // Even when the repository was skipped, since the row is not the one of this repository
+            &HashSet::from(["beta".to_string()]),
+            &HashSet::new(),
+        );
+
+        assert_eq!(
+            changes
+                .into_iter()
+                .map(|c| (c.change, c.login, c.repository))
+                .collect::<Vec<_>>(),
+            [
+                change(Change::Revoked, "eve", "beta"),
+                change(Change::LeftOrg, "mallory", "alpha"),
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_repositories_are_not_reported_as_revoked_or_closed() {
+        let previous = permissions([
+            access("eve", "alpha", "push"),
+            access("eve", "beta", "push"),
+            invitation("zed", "alpha", "pull"),
+            invitation("zed", "beta", "pull"),
+        ]);
+        let current = ExternalCollaboratorPermissions::new();
+
+        // The collaborators of alpha and the invitations of beta could not be fetched
+        assert_eq!(
+            changes(&previous, &current, &["alpha"], &["beta"]),
+            [
+                change(Change::Revoked, "eve", "beta"),
+                change(Change::InvitationClosed, "zed", "alpha"),
+            ]
+        );
+    }
+}
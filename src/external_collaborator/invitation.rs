@@ -0,0 +1,107 @@
+//! Repository invitations that were not accepted yet: each one is access that an outsider gets as soon as they accept it
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    finding::{self, Target},
+    make_paginated_github_request, Bootstrap, Error, Finding,
+};
+
+/// The status of the rows of the CSV that are invitations rather than access
+pub const INVITED: &str = "Invited";
+
+#[derive(Debug, serde::Deserialize)]
+struct User {
+    login: String,
+    id: u64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RepositoryInvitation {
+    /// `None` if the account of the invitee was deleted
+    invitee: Option<User>,
+    /// `None` if the account of the inviter was deleted
+    inviter: Option<User>,
+    /// `read`, `write`, `triage`, `maintain` or `admin`
+    permissions: String,
+    created_at: String,
+    /// GitHub invitations expire after 7 days, but stay listed until they are deleted
+    #[serde(default)]
+    expired: bool,
+}
+
+impl RepositoryInvitation {
+    pub fn invitee(&self) -> Option<&str> {
+        self.invitee.as_ref().map(|u| u.login.as_str())
+    }
+
+    pub fn invitee_id(&self) -> Option<u64> {
+        self.invitee.as_ref().map(|u| u.id)
+    }
+
+    /// The permission the invitee gets, with the names of the collaborators API (e.g., `push` for `write`)
+    pub fn access(&self) -> String {
+        match self.permissions.as_str() {
+            "read" => "pull".to_string(),
+            "write" => "push".to_string(),
+            permission => permission.to_string(),
+        }
+    }
+
+    /// How many days ago the invitation was sent. `None` if GitHub gave us a date we can't read
+    fn age_days(&self, now: DateTime<Utc>) -> Option<i64> {
+        DateTime::parse_from_rfc3339(&self.created_at)
+            .ok()
+            .map(|created_at| (now - created_at.with_timezone(&Utc)).num_days())
+    }
+
+    /// The finding for this invitation to `repo`, which must have an invitee
+    pub fn finding(&self, repo: &str, now: DateTime<Utc>) -> Finding {
+        let invitee = self.invitee().unwrap_or_default();
+        let inviter = self.inviter.as_ref().map_or("", |u| u.login.as_str());
+        let age = self
+            .age_days(now)
+            .map_or(String::new(), |days| days.to_string());
+        let mut message = format!(
+            "{invitee} was invited to {repo} with {} access by {} {} days ago",
+            self.access(),
+            if inviter.is_empty() {
+                "a deleted user"
+            } else {
+                inviter
+            },
+            if age.is_empty() { "?" } else { &age },
+        );
+        if self.expired {
+            message.push_str(", and the invitation expired");
+        }
+        Finding::new(
+            &finding::EC_PENDING_INVITATION,
+            Target::RepositoryUser {
+                repo: repo.to_string(),
+                login: invitee.to_string(),
+            },
+            message,
+        )
+        .with("access", self.access())
+        .with(
+            "user_id",
+            self.invitee_id().map_or(String::new(), |id| id.to_string()),
+        )
+        .with("inviter", inviter)
+        .with("created_at", &self.created_at)
+        .with("age_days", age)
+        .with("expired", self.expired)
+    }
+}
+
+/// Get the pending invitations of a repository
+pub fn fetch(bootstrap: &Bootstrap, repo: &str) -> Result<Vec<RepositoryInvitation>, Error> {
+    make_paginated_github_request(
+        bootstrap,
+        100,
+        &format!("/repos/{}/{}/invitations", &bootstrap.org, repo),
+        3,
+        None,
+    )
+}
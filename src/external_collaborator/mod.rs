@@ -0,0 +1,1198 @@
+mod approval;
+mod diff;
+mod invitation;
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+use colored::Colorize;
+use log::{error, info, warn};
+
+use self::{diff::Change, invitation::INVITED};
+use crate::{
+    audit::Audit,
+    config::Config,
+    finding::{self, Target},
+    for_each_repo_collaborators, make_paginated_github_request_with_index,
+    members::get_indexed_org_members,
+    output,
+    pool::{for_each_ordered, progress_bar},
+    preflight::{AuditKind, PreflightOptions},
+    Bootstrap, Error, Finding, Findings, GitHubIndex, Repository, Skipped,
+};
+
+/// Indexed by (login, repository). We use a sorted map so that the CSV we generate is stable across runs.
+/// The rows of a previous run are indexed by the current names, see [`follow_renames`]
+pub type ExternalCollaboratorPermissions =
+    BTreeMap<(String, String), ExternalCollaboratorPermission>;
+
+#[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq, Clone)]
+pub struct OutsideCollaborator {
+    login: String,
+    id: u64,
+}
+
+impl GitHubIndex for OutsideCollaborator {
+    fn index(&self) -> String {
+        self.login.clone()
+    }
+}
+
+const LOGIN: &str = "GitHub User";
+const REPOSITORY: &str = "Repo";
+const ACCESS: &str = "Access";
+const STATUS: &str = "Status";
+const APPROVAL_DATE: &str = "Approval Date";
+const APPROVER: &str = "Approver";
+const EXPIRY_DATE: &str = "Expiry Date";
+const USER_ID: &str = "GitHub User ID";
+const REPOSITORY_ID: &str = "Repo ID";
+
+/// The columns of the CSV that the audit reads or fills in. The other ones are metadata
+pub const BUILT_IN_COLUMNS: [&str; 9] = [
+    LOGIN,
+    REPOSITORY,
+    ACCESS,
+    STATUS,
+    APPROVAL_DATE,
+    APPROVER,
+    EXPIRY_DATE,
+    USER_ID,
+    REPOSITORY_ID,
+];
+
+/// The metadata columns of the CSV, if `metadata_columns` is not configured
+pub const DEFAULT_METADATA_COLUMNS: [&str; 2] = ["JIRA Ticket", "Quorum Proposal"];
+
+/// The keys of the evidence of an `EC004` finding that are not metadata columns
+pub const ACCESS_EVIDENCE: [&str; 7] = [
+    "access",
+    "status",
+    "approval_date",
+    "approver",
+    "expiry_date",
+    "user_id",
+    "repository_id",
+];
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ExternalCollaboratorPermission {
+    login: String,
+    repository: String,
+    access: String,
+    /// GitHub's numeric IDs of the user and the repository, to recognize them after a rename.
+    /// `None` in CSVs written before they existed, until the row is matched by name
+    user_id: Option<u64>,
+    repository_id: Option<u64>,
+    status: Option<String>,
+    /// When the access was approved, as YYYY-MM-DD. Optional in CSVs written before it existed
+    approval_date: Option<String>,
+    /// Who approved the access
+    approver: Option<String>,
+    /// When the approval lapses, as YYYY-MM-DD. The access must be re-certified by then
+    expiry_date: Option<String>,
+    /// The values of the other columns, e.g., a ticket, by column name. Kept verbatim from the previous run
+    metadata: BTreeMap<String, String>,
+}
+
+impl ExternalCollaboratorPermission {
+    fn new(
+        (login, user_id): (String, Option<u64>),
+        (repository, repository_id): (String, Option<u64>),
+        access: String,
+    ) -> Self {
+        Self {
+            login,
+            repository,
+            access,
+            user_id,
+            repository_id,
+            status: None,
+            approval_date: None,
+            approver: None,
+            expiry_date: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Whether the row is a pending invitation rather than access
+    fn is_invitation(&self) -> bool {
+        self.status.as_deref() == Some(INVITED)
+    }
+
+    /// Whether the row can be about the user and repository with these IDs. An unknown ID matches any other,
+    /// but a known one that differs means another account took the login (or another repository the name)
+    fn has_ids(&self, user_id: Option<u64>, repository_id: Option<u64>) -> bool {
+        let same = |a: Option<u64>, b: Option<u64>| a.zip(b).is_none_or(|(a, b)| a == b);
+        same(self.user_id, user_id) && same(self.repository_id, repository_id)
+    }
+
+    /// The same row with the current IDs, which fills them in for rows of CSVs written before they existed
+    fn with_ids(&self, user_id: Option<u64>, repository_id: Option<u64>) -> Self {
+        Self {
+            user_id: user_id.or(self.user_id),
+            repository_id: repository_id.or(self.repository_id),
+            ..self.clone()
+        }
+    }
+
+    /// The value of `column` in the CSV, empty if it is not filled in
+    fn column(&self, column: &str) -> String {
+        let optional = |value: &Option<String>| value.clone().unwrap_or_default();
+        let id = |value: Option<u64>| value.map_or(String::new(), |id| id.to_string());
+        match column {
+            LOGIN => self.login.clone(),
+            REPOSITORY => self.repository.clone(),
+            ACCESS => self.access.clone(),
+            STATUS => optional(&self.status),
+            APPROVAL_DATE => optional(&self.approval_date),
+            APPROVER => optional(&self.approver),
+            EXPIRY_DATE => optional(&self.expiry_date),
+            USER_ID => id(self.user_id),
+            REPOSITORY_ID => id(self.repository_id),
+            _ => self.metadata.get(column).cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// The CSV of a previous run
+struct PreviousRun {
+    permissions: ExternalCollaboratorPermissions,
+    /// The metadata columns it has, in its order
+    metadata_columns: Vec<String>,
+}
+
+/// Read the CSV of a previous run. Columns are found by name, so they can be in any order, and the ones
+/// we don't know are kept as metadata. Only the user, repository and access columns are required
+fn parse_previous_run_csv(file: impl AsRef<Path>) -> Result<PreviousRun, Error> {
+    let mut reader = csv::Reader::from_path(file)
+        .map_err(|e| Error::Config(format!("Couldn't read the previous run CSV: {e}")))?;
+    let headers = reader
+        .headers()
+        .map_err(|e| Error::Config(format!("Couldn't read the previous run CSV: {e}")))?
+        .clone();
+    let position = |column: &str| headers.iter().position(|header| header == column);
+    let required = |column: &str| {
+        position(column).ok_or_else(|| {
+            Error::Config(format!(
+                "The previous run CSV has no {column} column, so I can't use it"
+            ))
+        })
+    };
+    let (login, repository, access) = (required(LOGIN)?, required(REPOSITORY)?, required(ACCESS)?);
+    let metadata_columns: Vec<String> = headers
+        .iter()
+        .filter(|header| !BUILT_IN_COLUMNS.contains(header))
+        .map(str::to_string)
+        .collect();
+
+    let mut permissions = ExternalCollaboratorPermissions::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("{}: {:?}", "Couldn't parse a row".red(), e);
+                continue;
+            }
+        };
+        let value = |index: usize| record.get(index).unwrap_or_default().to_string();
+        // Empty cells are the same as missing columns
+        let optional = |column: &str| {
+            position(column)
+                .map(value)
+                .filter(|value| !value.is_empty())
+        };
+        let id = |column: &str| {
+            let value = optional(column)?;
+            value
+                .trim()
+                .parse::<u64>()
+                .inspect_err(|_| {
+                    warn!(
+                        "{} {column} [{value}], so I'll match the row by name",
+                        "Ignoring the invalid".yellow()
+                    )
+                })
+                .ok()
+        };
+        let mut permission = ExternalCollaboratorPermission::new(
+            (value(login), id(USER_ID)),
+            (value(repository), id(REPOSITORY_ID)),
+            value(access),
+        );
+        if permission.login.is_empty() || permission.repository.is_empty() {
+            warn!("{}: {:?}", "Couldn't parse a row".red(), record);
+            continue;
+        }
+        permission.status = optional(STATUS);
+        permission.approval_date = optional(APPROVAL_DATE);
+        permission.approver = optional(APPROVER);
+        permission.expiry_date = optional(EXPIRY_DATE);
+        for (header, value) in headers.iter().zip(record.iter()) {
+            if !BUILT_IN_COLUMNS.contains(&header) && !value.is_empty() {
+                permission
+                    .metadata
+                    .insert(header.to_string(), value.to_string());
+            }
+        }
+        permissions.insert(
+            (permission.login.clone(), permission.repository.clone()),
+            permission,
+        );
+    }
+    Ok(PreviousRun {
+        permissions,
+        metadata_columns,
+    })
+}
+
+/// The columns of the CSV we generate: the configured metadata columns go after the status, and the ones of the
+/// previous run that are not configured go last, so that nothing is lost
+fn csv_columns(configured: &[String], previous: &[String]) -> Vec<String> {
+    let mut columns: Vec<String> = [LOGIN, REPOSITORY, ACCESS, STATUS]
+        .into_iter()
+        .map(str::to_string)
+        .chain(configured.iter().cloned())
+        .chain([APPROVAL_DATE, APPROVER, EXPIRY_DATE, USER_ID, REPOSITORY_ID].map(str::to_string))
+        .collect();
+    for column in previous {
+        if !columns.contains(column) {
+            columns.push(column.clone());
+        }
+    }
+    columns
+}
+
+/// The current name of what was called `name` in the previous run, found by `id` in `current` (whose names are
+/// in `current_names`), and whether it was renamed. `None` if the ID is gone and another one has the name now
+fn current_name<'a>(
+    name: &'a str,
+    id: Option<u64>,
+    current: &HashMap<u64, &'a str>,
+    current_names: &HashSet<&str>,
+) -> Option<(&'a str, bool)> {
+    match id.map(|id| current.get(&id)) {
+        Some(Some(current)) => Some((current, *current != name)),
+        Some(None) if current_names.contains(name) => None,
+        _ => Some((name, false)),
+    }
+}
+
+/// Index the rows of a previous run by the current login and repository name, found by ID in `users` and
+/// `repositories`, so that renaming an account or a repository doesn't look like new access.
+/// Rows without IDs, or whose IDs we don't see anymore, keep their names.
+/// Rows are matched by ID first: the ones whose login or repository name is now used by another account or
+/// repository, and the ones that end up with the same login and repository as a better match, are returned apart,
+/// as access that nobody has anymore
+fn follow_renames(
+    previous: ExternalCollaboratorPermissions,
+    users: &HashMap<u64, &str>,
+    repositories: &HashMap<u64, &str>,
+) -> (
+    ExternalCollaboratorPermissions,
+    Vec<ExternalCollaboratorPermission>,
+) {
+    let logins = users.values().copied().collect();
+    let repository_names = repositories.values().copied().collect();
+    let mut replaced = vec![];
+    let mut matches = vec![];
+    for permission in previous.into_values() {
+        let user = current_name(&permission.login, permission.user_id, users, &logins);
+        let repository = current_name(
+            &permission.repository,
+            permission.repository_id,
+            repositories,
+            &repository_names,
+        );
+        let Some(((login, user_renamed), (repository, repository_renamed))) = user.zip(repository)
+        else {
+            replaced.push(permission);
+            continue;
+        };
+        // Rows that are still what they were go first, then the renamed ones, then the ones matched by name only
+        let known_ids = permission.user_id.is_some() && permission.repository_id.is_some();
+        let rank = (user_renamed || repository_renamed, !known_ids);
+        let key = (login.to_string(), repository.to_string());
+        matches.push((rank, key, permission));
+    }
+    matches.sort_by_key(|(rank, _, _)| *rank);
+
+    let mut renames = BTreeSet::new();
+    let mut permissions = ExternalCollaboratorPermissions::new();
+    for (_, key, mut permission) in matches {
+        if permissions.contains_key(&key) {
+            replaced.push(permission);
+            continue;
+        }
+        for (name, current) in [
+            (&mut permission.login, &key.0),
+            (&mut permission.repository, &key.1),
+        ] {
+            if name != current {
+                renames.insert((name.clone(), current.clone()));
+                *name = current.clone();
+            }
+        }
+        permissions.insert(key, permission);
+    }
+    for (before, after) in renames {
+        info!(
+            "{} {before} {} {after}",
+            "Matched by ID:".green(),
+            "is now".green()
+        );
+    }
+    for permission in &replaced {
+        info!(
+            "{} {} on {} {}",
+            "The previous row of".yellow(),
+            permission.login,
+            permission.repository,
+            "doesn't match any access by ID (another account or repository has the name now, or it's a duplicate), so it's lost access".yellow()
+        );
+    }
+    (permissions, replaced)
+}
+
+/// Index the rows of a previous run that have both IDs by them
+fn index_by_ids(
+    previous: &ExternalCollaboratorPermissions,
+) -> HashMap<(u64, u64), &ExternalCollaboratorPermission> {
+    previous
+        .values()
+        .filter_map(|p| Some(((p.user_id?, p.repository_id?), p)))
+        .collect()
+}
+
+/// The row of the previous run for the access of a user to a repository, each given by name and maybe ID.
+/// Rows are found by IDs when both are known, and by login and repository name otherwise,
+/// but a row with other IDs than the access is never its previous row
+fn find_previous<'a>(
+    previous: &'a ExternalCollaboratorPermissions,
+    by_ids: &HashMap<(u64, u64), &'a ExternalCollaboratorPermission>,
+    (login, user_id): (&str, Option<u64>),
+    (repository, repository_id): (&str, Option<u64>),
+) -> Option<&'a ExternalCollaboratorPermission> {
+    user_id
+        .zip(repository_id)
+        .and_then(|ids| by_ids.get(&ids).copied())
+        .or_else(|| {
+            previous
+                .get(&(login.to_string(), repository.to_string()))
+                .filter(|p| p.has_ids(user_id, repository_id))
+        })
+}
+
+/// Copy the rows of the previous run that we could not look at, unchanged, so that their approvals and metadata
+/// are not lost: the access to the `skipped_repos`, and the invitations to the `skipped_invitations` repositories
+fn carry_over_skipped(
+    previous: &ExternalCollaboratorPermissions,
+    skipped_repos: &HashSet<String>,
+    skipped_invitations: &HashSet<String>,
+    ec_permissions: &mut ExternalCollaboratorPermissions,
+) {
+    for (key, permission) in previous {
+        let skipped = if permission.is_invitation() {
+            skipped_invitations
+        } else {
+            skipped_repos
+        };
+        if skipped.contains(&permission.repository) {
+            ec_permissions
+                .entry(key.clone())
+                .or_insert_with(|| permission.clone());
+        }
+    }
+}
+
+fn generate_csv(ec_permissions: ExternalCollaboratorPermissions, columns: &[String]) -> String {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(columns).unwrap();
+    for (_, permission) in ec_permissions {
+        writer
+            .write_record(columns.iter().map(|column| permission.column(column)))
+            .unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+/// Outside collaborators and their access to each repository
+#[derive(Default)]
+pub struct ExternalCollaboratorAudit {
+    /// The CSV of the previous run, to keep approvals and detect access changes
+    pub previous: Option<String>,
+    /// Write what changed since the previous run to this file, as CSV or JSON
+    pub diff: Option<String>,
+    /// Also write the CSV to this file, which is required when the data is not written as text
+    pub csv: Option<String>,
+    /// The metadata columns of the CSV, e.g., a ticket or an owner. If `None`, [`DEFAULT_METADATA_COLUMNS`]
+    pub metadata_columns: Option<Vec<String>>,
+    /// Also list the pending invitations of non-members to each repository
+    pub include_invitations: bool,
+}
+
+impl Audit for ExternalCollaboratorAudit {
+    fn name(&self) -> &'static str {
+        "ec"
+    }
+
+    fn description(&self) -> &'static str {
+        "Outside collaborators and their access to each repository"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        if self.include_invitations {
+            &["members: read", "metadata: read", "administration: read"]
+        } else {
+            &["members: read", "metadata: read"]
+        }
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::ExternalCollaborators
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            invitations: self.include_invitations,
+            ..Default::default()
+        }
+    }
+
+    fn apply_config(&mut self, config: &mut Config) {
+        self.previous = self.previous.take().or(config.ec.previous.take());
+        self.diff = self.diff.take().or(config.ec.diff.take());
+        self.csv = self.csv.take().or(config.ec.csv.take());
+        self.metadata_columns = self
+            .metadata_columns
+            .take()
+            .or(config.ec.metadata_columns.take());
+        self.include_invitations |= config.ec.include_invitations;
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        let metadata_columns = self.metadata_columns.clone().unwrap_or_else(|| {
+            DEFAULT_METADATA_COLUMNS
+                .into_iter()
+                .map(str::to_string)
+                .collect()
+        });
+        run_audit(
+            bootstrap,
+            self.previous.clone(),
+            self.diff.clone(),
+            self.csv.clone(),
+            &metadata_columns,
+            self.include_invitations,
+        )
+    }
+}
+
+pub fn run_audit(
+    bootstrap: &Bootstrap,
+    previous_csv: Option<String>,
+    diff_path: Option<String>,
+    csv_path: Option<String>,
+    metadata_columns: &[String],
+    include_invitations: bool,
+) -> Result<Findings, Error> {
+    info!("{}", "GitHub External Collaborator Audit".white().bold());
+
+    if diff_path.is_some() && previous_csv.is_none() {
+        return Err(Error::Config(
+            "The access changes are relative to a previous run: --diff needs --previous"
+                .to_string(),
+        ));
+    }
+    // The CSV is the previous run of the next one, so it can't be dropped like the other lines of data
+    if csv_path.is_none() && !output::writes_lines() {
+        return Err(Error::Config(
+            "With --format or --report, the CSV of the ec audit is not written to the output: \
+             write it to a file with --csv (or csv in the [ec] section of the configuration file)"
+                .to_string(),
+        ));
+    }
+    // Without a previous run, all access is new, so we don't report it
+    let has_previous_run = previous_csv.is_some();
+    let previous_run = match &previous_csv {
+        None => {
+            info!(
+                "{}",
+                "I don't see any previous CSV file so I'm going to assume this is the first run."
+                    .yellow()
+            );
+            PreviousRun {
+                permissions: ExternalCollaboratorPermissions::new(),
+                metadata_columns: vec![],
+            }
+        }
+        Some(previous_csv) => {
+            info!(
+                "{}",
+                "I see a path so I'm going to assume it's a CSV with the output from a previous run."
+                    .yellow()
+            );
+            parse_previous_run_csv(previous_csv)?
+        }
+    };
+    let mut previous_ec_permissions = previous_run.permissions;
+    // We can't tell what happened to invitations we don't look at
+    if !include_invitations {
+        let count = previous_ec_permissions.len();
+        previous_ec_permissions.retain(|_, permission| !permission.is_invitation());
+        let ignored = count - previous_ec_permissions.len();
+        if ignored > 0 {
+            info!(
+                "{} {ignored} {}",
+                "I'm ignoring the".yellow(),
+                "invitations of the previous run, since --include-invitations is not given"
+                    .yellow()
+            );
+        }
+    }
+    let columns = csv_columns(metadata_columns, &previous_run.metadata_columns);
+    // The configured ones, then the ones only found in the previous run
+    let metadata_columns: Vec<&str> = columns
+        .iter()
+        .map(String::as_str)
+        .filter(|column| !BUILT_IN_COLUMNS.contains(column))
+        .collect();
+
+    info!(
+        "{} {}",
+        "I've got this many people from previous runs:".green(),
+        previous_ec_permissions.len()
+    );
+
+    info!(
+        "{}",
+        "I'm going to fetch all external collaborators from the org".yellow(),
+    );
+
+    let outside_collaborators: HashMap<String, OutsideCollaborator> =
+        make_paginated_github_request_with_index(
+            bootstrap,
+            100,
+            &format!("/orgs/{}/outside_collaborators", &bootstrap.org),
+            3,
+            None,
+        )
+        .inspect_err(|_| error!("{}", "I couldn't fetch the outside collaborators".red()))?;
+
+    info!(
+        "{} {}",
+        "Success! I found: ".green(),
+        outside_collaborators.len()
+    );
+
+    info!(
+        "{}",
+        "Alright! Now I need to fetch all repositories so I can check for their access.".yellow()
+    );
+
+    let repositories: Vec<Repository> = bootstrap.fetch_all_repositories(75, false)?;
+    let mut skipped = Skipped::default();
+
+    // Invitations are fetched before we look at the previous run, so that renamed invitees are recognized by ID
+    let mut invitations = vec![];
+    let mut skipped_invitations = HashSet::new();
+    if include_invitations {
+        info!(
+            "{}",
+            "Now the pending invitations of each repository, to find the non-members who were invited"
+                .yellow()
+        );
+        let members = get_indexed_org_members(bootstrap)?;
+        let repository_names: Vec<(String, Option<u64>)> = repositories
+            .iter()
+            .map(|r| (r.name.clone(), r.id))
+            .collect();
+        let pb = progress_bar(repository_names.len());
+        for_each_ordered(
+            repository_names,
+            bootstrap.concurrency,
+            &pb,
+            |(repo, _)| invitation::fetch(bootstrap, repo),
+            |(repo, repo_id), result| match result {
+                Ok(repo_invitations) => invitations.extend(
+                    repo_invitations
+                        .into_iter()
+                        .filter(|i| {
+                            i.invitee()
+                                .is_some_and(|login| !members.contains_key(login))
+                        })
+                        .map(|i| (repo.clone(), repo_id, i)),
+                ),
+                Err(e) => {
+                    pb.suspend(|| skipped.record(format!("{repo} (invitations)"), e));
+                    skipped_invitations.insert(repo);
+                }
+            },
+        );
+        pb.finish_and_clear();
+        info!(
+            "{}: {} pending invitations of non-members",
+            "I'm done and I found".green(),
+            invitations.len()
+        );
+    }
+
+    let users: HashMap<u64, &str> = outside_collaborators
+        .values()
+        .map(|c| (c.id, c.login.as_str()))
+        .chain(
+            invitations
+                .iter()
+                .filter_map(|(_, _, i)| Some((i.invitee_id()?, i.invitee()?))),
+        )
+        .collect();
+    let (previous_ec_permissions, replaced) = follow_renames(
+        previous_ec_permissions,
+        &users,
+        &repositories
+            .iter()
+            .filter_map(|r| Some((r.id?, r.name.as_str())))
+            .collect(),
+    );
+    let previous_by_ids = index_by_ids(&previous_ec_permissions);
+
+    info!("{}", "Finally the big one, I'm going to check each repository one by one to find external collaborators and their access. This is going to take a while...".yellow());
+
+    let mut never_seen_outside_collaborators = outside_collaborators.clone();
+
+    let mut ec_permissions = ExternalCollaboratorPermissions::new();
+    let mut skipped_repos = HashSet::new();
+    let mut findings = Findings::default();
+
+    let pb = progress_bar(repositories.len());
+    for_each_repo_collaborators(bootstrap, repositories, &pb, |repository, collaborators| {
+        let collaborators = match collaborators {
+            Ok(c) => c,
+            Err(e) => {
+                skipped_repos.insert(repository.name.clone());
+                pb.suspend(|| skipped.record(repository.name, e));
+                return;
+            }
+        };
+
+        for collaborator in collaborators {
+            if outside_collaborators.contains_key(&collaborator.login) {
+                // An accepted invitation is new access, and so is the access of another account that took the login
+                match find_previous(
+                    &previous_ec_permissions,
+                    &previous_by_ids,
+                    (&collaborator.login, collaborator.id),
+                    (&repository.name, repository.id),
+                )
+                .filter(|ec_perm| !ec_perm.is_invitation())
+                {
+                    Some(ec_perm) => {
+                        if ec_perm.access != collaborator.permissions.highest_perm() {
+                            pb.suspend(|| {
+                                    findings.record_as_warning(
+                                        Finding::new(
+                                            &finding::EC_ACCESS_CHANGED,
+                                            Target::RepositoryUser {
+                                                repo: repository.name.clone(),
+                                                login: collaborator.login.clone(),
+                                            },
+                                            format!(
+                                                "The access of {} to {} changed from {} to {}, so I cleared its approval",
+                                                collaborator.login,
+                                                repository.name,
+                                                ec_perm.access,
+                                                collaborator.permissions.highest_perm()
+                                            ),
+                                        )
+                                        .with("previous_access", &ec_perm.access)
+                                        .with("access", collaborator.permissions.highest_perm()),
+                                    );
+                                });
+                            ec_permissions.insert(
+                                (collaborator.login.clone(), repository.name.clone()),
+                                ExternalCollaboratorPermission::new(
+                                    (collaborator.login.clone(), collaborator.id),
+                                    (repository.name.clone(), repository.id),
+                                    collaborator.permissions.highest_perm(),
+                                ),
+                            );
+                        } else {
+                            ec_permissions.insert(
+                                (collaborator.login.clone(), repository.name.clone()),
+                                ec_perm.with_ids(collaborator.id, repository.id),
+                            );
+                        }
+                    }
+                    None => {
+                        if has_previous_run {
+                            pb.suspend(|| {
+                                findings.record_as_warning(
+                                    Finding::new(
+                                        &finding::EC_NEW_ACCESS,
+                                        Target::RepositoryUser {
+                                            repo: repository.name.clone(),
+                                            login: collaborator.login.clone(),
+                                        },
+                                        format!(
+                                            "{} got access to {} since the previous run",
+                                            collaborator.login, repository.name
+                                        ),
+                                    )
+                                    .with("access", collaborator.permissions.highest_perm()),
+                                );
+                            });
+                        }
+                        ec_permissions.insert(
+                            (collaborator.login.clone(), repository.name.clone()),
+                            ExternalCollaboratorPermission::new(
+                                (collaborator.login.clone(), collaborator.id),
+                                (repository.name.clone(), repository.id),
+                                collaborator.permissions.highest_perm(),
+                            ),
+                        );
+                    }
+                };
+                never_seen_outside_collaborators.remove(&collaborator.login);
+            }
+        }
+    });
+    pb.finish_and_clear();
+
+    info!(
+        "{}: {} different access permissions",
+        "I'm done and I found:".green(),
+        ec_permissions.len()
+    );
+
+    let now = chrono::Utc::now();
+    for (repo, repo_id, invitation) in invitations {
+        let Some(invitee) = invitation.invitee() else {
+            continue;
+        };
+        findings.record_as_warning(invitation.finding(&repo, now));
+        let key = (invitee.to_string(), repo.clone());
+        let invitee_id = invitation.invitee_id();
+        let permission = match find_previous(
+            &previous_ec_permissions,
+            &previous_by_ids,
+            (invitee, invitee_id),
+            (&repo, repo_id),
+        ) {
+            // Still pending for the same access, so we keep what was filled in
+            Some(previous)
+                if previous.is_invitation() && previous.access == invitation.access() =>
+            {
+                previous.with_ids(invitee_id, repo_id)
+            }
+            _ => ExternalCollaboratorPermission {
+                status: Some(INVITED.to_string()),
+                ..ExternalCollaboratorPermission::new(
+                    (invitee.to_string(), invitee_id),
+                    (repo.clone(), repo_id),
+                    invitation.access(),
+                )
+            },
+        };
+        // An outside collaborator can be invited to more access than they have: the CSV keeps the access
+        ec_permissions.entry(key).or_insert(permission);
+    }
+
+    carry_over_skipped(
+        &previous_ec_permissions,
+        &skipped_repos,
+        &skipped_invitations,
+        &mut ec_permissions,
+    );
+
+    let mut never_seen_outside_collaborators = never_seen_outside_collaborators
+        .into_keys()
+        .collect::<Vec<String>>();
+    never_seen_outside_collaborators.sort();
+    if !never_seen_outside_collaborators.is_empty() {
+        warn!(
+            "{} {:?}",
+            "These external collaborators have no access to any repository weirdly enough".yellow(),
+            never_seen_outside_collaborators
+        );
+    }
+    for login in never_seen_outside_collaborators {
+        findings.record_quietly(Finding::new(
+            &finding::EC_NO_ACCESS,
+            Target::User {
+                login: login.clone(),
+            },
+            format!("{login} has no access to any repository"),
+        ));
+    }
+
+    // On the first run, nothing is approved yet
+    if has_previous_run {
+        let today = chrono::Utc::now().date_naive();
+        for permission in ec_permissions.values().filter(|p| !p.is_invitation()) {
+            if let Some(finding) = approval::check(permission, today) {
+                findings.record_as_warning(finding);
+            }
+        }
+    }
+
+    if let Some(previous_csv) = &previous_csv {
+        let changes = diff::diff(
+            &previous_ec_permissions,
+            &replaced,
+            &ec_permissions,
+            &outside_collaborators,
+            &skipped_repos,
+            &skipped_invitations,
+        );
+        let count = |change: Change| changes.iter().filter(|c| c.change == change).count();
+        let mut summary = format!(
+            "{} granted, {} escalated, {} downgraded, {} changed, {} revoked, {} left the org",
+            count(Change::Granted),
+            count(Change::Escalated),
+            count(Change::Downgraded),
+            count(Change::Changed),
+            count(Change::Revoked),
+            count(Change::LeftOrg),
+        );
+        if include_invitations {
+            summary.push_str(&format!(
+                ", {} invited, {} invitations closed",
+                count(Change::Invited),
+                count(Change::InvitationClosed)
+            ));
+        }
+        info!("{} {summary}", "Since the previous run:".green());
+        // Granted and changed access are already reported as they are found
+        for change in changes
+            .iter()
+            .filter(|c| matches!(c.change, Change::Revoked | Change::LeftOrg))
+        {
+            let previous_access = change.previous_access.as_deref().unwrap_or_default();
+            findings.record_quietly(
+                Finding::new(
+                    &finding::EC_ACCESS_REVOKED,
+                    Target::RepositoryUser {
+                        repo: change.repository.clone(),
+                        login: change.login.clone(),
+                    },
+                    match change.change {
+                        Change::LeftOrg => format!(
+                            "{} lost {} access to {} since the previous run, as they are not an outside collaborator anymore",
+                            change.login, previous_access, change.repository
+                        ),
+                        _ => format!(
+                            "{} lost {} access to {} since the previous run",
+                            change.login, previous_access, change.repository
+                        ),
+                    },
+                )
+                .with("change", change.change)
+                .with("previous_access", previous_access)
+                .with(
+                    "user_id",
+                    change.user_id.map_or(String::new(), |id| id.to_string()),
+                ),
+            );
+        }
+        if let Some(diff_path) = &diff_path {
+            diff::write(
+                diff_path,
+                &bootstrap.org,
+                previous_csv,
+                &metadata_columns,
+                &changes,
+            )?;
+            info!("{} {}", "I wrote the access changes to".green(), diff_path);
+        }
+    }
+
+    // The content of the CSV, for the machine-readable output. Invitations are already reported
+    for permission in ec_permissions.values().filter(|p| !p.is_invitation()) {
+        let mut finding = Finding::new(
+            &finding::EC_ACCESS,
+            Target::RepositoryUser {
+                repo: permission.repository.clone(),
+                login: permission.login.clone(),
+            },
+            format!(
+                "{} has {} access to {}",
+                permission.login, permission.access, permission.repository
+            ),
+        )
+        .with("access", &permission.access)
+        .with("user_id", permission.column(USER_ID))
+        .with("repository_id", permission.column(REPOSITORY_ID))
+        .with("status", permission.status.as_deref().unwrap_or_default())
+        .with(
+            "approval_date",
+            permission.approval_date.as_deref().unwrap_or_default(),
+        )
+        .with(
+            "approver",
+            permission.approver.as_deref().unwrap_or_default(),
+        )
+        .with(
+            "expiry_date",
+            permission.expiry_date.as_deref().unwrap_or_default(),
+        );
+        // Under their column names, so that each one is there even when it's empty
+        for column in &metadata_columns {
+            finding = finding.with(column, permission.column(column));
+        }
+        findings.record_quietly(finding);
+    }
+
+    skipped.report();
+    findings.report();
+
+    let csv = generate_csv(ec_permissions, &columns);
+    if let Some(csv_path) = &csv_path {
+        std::fs::write(csv_path, &csv)
+            .map_err(|e| Error::Config(format!("Could not write the ec CSV to {csv_path}: {e}")))?;
+        info!("{} {}", "I wrote the CSV to".green(), csv_path);
+    }
+    output::line(csv.trim_end_matches('\n'));
+    Ok(findings.with_skipped(skipped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `content` to a CSV file of its own and parse it as a previous run
+    fn previous_run(name: &str, content: &str) -> PreviousRun {
+        let path =
+            std::env::temp_dir().join(format!("gh-ec-audit-{}-{name}.csv", std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        let previous = parse_previous_run_csv(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        previous
+    }
+
+    fn default_columns() -> Vec<String> {
+        DEFAULT_METADATA_COLUMNS.map(str::to_string).to_vec()
+    }
+
+    #[test]
+    fn skipped_repos_keep_their_rows() {
+        let csv = "\
+GitHub User,Repo,Access,Status,JIRA Ticket,Quorum Proposal,Approval Date,Approver,Expiry Date,GitHub User ID,Repo ID
+eve,alpha,push,Approved,SEC-1,Q-1,2026-01-02,alice,2027-01-01,9,100
+eve,beta,pull,Approved,SEC-2,,2026-01-02,bob,,9,101
+zed,beta,push,Invited,SEC-3,,,,,77,101
+";
+        let previous = previous_run("skipped", csv).permissions;
+        // alpha was looked at, and eve lost her access there. beta could not be looked at at all
+        let mut current = ExternalCollaboratorPermissions::new();
+        let skipped = HashSet::from(["beta".to_string()]);
+        carry_over_skipped(&previous, &skipped, &skipped, &mut current);
+
+        assert_eq!(current.len(), 2);
+        assert!(!current.contains_key(&("eve".to_string(), "alpha".to_string())));
+        let generated = generate_csv(current, &csv_columns(&default_columns(), &[]));
+        assert_eq!(
+            generated,
+            "\
+GitHub User,Repo,Access,Status,JIRA Ticket,Quorum Proposal,Approval Date,Approver,Expiry Date,GitHub User ID,Repo ID
+eve,beta,pull,Approved,SEC-2,,2026-01-02,bob,,9,101
+zed,beta,push,Invited,SEC-3,,,,,77,101
+"
+        );
+    }
+
+    #[test]
+    fn skipped_invitations_dont_keep_access_rows() {
+        let csv = "\
+GitHub User,Repo,Access,Status
+eve,beta,pull,Approved
+zed,beta,push,Invited
+";
+        let previous = previous_run("invitations", csv).permissions;
+        let mut current = ExternalCollaboratorPermissions::new();
+        let skipped_invitations = HashSet::from(["beta".to_string()]);
+        carry_over_skipped(
+            &previous,
+            &HashSet::new(),
+            &skipped_invitations,
+            &mut current,
+        );
+
+        assert_eq!(
+            current.keys().collect::<Vec<_>>(),
+            [&("zed".to_string(), "beta".to_string())]
+        );
+    }
+
+    #[test]
+    fn rows_seen_in_this_run_win_over_skipped_ones() {
+        let previous = previous_run("seen", "GitHub User,Repo,Access\neve,beta,pull\n").permissions;
+        let mut current = ExternalCollaboratorPermissions::new();
+        let key = ("eve".to_string(), "beta".to_string());
+        current.insert(
+            key.clone(),
+            ExternalCollaboratorPermission::new(
+                ("eve".to_string(), Some(9)),
+                ("beta".to_string(), Some(101)),
+                "admin".to_string(),
+            ),
+        );
+        carry_over_skipped(
+            &previous,
+            &HashSet::from(["beta".to_string()]),
+            &HashSet::new(),
+            &mut current,
+        );
+
+        assert_eq!(current[&key].access, "admin");
+    }
+
+    fn key(login: &str, repository: &str) -> (String, String) {
+        (login.to_string(), repository.to_string())
+    }
+
+    #[test]
+    fn ids_match_unless_both_are_known_and_differ() {
+        let permission = ExternalCollaboratorPermission::new(
+            ("eve".to_string(), Some(9)),
+            ("beta".to_string(), None),
+            "pull".to_string(),
+        );
+        assert!(permission.has_ids(Some(9), Some(101)));
+        assert!(permission.has_ids(None, None));
+        assert!(!permission.has_ids(Some(55), Some(101)));
+
+        // IDs we learn are kept, and IDs we don't know don't erase the ones we had
+        let permission = permission.with_ids(None, Some(101));
+        assert_eq!(permission.user_id, Some(9));
+        assert_eq!(permission.repository_id, Some(101));
+        assert!(!permission.has_ids(Some(9), Some(102)));
+    }
+
+    /// The login and repository of each row
+    fn keys<'a>(
+        permissions: impl IntoIterator<Item = &'a ExternalCollaboratorPermission>,
+    ) -> Vec<(String, String)> {
+        permissions
+            .into_iter()
+            .map(|p| key(&p.login, &p.repository))
+            .collect()
+    }
+
+    #[test]
+    fn renamed_users_and_repositories_are_followed_by_id() {
+        let csv = "\
+GitHub User,Repo,Access,Status,GitHub User ID,Repo ID
+eve-old,alpha-old,push,Approved,9,100
+zed-old,alpha,push,Invited,77,100
+gone,gamma,pull,Approved,,102
+";
+        let previous = previous_run("renames", csv).permissions;
+        let users = HashMap::from([(9, "eve"), (77, "zed")]);
+        let repositories = HashMap::from([(100, "alpha"), (101, "beta")]);
+        let (permissions, replaced) = follow_renames(previous, &users, &repositories);
+
+        assert_eq!(
+            permissions.keys().cloned().collect::<Vec<_>>(),
+            [
+                key("eve", "alpha"),
+                key("gone", "gamma"),
+                key("zed", "alpha")
+            ]
+        );
+        // The rows have their new names too
+        assert_eq!(
+            keys(permissions.values()),
+            permissions.keys().cloned().collect::<Vec<_>>()
+        );
+        let eve = &permissions[&key("eve", "alpha")];
+        assert_eq!(eve.status.as_deref(), Some("Approved"));
+        assert!(permissions[&key("zed", "alpha")].is_invitation());
+        assert!(replaced.is_empty());
+    }
+
+    #[test]
+    fn swapped_logins_are_followed_by_id() {
+        let csv = "\
+GitHub User,Repo,Access,Status,JIRA Ticket,GitHub User ID,Repo ID
+eve,alpha,push,Approved,SEC-1,9,100
+mallory,alpha,pull,Approved,SEC-2,55,100
+";
+        let previous = previous_run("swap", csv).permissions;
+        let users = HashMap::from([(9, "mallory"), (55, "eve")]);
+        let repositories = HashMap::from([(100, "alpha")]);
+        let (permissions, replaced) = follow_renames(previous, &users, &repositories);
+
+        assert!(replaced.is_empty());
+        let eve = &permissions[&key("eve", "alpha")];
+        assert_eq!((eve.user_id, eve.access.as_str()), (Some(55), "pull"));
+        assert_eq!(eve.column("JIRA Ticket"), "SEC-2");
+        let mallory = &permissions[&key("mallory", "alpha")];
+        assert_eq!(
+            (mallory.user_id, mallory.access.as_str()),
+            (Some(9), "push")
+        );
+        assert_eq!(mallory.column("JIRA Ticket"), "SEC-1");
+    }
+
+    #[test]
+    fn rows_whose_names_are_taken_are_replaced() {
+        let csv = "\
+GitHub User,Repo,Access,Status,GitHub User ID,Repo ID
+eve,alpha,push,Approved,9,100
+eve-old,alpha,pull,Approved,9,100
+mallory,alpha,pull,Approved,55,100
+trudy,alpha,pull,Approved,66,100
+trudy,beta,pull,Approved,66,101
+";
+        let previous = previous_run("replaced", csv).permissions;
+        // 55 is gone and 56 took the login mallory. 66 is gone too, but nobody took trudy.
+        // The beta repository was deleted, and another one was created with the same name
+        let users = HashMap::from([(9, "eve"), (56, "mallory")]);
+        let repositories = HashMap::from([(100, "alpha"), (102, "beta")]);
+        let (permissions, replaced) = follow_renames(previous, &users, &repositories);
+
+        assert_eq!(
+            permissions.keys().cloned().collect::<Vec<_>>(),
+            [key("eve", "alpha"), key("trudy", "alpha")]
+        );
+        // The row that was already right wins over the one that was renamed to it
+        assert_eq!(permissions[&key("eve", "alpha")].access, "push");
+        assert_eq!(
+            keys(&replaced),
+            [
+                key("mallory", "alpha"),
+                key("trudy", "beta"),
+                key("eve-old", "alpha")
+            ]
+        );
+    }
+
+    #[test]
+    fn previous_rows_are_found_by_ids() {
+        let csv = "\
+GitHub User,Repo,Access,GitHub User ID,Repo ID
+eve,alpha,push,9,100
+zed,alpha,pull,,
+";
+        let previous = previous_run("ids", csv).permissions;
+        let by_ids = index_by_ids(&previous);
+        let find = |login, user_id, repository, repository_id| {
+            find_previous(
+                &previous,
+                &by_ids,
+                (login, user_id),
+                (repository, repository_id),
+            )
+            .map(|p| p.access.as_str())
+        };
+
+        assert_eq!(find("eve", Some(9), "alpha", Some(100)), Some("push"));
+        assert_eq!(find("eve", None, "alpha", None), Some("push"));
+        // Another account with the same login
+        assert_eq!(find("eve", Some(55), "alpha", Some(100)), None);
+        // Rows without IDs are found by name
+        assert_eq!(find("zed", Some(77), "alpha", Some(100)), Some("pull"));
+    }
+}
@@ -0,0 +1,591 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use colored::{ColoredString, Colorize};
+use log::{info, warn};
+
+use crate::{output, Skipped};
+
+/// How bad a finding is, from least to most severe. `Info` is for what an audit lists without it being a problem
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    fn label(&self) -> ColoredString {
+        let label = format!("[{}]", self.name().to_uppercase());
+        match self {
+            Severity::Info => label.white(),
+            Severity::Low => label.cyan(),
+            Severity::Medium => label.yellow(),
+            Severity::High => label.red(),
+            Severity::Critical => label.red().bold(),
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Something an audit can report on. The ID is stable across releases, so it can be used
+/// to filter or suppress findings in other tools
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub id: &'static str,
+    /// A short, kebab-case name for the rule
+    pub name: &'static str,
+    pub severity: Severity,
+    /// What the rule looks for
+    pub description: &'static str,
+    /// What to do about a finding
+    pub remediation: &'static str,
+}
+
+pub static EC_ACCESS_CHANGED: Rule = Rule {
+    id: "EC001",
+    name: "external-collaborator-access-changed",
+    severity: Severity::Medium,
+    description: "An external collaborator's access to a repository changed since the previous run, so its approval was cleared",
+    remediation: "Review the new access level and record a new approval in the CSV, or reduce the access",
+};
+
+pub static EC_NEW_ACCESS: Rule = Rule {
+    id: "EC002",
+    name: "external-collaborator-new-access",
+    severity: Severity::Medium,
+    description: "An external collaborator has access to a repository that was not in the previous run",
+    remediation: "Review the access and record its approval in the CSV, or remove the collaborator from the repository",
+};
+
+pub static EC_NO_ACCESS: Rule = Rule {
+    id: "EC003",
+    name: "external-collaborator-without-access",
+    severity: Severity::Low,
+    description: "An outside collaborator of the organization has no access to any repository",
+    remediation: "Remove the outside collaborator from the organization",
+};
+
+pub static EC_ACCESS: Rule = Rule {
+    id: "EC004",
+    name: "external-collaborator-access",
+    severity: Severity::Info,
+    description: "An external collaborator has access to a repository. The evidence has the approval columns of the CSV",
+    remediation: "Keep the approval of the access up to date in the CSV",
+};
+
+pub static EC_ACCESS_REVOKED: Rule = Rule {
+    id: "EC005",
+    name: "external-collaborator-access-revoked",
+    severity: Severity::Info,
+    description: "An external collaborator lost access to a repository since the previous run",
+    remediation: "Check that the access was meant to be removed, and archive its approval",
+};
+
+pub static EC_APPROVAL_MISSING: Rule = Rule {
+    id: "EC006",
+    name: "external-collaborator-approval-missing",
+    severity: Severity::Medium,
+    description: "The access of an external collaborator to a repository has no approval in the CSV",
+    remediation: "Get the access approved and fill in the Status, Approval Date, Approver and Expiry Date columns, or remove it",
+};
+
+pub static EC_APPROVAL_UNRECOGNIZED: Rule = Rule {
+    id: "EC007",
+    name: "external-collaborator-approval-unrecognized",
+    severity: Severity::Low,
+    description: "The approval of an external collaborator's access is not Approved, has no approval date or approver, or has a date that is not YYYY-MM-DD",
+    remediation: "Set the Status column to Approved once the access is approved, fill in the Approval Date and Approver columns, and write dates as YYYY-MM-DD",
+};
+
+pub static EC_APPROVAL_EXPIRED: Rule = Rule {
+    id: "EC008",
+    name: "external-collaborator-approval-expired",
+    severity: Severity::Medium,
+    description: "The approval of an external collaborator's access has expired",
+    remediation:
+        "Re-certify the access and update the Approval Date and Expiry Date columns, or remove it",
+};
+
+pub static EC_PENDING_INVITATION: Rule = Rule {
+    id: "EC009",
+    name: "external-collaborator-pending-invitation",
+    severity: Severity::Low,
+    description: "A non-member was invited to a repository and has not accepted yet",
+    remediation: "Approve the access before the invitation is accepted, or cancel the invitation",
+};
+
+pub static DEPLOY_KEY_NON_MEMBER: Rule = Rule {
+    id: "DK001",
+    name: "deploy-key-added-by-non-member",
+    severity: Severity::High,
+    description: "A deploy key was added by someone who is not a member of the organization",
+    remediation: "Check whether the key is still needed and delete it, or replace it with one owned by a member",
+};
+
+pub static DEPLOY_KEY_MEMBER: Rule = Rule {
+    id: "DK002",
+    name: "deploy-key-added-by-member",
+    severity: Severity::Info,
+    description:
+        "A deploy key was added by a member of the organization (only reported with --all)",
+    remediation:
+        "Check whether the key is still needed, and that it is read-only unless it must push",
+};
+
+pub static ADMIN_TEAM: Rule = Rule {
+    id: "ADM001",
+    name: "repository-admin-team",
+    severity: Severity::Low,
+    description: "A team has admin access to a repository",
+    remediation:
+        "Check that every member of the team needs admin access, or lower the team's permission",
+};
+
+pub static ADMIN_USER: Rule = Rule {
+    id: "ADM002",
+    name: "repository-admin-user",
+    severity: Severity::Medium,
+    description: "A user has admin access to a repository without being an organization admin or getting it from a team",
+    remediation: "Grant admin access through a team, or lower the user's permission",
+};
+
+pub static BRANCH_UNPROTECTED: Rule = Rule {
+    id: "BPR001",
+    name: "default-branch-unprotected",
+    severity: Severity::High,
+    description:
+        "The default branch of a repository has neither a branch protection rule nor a ruleset",
+    remediation: "Protect the default branch with a ruleset or a branch protection rule",
+};
+
+pub static BRANCH_PROTECTION: Rule = Rule {
+    id: "BPR002",
+    name: "default-branch-protection",
+    severity: Severity::Info,
+    description: "How the default branch of a repository is protected",
+    remediation: "Check that the protection matches your policy",
+};
+
+pub static EMPTY_TEAM: Rule = Rule {
+    id: "TEAM001",
+    name: "empty-team",
+    severity: Severity::Low,
+    description: "A team has no members",
+    remediation: "Delete the team, or add the members it is meant to have",
+};
+
+pub static TEAM_REPOSITORY_ACCESS: Rule = Rule {
+    id: "TEAM002",
+    name: "team-repository-access",
+    severity: Severity::Info,
+    description: "A team can access a repository (listed by team-perms)",
+    remediation: "Check that the team needs this permission",
+};
+
+pub static ORG_MEMBER: Rule = Rule {
+    id: "MEM001",
+    name: "org-member",
+    severity: Severity::Info,
+    description: "A member of the organization (listed by members)",
+    remediation: "Check that the user still needs to be a member",
+};
+
+pub static CODEOWNERS_USER_NOT_IN_ORG: Rule = Rule {
+    id: "CO001",
+    name: "codeowners-user-not-in-org",
+    severity: Severity::High,
+    description: "A CODEOWNERS file mentions a user who is not a member of the organization",
+    remediation: "Remove the user from the CODEOWNERS file, or replace them with a team",
+};
+
+pub static CODEOWNERS_TEAM_NOT_IN_ORG: Rule = Rule {
+    id: "CO002",
+    name: "codeowners-team-not-in-org",
+    severity: Severity::High,
+    description: "A CODEOWNERS file mentions a team that does not exist in the organization",
+    remediation: "Fix the team name in the CODEOWNERS file, or remove it",
+};
+
+pub static CODEOWNERS_EMPTY_TEAM: Rule = Rule {
+    id: "CO003",
+    name: "codeowners-empty-team",
+    severity: Severity::Medium,
+    description:
+        "A CODEOWNERS file mentions a team with no members, so nobody can approve as that owner",
+    remediation: "Add members to the team, or replace it in the CODEOWNERS file",
+};
+
+pub static CODEOWNERS_GITHUB_ERRORS: Rule = Rule {
+    id: "CO004",
+    name: "codeowners-github-errors",
+    severity: Severity::Medium,
+    description: "GitHub reports errors in a CODEOWNERS file (only checked with --also-gh-api)",
+    remediation: "Fix the errors GitHub shows when viewing the CODEOWNERS file",
+};
+
+pub static CODEOWNERS_TEAM_MENTION: Rule = Rule {
+    id: "CO005",
+    name: "codeowners-team-mention",
+    severity: Severity::Info,
+    description: "A CODEOWNERS file mentions a team (listed by team-in-codeowners)",
+    remediation: "Update the CODEOWNERS file before renaming or removing the team",
+};
+
+pub static COMPLIANCE_PR_ONE_APPROVAL: Rule = Rule {
+    id: "CMP001",
+    name: "pr-one-approval",
+    severity: Severity::High,
+    description: "Pull requests to the default branch don't require at least one approving review",
+    remediation: "Require at least one approving review in a ruleset or branch protection rule",
+};
+
+pub static COMPLIANCE_PR_DISMISS_STALE: Rule = Rule {
+    id: "CMP002",
+    name: "pr-dismiss-stale",
+    severity: Severity::Medium,
+    description: "Approvals are not dismissed when new commits are pushed to a pull request",
+    remediation: "Enable dismissal of stale reviews on the default branch",
+};
+
+pub static COMPLIANCE_PR_REQUIRE_CODE_OWNER: Rule = Rule {
+    id: "CMP003",
+    name: "pr-require-code-owner",
+    severity: Severity::Medium,
+    description:
+        "Pull requests to the default branch don't require an approval from the code owners",
+    remediation: "Require review from code owners on the default branch",
+};
+
+pub static COMPLIANCE_DISABLE_FORCE_PUSH: Rule = Rule {
+    id: "CMP004",
+    name: "disable-force-push",
+    severity: Severity::High,
+    description: "Force-pushes to the default branch are allowed",
+    remediation: "Block force-pushes on the default branch",
+};
+
+pub static COMPLIANCE_DISABLE_DELETION: Rule = Rule {
+    id: "CMP005",
+    name: "disable-deletion",
+    severity: Severity::Medium,
+    description: "The default branch can be deleted",
+    remediation: "Restrict deletions of the default branch",
+};
+
+pub static COMPLIANCE_REQUIRE_SIGNED_COMMITS: Rule = Rule {
+    id: "CMP006",
+    name: "require-signed-commits",
+    severity: Severity::Low,
+    description: "Commits to the default branch don't need to be signed",
+    remediation: "Require signed commits on the default branch",
+};
+
+pub static COMPLIANCE_REQUIRE_STATUS_CHECKS: Rule = Rule {
+    id: "CMP007",
+    name: "require-status-checks",
+    severity: Severity::Medium,
+    description: "No status checks are required to pass before merging into the default branch",
+    remediation: "Require the repository's CI checks to pass before merging",
+};
+
+pub static COMPLIANCE_CODEOWNERS_VALID: Rule = Rule {
+    id: "CMP008",
+    name: "codeowners-valid",
+    severity: Severity::Medium,
+    description: "The repository has no CODEOWNERS file, or GitHub reports errors in it",
+    remediation: "Add a CODEOWNERS file and fix the errors GitHub reports in it",
+};
+
+pub static COMPLIANCE_SCORE: Rule = Rule {
+    id: "CMP009",
+    name: "compliance-score",
+    severity: Severity::Info,
+    description: "The compliance score of a repository, with the result of every check as evidence",
+    remediation: "Fix the failing checks, which are reported separately",
+};
+
+pub static COMPLIANCE_SCORE_BELOW_MINIMUM: Rule = Rule {
+    id: "CMP010",
+    name: "compliance-score-below-minimum",
+    severity: Severity::High,
+    description: "The compliance score of a repository is below the minimum given with --min-score",
+    remediation: "Fix the failing checks until the score reaches the minimum",
+};
+
+/// Every rule, in the order they are documented
+pub static RULES: [&Rule; 33] = [
+    &EC_ACCESS_CHANGED,
+    &EC_NEW_ACCESS,
+    &EC_NO_ACCESS,
+    &EC_ACCESS,
+    &EC_ACCESS_REVOKED,
+    &EC_APPROVAL_MISSING,
+    &EC_APPROVAL_UNRECOGNIZED,
+    &EC_APPROVAL_EXPIRED,
+    &EC_PENDING_INVITATION,
+    &DEPLOY_KEY_NON_MEMBER,
+    &DEPLOY_KEY_MEMBER,
+    &ADMIN_TEAM,
+    &ADMIN_USER,
+    &BRANCH_UNPROTECTED,
+    &BRANCH_PROTECTION,
+    &EMPTY_TEAM,
+    &TEAM_REPOSITORY_ACCESS,
+    &ORG_MEMBER,
+    &CODEOWNERS_USER_NOT_IN_ORG,
+    &CODEOWNERS_TEAM_NOT_IN_ORG,
+    &CODEOWNERS_EMPTY_TEAM,
+    &CODEOWNERS_GITHUB_ERRORS,
+    &CODEOWNERS_TEAM_MENTION,
+    &COMPLIANCE_PR_ONE_APPROVAL,
+    &COMPLIANCE_PR_DISMISS_STALE,
+    &COMPLIANCE_PR_REQUIRE_CODE_OWNER,
+    &COMPLIANCE_DISABLE_FORCE_PUSH,
+    &COMPLIANCE_DISABLE_DELETION,
+    &COMPLIANCE_REQUIRE_SIGNED_COMMITS,
+    &COMPLIANCE_REQUIRE_STATUS_CHECKS,
+    &COMPLIANCE_CODEOWNERS_VALID,
+    &COMPLIANCE_SCORE,
+    &COMPLIANCE_SCORE_BELOW_MINIMUM,
+];
+
+/// What a finding is about
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Target {
+    Repository {
+        repo: String,
+    },
+    User {
+        login: String,
+    },
+    Team {
+        team: String,
+    },
+    /// A user's access to a repository
+    RepositoryUser {
+        repo: String,
+        login: String,
+    },
+    /// A team's access to a repository
+    RepositoryTeam {
+        repo: String,
+        team: String,
+    },
+    DeployKey {
+        repo: String,
+        id: u64,
+        title: String,
+    },
+    /// A file in a repository, or a line of it. The URL points to the file on GitHub
+    File {
+        repo: String,
+        /// From the root of the repository, e.g., `.github/CODEOWNERS`
+        path: String,
+        url: String,
+        /// Starting from 1
+        #[serde(skip_serializing_if = "Option::is_none")]
+        line: Option<usize>,
+    },
+}
+
+impl Target {
+    /// The repository the target belongs to, if any
+    pub fn repo(&self) -> Option<&str> {
+        match self {
+            Target::Repository { repo }
+            | Target::RepositoryUser { repo, .. }
+            | Target::RepositoryTeam { repo, .. }
+            | Target::DeployKey { repo, .. }
+            | Target::File { repo, .. } => Some(repo),
+            Target::User { .. } | Target::Team { .. } => None,
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Repository { repo } => write!(f, "{repo}"),
+            Target::User { login } => write!(f, "user {login}"),
+            Target::Team { team } => write!(f, "team {team}"),
+            Target::RepositoryUser { repo, login } => write!(f, "user {login} on {repo}"),
+            Target::RepositoryTeam { repo, team } => write!(f, "team {team} on {repo}"),
+            Target::DeployKey { repo, title, .. } => write!(f, "deploy key {title} on {repo}"),
+            Target::File {
+                url,
+                line: Some(line),
+                ..
+            } => write!(f, "{url}#L{line}"),
+            Target::File { url, .. } => write!(f, "{url}"),
+        }
+    }
+}
+
+/// Something an audit found. All audits report their results in this shape,
+/// so they can be filtered and exported the same way
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub rule: &'static Rule,
+    pub target: Target,
+    /// A human-readable sentence describing this occurrence
+    pub message: String,
+    /// The facts that led to the finding, e.g., who added a deploy key
+    pub evidence: BTreeMap<String, String>,
+}
+
+impl Finding {
+    pub fn new(rule: &'static Rule, target: Target, message: impl Into<String>) -> Self {
+        Self {
+            rule,
+            target,
+            message: message.into(),
+            evidence: BTreeMap::new(),
+        }
+    }
+
+    /// Add a piece of evidence
+    pub fn with(mut self, key: &str, value: impl ToString) -> Self {
+        self.evidence.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn rule_id(&self) -> &'static str {
+        self.rule.id
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.rule.severity
+    }
+
+    pub fn remediation(&self) -> &'static str {
+        self.rule.remediation
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.severity().label(),
+            self.rule.id.white().dimmed(),
+            self.message
+        )
+    }
+}
+
+/// Collects the findings of an audit, in the order they were found,
+/// and the targets the audit had to skip
+#[derive(Default)]
+pub struct Findings {
+    entries: Vec<Finding>,
+    skipped: Skipped,
+}
+
+impl Findings {
+    /// Record a finding, and write it right away with the data
+    pub fn record(&mut self, finding: Finding) {
+        output::line(&finding);
+        self.entries.push(finding);
+    }
+
+    /// Record a finding, and log it as a warning instead of writing it with the data,
+    /// for audits whose data has another format (e.g., the external collaborators CSV)
+    pub fn record_as_warning(&mut self, finding: Finding) {
+        warn!("{finding}");
+        self.entries.push(finding);
+    }
+
+    /// Record a finding without printing it, for audits that present their results in another way
+    pub fn record_quietly(&mut self, finding: Finding) {
+        self.entries.push(finding);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Finding> {
+        self.entries.iter()
+    }
+
+    /// Keep track of the targets the audit skipped, so that they can be exported with the findings
+    pub fn with_skipped(mut self, skipped: Skipped) -> Self {
+        self.skipped = skipped;
+        self
+    }
+
+    pub fn skipped(&self) -> &Skipped {
+        &self.skipped
+    }
+
+    /// Add the findings of another audit after ours
+    pub fn extend(&mut self, other: Findings) {
+        self.entries.extend(other.entries);
+        self.skipped.extend(other.skipped);
+    }
+
+    pub fn into_vec(self) -> Vec<Finding> {
+        self.entries
+    }
+
+    /// Print how many findings we have for each severity, most severe first
+    pub fn report(&self) {
+        if self.entries.is_empty() {
+            info!("{}", "No findings.".green());
+            return;
+        }
+        let mut counts = BTreeMap::new();
+        for finding in &self.entries {
+            *counts.entry(finding.severity()).or_insert(0) += 1;
+        }
+        let counts = counts
+            .iter()
+            .rev()
+            .map(|(severity, count)| format!("{count} {severity}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        info!(
+            "{} {} {} {}",
+            "I found".yellow(),
+            self.entries.len().to_string().white(),
+            "findings:".yellow(),
+            counts.white()
+        );
+    }
+}
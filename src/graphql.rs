@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    codeowners::CO_LOCATIONS, get_repo_collaborators, send_rate_limited, with_retries, Bootstrap,
+    Collaborator, Error, Permissions, Repository,
+};
+
+/// How many repositories we ask about in a single GraphQL query.
+/// Each of them can bring up to 100 collaborators, so we stay well below GitHub's node limit.
+/// For more info, see https://docs.github.com/en/graphql/overview/rate-limits-and-node-limits-for-the-graphql-api
+pub const BATCH_SIZE: usize = 25;
+
+/// What we want to know about each repository, on top of its default branch and visibility
+#[derive(Clone, Copy, Default)]
+pub struct RepoFields {
+    pub collaborators: bool,
+    pub protection: bool,
+    pub codeowners: bool,
+}
+
+/// Everything we fetched about a repository with a single query
+pub struct RepoSnapshot {
+    pub default_branch: Option<String>,
+    /// Lowercase, like the REST API returns it (e.g., `private`)
+    pub visibility: Option<String>,
+    /// The branch protection rule that applies to the default branch, if any
+    pub protection: Result<Option<BranchProtection>, Error>,
+    pub collaborators: Result<Vec<Collaborator>, Error>,
+    /// The first location where GitHub would look for a CODEOWNERS file and found one
+    pub codeowners_path: Option<String>,
+}
+
+/// A branch protection rule, as returned by the GraphQL API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BranchProtection {
+    pub requires_approving_reviews: bool,
+    pub required_approving_review_count: Option<u32>,
+    pub dismisses_stale_reviews: bool,
+    pub requires_code_owner_reviews: bool,
+    pub allows_force_pushes: bool,
+    pub allows_deletions: bool,
+    pub requires_commit_signatures: bool,
+    pub requires_status_checks: bool,
+    #[serde(default)]
+    pub required_status_checks: Option<Vec<RequiredStatusCheck>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequiredStatusCheck {
+    pub context: String,
+}
+
+const BRANCH_PROTECTION_FIELDS: &str = "    branchProtectionRule {
+      requiresApprovingReviews
+      requiredApprovingReviewCount
+      dismissesStaleReviews
+      requiresCodeOwnerReviews
+      allowsForcePushes
+      allowsDeletions
+      requiresCommitSignatures
+      requiresStatusChecks
+      requiredStatusChecks { context }
+    }
+";
+
+const COLLABORATORS_FIELDS: &str =
+    "  collaborators(first: 100) { pageInfo { hasNextPage } edges { permission node { login databaseId } } }
+";
+
+#[derive(Deserialize)]
+struct GraphQlResponse {
+    data: Option<HashMap<String, Option<RepositoryNode>>>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlError {
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    message: String,
+    #[serde(default)]
+    path: Vec<serde_json::Value>,
+}
+
+impl GraphQlError {
+    fn to_error(&self, target: &str) -> Error {
+        let url = format!("/graphql ({target})");
+        match self.type_.as_deref() {
+            Some("NOT_FOUND") => Error::NotFound { url },
+            Some("FORBIDDEN") => Error::Forbidden {
+                url,
+                message: self.message.clone(),
+            },
+            Some("RATE_LIMITED") => Error::RateLimited { url },
+            _ => Error::GraphQl(format!("{target}: {}", self.message)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepositoryNode {
+    visibility: Option<String>,
+    default_branch_ref: Option<BranchRef>,
+    collaborators: Option<CollaboratorConnection>,
+    /// The CODEOWNERS locations, aliased `co0`, `co1`, ...
+    #[serde(flatten)]
+    objects: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BranchRef {
+    name: String,
+    branch_protection_rule: Option<BranchProtection>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollaboratorConnection {
+    page_info: PageInfo,
+    edges: Vec<CollaboratorEdge>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PageInfo {
+    has_next_page: bool,
+}
+
+#[derive(Deserialize)]
+struct CollaboratorEdge {
+    permission: String,
+    node: CollaboratorNode,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CollaboratorNode {
+    login: String,
+    database_id: Option<u64>,
+}
+
+/// Build a query that asks about `count` repositories at once. Each of them gets an alias
+/// (`r0`, `r1`, ...) and its name is passed in a variable, so we never put user input in the query.
+fn build_query(count: usize, fields: RepoFields) -> String {
+    let mut query = String::from("query($owner: String!");
+    for i in 0..count {
+        query.push_str(&format!(", $n{i}: String!"));
+    }
+    query.push_str(") {\n");
+    for i in 0..count {
+        query.push_str(&format!(
+            "  r{i}: repository(owner: $owner, name: $n{i}) {{ ...repo }}\n"
+        ));
+    }
+    query.push_str(
+        "}\n\nfragment repo on Repository {\n  visibility\n  defaultBranchRef {\n    name\n",
+    );
+    if fields.protection {
+        query.push_str(BRANCH_PROTECTION_FIELDS);
+    }
+    query.push_str("  }\n");
+    if fields.codeowners {
+        for (i, location) in CO_LOCATIONS.iter().enumerate() {
+            query.push_str(&format!(
+                "  co{i}: object(expression: \"HEAD:{location}\") {{ __typename }}\n"
+            ));
+        }
+    }
+    if fields.collaborators {
+        query.push_str(COLLABORATORS_FIELDS);
+    }
+    query.push_str("}\n");
+    query
+}
+
+/// Send a query to the GraphQL API
+fn post_graphql(
+    bootstrap: &Bootstrap,
+    query: &str,
+    variables: serde_json::Value,
+) -> Result<GraphQlResponse, Error> {
+    let endpoint = bootstrap.graphql_endpoint();
+    let payload = serde_json::json!({ "query": query, "variables": variables });
+
+    let response = with_retries(&endpoint, bootstrap.retries.unwrap_or(3), || {
+        let response = send_rate_limited(bootstrap, "/graphql", || {
+            bootstrap.client.post(&endpoint).json(&payload)
+        })?;
+        // GraphQL reports that the rate limit was hit with a 200 and an error in the body:
+        // we turn it into an error that will be retried, once the rate limiter lets us
+        if response.status.is_success() && response.body.contains("\"RATE_LIMITED\"") {
+            return Err(Error::RateLimited {
+                url: endpoint.clone(),
+            });
+        }
+        Ok(response)
+    })?;
+
+    Ok(serde_json::from_str::<GraphQlResponse>(&response.body)?)
+}
+
+/// Check that we can query the GraphQL API about the organization
+pub(crate) fn check_access(bootstrap: &Bootstrap) -> Result<(), Error> {
+    let response = post_graphql(
+        bootstrap,
+        "query($owner: String!) { organization(login: $owner) { login } }",
+        serde_json::json!({ "owner": bootstrap.org }),
+    )?;
+    match response.errors.first() {
+        Some(error) => Err(error.to_error(&bootstrap.org)),
+        None => Ok(()),
+    }
+}
+
+/// Fetch what we need to know about many repositories, `BATCH_SIZE` at a time.
+/// Returns one result per repository, in the same order as `names`: a repository we can't
+/// see doesn't prevent us from getting the others.
+pub fn fetch_repositories(
+    bootstrap: &Bootstrap,
+    names: &[String],
+    fields: RepoFields,
+) -> Vec<Result<RepoSnapshot, Error>> {
+    names
+        .chunks(BATCH_SIZE)
+        .flat_map(|chunk| fetch_batch(bootstrap, chunk, fields))
+        .collect()
+}
+
+fn fetch_batch(
+    bootstrap: &Bootstrap,
+    names: &[String],
+    fields: RepoFields,
+) -> Vec<Result<RepoSnapshot, Error>> {
+    let mut variables = serde_json::Map::new();
+    variables.insert("owner".to_string(), bootstrap.org.clone().into());
+    for (i, name) in names.iter().enumerate() {
+        variables.insert(format!("n{i}"), name.clone().into());
+    }
+
+    let response = match post_graphql(
+        bootstrap,
+        &build_query(names.len(), fields),
+        variables.into(),
+    ) {
+        Ok(response) => response,
+        // The whole query failed, so we have nothing for any of the repositories
+        Err(e) => return names.iter().map(|_| Err(e.clone())).collect(),
+    };
+
+    // Errors about a repository (or one of its fields) have a path starting with its alias.
+    // Errors without a path are about the query as a whole.
+    let mut errors: HashMap<String, Vec<&GraphQlError>> = HashMap::new();
+    let mut query_errors = vec![];
+    for error in &response.errors {
+        match error.path.first().and_then(|p| p.as_str()) {
+            Some(alias) => errors.entry(alias.to_string()).or_default().push(error),
+            None => query_errors.push(error),
+        }
+    }
+    let mut data = match (response.data, query_errors.first()) {
+        (Some(data), None) => data,
+        (_, Some(error)) => return names.iter().map(|name| Err(error.to_error(name))).collect(),
+        (None, None) => {
+            return names
+                .iter()
+                .map(|_| {
+                    Err(Error::Decode(
+                        "The GraphQL response has no data".to_string(),
+                    ))
+                })
+                .collect()
+        }
+    };
+
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let alias = format!("r{i}");
+            let errors = errors.remove(&alias).unwrap_or_default();
+            // Find the first error about a given field of the repository
+            let field_error = |field: &str| {
+                errors
+                    .iter()
+                    .find(|e| e.path.get(1).and_then(|p| p.as_str()) == Some(field))
+                    .map(|e| e.to_error(name))
+            };
+
+            let node = match data.remove(&alias).flatten() {
+                Some(node) => node,
+                None => {
+                    return Err(match errors.first() {
+                        Some(e) => e.to_error(name),
+                        None => Error::NotFound {
+                            url: format!("/graphql ({name})"),
+                        },
+                    })
+                }
+            };
+
+            let collaborators = match (field_error("collaborators"), node.collaborators) {
+                (Some(e), _) => Err(e),
+                // Too many collaborators for a single page: let the REST API paginate them
+                (None, Some(c)) if c.page_info.has_next_page => {
+                    get_repo_collaborators(bootstrap, name)
+                }
+                (None, Some(c)) => Ok(c
+                    .edges
+                    .into_iter()
+                    .map(|edge| Collaborator {
+                        login: edge.node.login,
+                        id: edge.node.database_id,
+                        permissions: Permissions::from_role(&edge.permission),
+                    })
+                    .collect()),
+                (None, None) => Ok(vec![]),
+            };
+
+            let (default_branch, rule) = match node.default_branch_ref {
+                Some(branch) => (Some(branch.name), branch.branch_protection_rule),
+                // Empty repositories have no default branch
+                None => (None, None),
+            };
+            let protection = match field_error("defaultBranchRef") {
+                Some(e) => Err(e),
+                None => Ok(rule),
+            };
+
+            let codeowners_path = CO_LOCATIONS
+                .iter()
+                .enumerate()
+                .find(|(i, _)| {
+                    node.objects
+                        .get(&format!("co{i}"))
+                        .is_some_and(|o| !o.is_null())
+                })
+                .map(|(_, location)| location.to_string());
+
+            Ok(RepoSnapshot {
+                default_branch,
+                visibility: node.visibility.map(|v| v.to_lowercase()),
+                protection,
+                collaborators,
+                codeowners_path,
+            })
+        })
+        .collect()
+}
+
+/// Fetch the collaborators of many repositories, `BATCH_SIZE` at a time.
+/// Returns one result per repository, in the same order as `repositories`.
+pub fn fetch_collaborators(
+    bootstrap: &Bootstrap,
+    repositories: &[Repository],
+) -> Vec<Result<Vec<Collaborator>, Error>> {
+    let names = repositories
+        .iter()
+        .map(|r| r.name.clone())
+        .collect::<Vec<String>>();
+    let fields = RepoFields {
+        collaborators: true,
+        ..Default::default()
+    };
+    fetch_repositories(bootstrap, &names, fields)
+        .into_iter()
+        .map(|snapshot| snapshot.and_then(|s| s.collaborators))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::mock_server::MockServer;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    fn collaborator(login: &str, id: u64, role: &str) -> Collaborator {
+        Collaborator {
+            login: login.to_string(),
+            id: Some(id),
+            permissions: Permissions::from_role(role),
+        }
+    }
+
+    fn repository(collaborators: Value) -> Value {
+        json!({
+            "visibility": "PRIVATE",
+            "defaultBranchRef": { "name": "main" },
+            "collaborators": collaborators,
+        })
+    }
+
+    #[test]
+    fn query_has_an_alias_and_a_variable_per_repository() {
+        let fields = RepoFields {
+            collaborators: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            build_query(2, fields),
+            "query($owner: String!, $n0: String!, $n1: String!) {
+  r0: repository(owner: $owner, name: $n0) { ...repo }
+  r1: repository(owner: $owner, name: $n1) { ...repo }
+}
+
+fragment repo on Repository {
+  visibility
+  defaultBranchRef {
+    name
+  }
+  collaborators(first: 100) { pageInfo { hasNextPage } edges { permission node { login databaseId } } }
+}
+"
+        );
+
+        let fields = RepoFields {
+            protection: true,
+            codeowners: true,
+            ..Default::default()
+        };
+        let query = build_query(1, fields);
+        assert!(query.contains("    branchProtectionRule {\n"));
+        assert!(query.contains("  co1: object(expression: \"HEAD:CODEOWNERS\") { __typename }\n"));
+        assert!(!query.contains("collaborators"));
+    }
+
+    #[test]
+    fn names_are_sent_as_variables() {
+        let server = MockServer::start(vec![(
+            200,
+            json!({ "data": { "r0": repository(Value::Null), "r1": repository(Value::Null) } })
+                .to_string(),
+        )]);
+        let bootstrap = Bootstrap::for_tests(&server.url);
+
+        let snapshots = fetch_batch(
+            &bootstrap,
+            &names(&["alpha", "\"beta\""]),
+            RepoFields::default(),
+        );
+        assert_eq!(snapshots.len(), 2);
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(requests[0].path, "/graphql");
+        let payload = serde_json::from_str::<Value>(&requests[0].body).unwrap();
+        assert_eq!(payload["query"], build_query(2, RepoFields::default()));
+        assert_eq!(
+            payload["variables"],
+            json!({ "owner": "my-org", "n0": "alpha", "n1": "\"beta\"" })
+        );
+    }
+
+    #[test]
+    fn errors_are_routed_to_their_repository_by_path() {
+        let server = MockServer::start(vec![(
+            200,
+            json!({
+                "data": {
+                    "r0": repository(json!({
+                        "pageInfo": { "hasNextPage": false },
+                        "edges": [{ "permission": "WRITE", "node": { "login": "eve", "databaseId": 9 } }],
+                    })),
+                    "r1": null,
+                    "r2": repository(Value::Null),
+                },
+                "errors": [
+                    { "type": "NOT_FOUND", "message": "Could not resolve to a Repository", "path": ["r1"] },
+                    { "type": "FORBIDDEN", "message": "Must have push access", "path": ["r2", "collaborators"] },
+                ],
+            })
+            .to_string(),
+        )]);
+        let bootstrap = Bootstrap::for_tests(&server.url);
+        let fields = RepoFields {
+            collaborators: true,
+            ..Default::default()
+        };
+
+        let snapshots = fetch_batch(&bootstrap, &names(&["alpha", "beta", "gamma"]), fields);
+        server.requests();
+
+        let alpha = snapshots[0].as_ref().unwrap();
+        assert_eq!(alpha.default_branch.as_deref(), Some("main"));
+        assert_eq!(alpha.visibility.as_deref(), Some("private"));
+        assert_eq!(
+            alpha.collaborators.as_ref().unwrap(),
+            &[collaborator("eve", 9, "WRITE")]
+        );
+        assert!(matches!(&snapshots[1], Err(Error::NotFound { url }) if url == "/graphql (beta)"));
+        // Only the field is lost, not the whole repository
+        let gamma = snapshots[2].as_ref().unwrap();
+        assert_eq!(gamma.default_branch.as_deref(), Some("main"));
+        assert!(matches!(
+            &gamma.collaborators,
+            Err(Error::Forbidden { url, message })
+                if url == "/graphql (gamma)" && message == "Must have push access"
+        ));
+    }
+
+    #[test]
+    fn errors_without_a_path_fail_every_repository() {
+        let server = MockServer::start(vec![(
+            200,
+            json!({ "errors": [{ "message": "Something went wrong" }] }).to_string(),
+        )]);
+        let bootstrap = Bootstrap::for_tests(&server.url);
+
+        let snapshots = fetch_batch(
+            &bootstrap,
+            &names(&["alpha", "beta"]),
+            RepoFields::default(),
+        );
+        server.requests();
+
+        assert!(snapshots
+            .iter()
+            .all(|s| matches!(s, Err(Error::GraphQl(m)) if m.ends_with("Something went wrong"))));
+    }
+
+    #[test]
+    fn more_than_100_collaborators_are_fetched_with_rest() {
+        let server = MockServer::start(vec![
+            (
+                200,
+                json!({ "data": { "r0": repository(json!({
+                    "pageInfo": { "hasNextPage": true },
+                    "edges": [{ "permission": "READ", "node": { "login": "first", "databaseId": 1 } }],
+                })) } })
+                .to_string(),
+            ),
+            (
+                200,
+                json!([
+                    { "login": "first", "id": 1, "permissions": { "pull": true, "triage": false, "push": false, "maintain": false, "admin": false } },
+                    { "login": "eve", "id": 9, "permissions": { "pull": true, "triage": true, "push": true, "maintain": false, "admin": false } },
+                ])
+                .to_string(),
+            ),
+        ]);
+        let bootstrap = Bootstrap::for_tests(&server.url);
+        let fields = RepoFields {
+            collaborators: true,
+            ..Default::default()
+        };
+
+        let snapshots = fetch_batch(&bootstrap, &names(&["alpha"]), fields);
+        let requests = server.requests();
+
+        assert_eq!(
+            snapshots[0]
+                .as_ref()
+                .unwrap()
+                .collaborators
+                .as_ref()
+                .unwrap(),
+            &[
+                collaborator("first", 1, "READ"),
+                collaborator("eve", 9, "WRITE")
+            ]
+        );
+        assert_eq!(requests.len(), 2);
+        assert!(requests[1]
+            .path
+            .starts_with("/repos/my-org/alpha/collaborators?"));
+    }
+}
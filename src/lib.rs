@@ -1,18 +1,37 @@
-use std::{
-    collections::{HashMap, HashSet},
-    fmt::Display,
-    thread::sleep,
-    time::Duration,
-};
+use std::{collections::HashMap, path::PathBuf, sync::OnceLock, thread::sleep, time::Duration};
 
-use colored::Colorize;
+use auth::{AppAuth, Auth};
+use cache::ResponseCache;
+use config::{Config, RepoFilter};
 
+use colored::Colorize;
+pub use error::{Error, Skipped};
+pub use finding::{Finding, Findings};
+use indicatif::ProgressBar;
+use log::{debug, error, info, trace, warn};
+use rate_limit::RateLimiter;
+use reqwest::{blocking::RequestBuilder, header::HeaderMap, StatusCode};
+
+pub mod audit;
+pub mod auth;
 pub mod bpr;
+pub mod cache;
 pub mod codeowners;
 pub mod compliance;
+pub mod config;
 pub mod deploy_key;
+pub mod error;
 pub mod external_collaborator;
+pub mod finding;
+pub mod graphql;
+pub mod logger;
 pub mod members;
+#[cfg(test)]
+mod mock_server;
+pub mod output;
+pub mod pool;
+pub mod preflight;
+pub mod rate_limit;
 pub mod teams;
 pub mod utils;
 
@@ -20,7 +39,7 @@ pub trait GitHubIndex {
     fn index(&self) -> String;
 }
 
-#[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, serde::Deserialize, Hash, Eq, PartialEq)]
 pub struct Permissions {
     pull: bool,
     triage: bool,
@@ -32,10 +51,12 @@ pub struct Permissions {
 #[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
 pub struct Collaborator {
     login: String,
+    /// The numeric ID of the user, which doesn't change when they rename their account
+    id: Option<u64>,
     permissions: Permissions,
 }
 
-#[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, serde::Deserialize, Hash, Eq, PartialEq)]
 pub struct Member {
     pub avatar_url: String,
     pub login: String,
@@ -48,6 +69,26 @@ impl GitHubIndex for Member {
 }
 
 impl Permissions {
+    /// Build the permissions from a role as the GraphQL API returns it (e.g., `WRITE`).
+    /// Like in the REST API, each role also grants the permissions of the ones below it.
+    fn from_role(role: &str) -> Self {
+        let rank = match role {
+            "ADMIN" => 5,
+            "MAINTAIN" => 4,
+            "WRITE" => 3,
+            "TRIAGE" => 2,
+            "READ" => 1,
+            _ => 0,
+        };
+        Self {
+            admin: rank >= 5,
+            maintain: rank >= 4,
+            push: rank >= 3,
+            triage: rank >= 2,
+            pull: rank >= 1,
+        }
+    }
+
     fn highest_perm(&self) -> String {
         if self.admin {
             return "admin".to_string();
@@ -68,33 +109,25 @@ impl Permissions {
     }
 }
 
-#[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
+/// The body GitHub sends back together with an error status
+#[derive(Debug, serde::Deserialize)]
 struct GitHubError {
     pub message: String,
-    pub documentation_url: String,
-    pub status: String,
-}
-
-impl Display for GitHubError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "GitHubError: {} {} {}",
-            self.message, self.documentation_url, self.status
-        )
-    }
 }
 
-#[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
+#[derive(Clone, Debug, serde::Deserialize, Hash, Eq, PartialEq)]
 pub struct Repository {
     pub name: String,
+    /// The numeric ID of the repository, which doesn't change when it is renamed.
+    /// `None` for repositories given by name
+    pub id: Option<u64>,
     pub private: bool,
     pub archived: bool,
     pub disabled: bool,
     pub permissions: Permissions,
 }
 
-#[derive(serde::Deserialize, Hash, Eq, PartialEq)]
+#[derive(Clone, serde::Deserialize, Hash, Eq, PartialEq)]
 pub struct Team {
     pub name: String,
     pub slug: String,
@@ -110,11 +143,11 @@ impl GitHubIndex for Team {
 impl Team {
     /// Return whether a team is empty, i.e., if the team has no members,
     /// including its sub-teams.
-    fn is_empty(&self, bootstrap: &Bootstrap) -> Result<bool, String> {
+    fn is_empty(&self, bootstrap: &Bootstrap) -> Result<bool, Error> {
         // NOTE - We don't make a paginated request on purpose: we only want
         // to see if a team is empty or not, and we don't need to fetch _all_ members.
         let members = make_github_request(
-            &bootstrap.token,
+            bootstrap,
             &format!("/orgs/{}/teams/{}/members", bootstrap.org, self.slug),
             3,
             None,
@@ -122,216 +155,321 @@ impl Team {
 
         match members.as_array() {
             Some(v) => Ok(v.is_empty()),
-            None => Err("The value returned by GitHub is not an array".to_string()),
+            None => Err(Error::Decode(
+                "The value returned by GitHub is not an array".to_string(),
+            )),
         }
     }
 }
 
-#[derive(Debug, serde::Deserialize, Hash, Eq, PartialEq)]
-#[serde(untagged)]
-enum GitHubResponse<T> {
-    Data(Vec<T>),
-    Error(GitHubError),
+/// A response received from the GitHub API
+struct GitHubHttpResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
 }
 
-fn make_github_request(
-    gh_token: &str,
-    url: &str,
-    retries: u8,
-    params: Option<&str>,
-) -> Result<serde_json::Value, String> {
-    let params = match params {
-        Some(params) => format!("?{params}"),
-        None => String::new(),
-    };
+/// Send a request to GitHub. Requests are paced according to GitHub's rate limits and,
+/// if GitHub rejects one because a rate limit was hit, we wait as long as needed and send it again.
+/// Args:
+/// * `path` - The API path of the request, used to find out which rate limit bucket it counts against
+/// * `build` - Build the request to send. It is called again every time the request has to be sent again
+fn send_rate_limited(
+    bootstrap: &Bootstrap,
+    path: &str,
+    build: impl Fn() -> RequestBuilder,
+) -> Result<GitHubHttpResponse, Error> {
+    let resource = rate_limit::resource_for_path(path);
+    let mut reauthenticated = false;
 
-    let mut tries = 0;
     loop {
-        tries += 1;
-        let response = reqwest::blocking::Client::new()
-            .get(&format!("https://api.github.com{url}{params}",))
+        bootstrap.rate_limiter.wait(resource);
+
+        let response = build()
             .header("User-Agent", "GitHub EC Audit")
-            .header("Accept", "application/vnd.github+json")
             .header("X-GitHub-Api-Version", "2022-11-28")
-            .header("Authorization", format!("Bearer {}", gh_token))
-            .send()
-            .map(|response| response.text());
-
-        // Handle communication issues with GitHub
-        let content = match response {
-            Ok(Ok(content)) => content,
-            Ok(Err(e)) => {
-                if tries >= retries {
-                    println!("{}", "Retries exhausted".red());
-                    return Err(e.to_string());
-                }
+            .header("Authorization", bootstrap.auth.authorization(bootstrap)?)
+            .send()?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.text()?;
+        debug!("{} {}", status.as_u16(), path);
+
+        // The installation token may have been revoked (or have expired earlier than we thought):
+        // we get a new one and try again, but only once
+        if status == StatusCode::UNAUTHORIZED && !reauthenticated && bootstrap.auth.invalidate() {
+            reauthenticated = true;
+            continue;
+        }
 
-                println!(
-                    "{}: {}",
-                    "Going to retry because couldn't read response from GitHub:".yellow(),
-                    e.to_string().red()
-                );
+        if let Some(wait) = bootstrap.rate_limiter.update(path, status, &headers, &body) {
+            info!(
+                "{} {} {}",
+                "GitHub rate limit hit: I am going to wait".yellow(),
+                wait.as_secs().to_string().white(),
+                "seconds and try again".yellow()
+            );
+            sleep(wait);
+            continue;
+        }
+
+        return Ok(GitHubHttpResponse {
+            status,
+            headers,
+            body,
+        });
+    }
+}
+
+/// Send a GET request to GitHub.
+/// If we have a cached response for the URL, we send a conditional request and, when GitHub
+/// answers 304, we return the cached response as if GitHub had sent it again.
+/// Args:
+/// * `url` - Either a path on the configured API (e.g., `/orgs/my-org/repos?page=2`) or an absolute URL
+fn send_github_request(bootstrap: &Bootstrap, url: &str) -> Result<GitHubHttpResponse, Error> {
+    let endpoint = bootstrap.api_endpoint(url);
+    let cached = bootstrap.cache.as_ref().and_then(|c| c.get(&endpoint));
+
+    let mut response = send_rate_limited(bootstrap, bootstrap.api_path(url), || {
+        let request = bootstrap
+            .client
+            .get(&endpoint)
+            .header("Accept", "application/vnd.github+json");
+        match &cached {
+            Some(cached) => request.header(reqwest::header::IF_NONE_MATCH, &cached.etag),
+            None => request,
+        }
+    })?;
+
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status, &cached) {
+        trace!("Not modified, I am using the cached response for {endpoint}");
+        // The entry is still current: store it again so that its TTL starts over
+        if let Some(cache) = &bootstrap.cache {
+            cache.put(
+                &endpoint,
+                &cached.etag,
+                cached.link.as_deref(),
+                &cached.body,
+            );
+        }
+        // GitHub doesn't necessarily repeat the `Link` header in a 304, but we need it to paginate
+        if let Some(link) = cached.link.as_ref().and_then(|l| l.parse().ok()) {
+            response.headers.insert(reqwest::header::LINK, link);
+        }
+        response.status = StatusCode::OK;
+        response.body = cached.body.clone();
+        return Ok(response);
+    }
+
+    if let (Some(cache), true) = (&bootstrap.cache, response.status.is_success()) {
+        if let Some(etag) = response
+            .headers
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+        {
+            trace!("I am caching the response for {endpoint}");
+            let link = response
+                .headers
+                .get(reqwest::header::LINK)
+                .and_then(|v| v.to_str().ok());
+            cache.put(&endpoint, etag, link, &response.body);
+        }
+    }
 
-                continue;
+    Ok(response)
+}
+
+/// Send a GET request to GitHub and turn any non-success status into an `Error`.
+/// Transient failures (network issues, 5xx) are retried up to `retries` times, while
+/// errors that would not go away (e.g., 404 or 403) are returned immediately.
+fn get_with_retries(
+    bootstrap: &Bootstrap,
+    url: &str,
+    retries: u8,
+) -> Result<GitHubHttpResponse, Error> {
+    with_retries(url, bootstrap.retries.unwrap_or(retries), || {
+        send_github_request(bootstrap, url)
+    })
+}
+
+/// Send a request with `send` until it succeeds, it fails for a reason that would not go away,
+/// or we tried `retries` times. Any non-success status is turned into an `Error`.
+fn with_retries(
+    url: &str,
+    retries: u8,
+    send: impl Fn() -> Result<GitHubHttpResponse, Error>,
+) -> Result<GitHubHttpResponse, Error> {
+    let mut tries = 0;
+    loop {
+        tries += 1;
+        let result = send().and_then(|response| {
+            if response.status.is_success() {
+                Ok(response)
+            } else {
+                let message = serde_json::from_str::<GitHubError>(&response.body)
+                    .map(|e| e.message)
+                    .unwrap_or_else(|_| response.body.clone());
+                Err(Error::from_status(url, response.status, message))
             }
-            Err(e) => {
+        });
+
+        match result {
+            Ok(response) => return Ok(response),
+            Err(e) if e.is_transient() => {
                 if tries >= retries {
-                    println!("{}", "Retries exhausted".red());
-                    return Err(e.to_string());
+                    error!("{}", "Retries exhausted".red());
+                    return Err(e);
                 }
 
-                println!(
+                info!(
                     "{}: {}",
-                    "Going to retry because couldn't make request to GitHub:".yellow(),
+                    "Going to retry because couldn't get a response from GitHub".yellow(),
                     e.to_string().red()
                 );
-
-                continue;
+                sleep(RETRY_DELAY * tries as u32);
             }
-        };
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn make_github_request(
+    bootstrap: &Bootstrap,
+    url: &str,
+    retries: u8,
+    params: Option<&str>,
+) -> Result<serde_json::Value, Error> {
+    let params = match params {
+        Some(params) => format!("?{params}"),
+        None => String::new(),
+    };
 
-        let value = serde_json::from_str::<serde_json::Value>(&content)
-            .map_err(|e| format!("Could not deserialize GitHub's response. Error: {e}"))?;
+    let response = get_with_retries(bootstrap, &format!("{url}{params}"), retries)?;
 
-        // break the loop and return the value we got
-        return Ok(value);
+    // Some endpoints legitimately answer with no content at all
+    if response.body.trim().is_empty() {
+        return Ok(serde_json::Value::Null);
     }
+
+    serde_json::from_str::<serde_json::Value>(&response.body).map_err(|e| {
+        Error::Decode(format!(
+            "Could not deserialize GitHub's response. Error: {e}"
+        ))
+    })
 }
 
+/// Fetch all the items of a paginated GitHub endpoint, in the order GitHub returns them.
+/// We follow the `Link: rel="next"` header, so this works for both page-based and
+/// cursor-based (e.g., the audit log) endpoints, and stops as soon as there is no next page.
+/// For more info, see https://docs.github.com/en/rest/using-the-rest-api/using-pagination-in-the-rest-api
 fn make_paginated_github_request<T>(
-    gh_token: &str,
+    bootstrap: &Bootstrap,
+    page_size: u8,
+    url: &str,
+    retries: u8,
+    params: Option<&str>,
+) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    paginate(bootstrap, page_size, url, retries, params, None)
+}
+
+/// Like `make_paginated_github_request`, but for endpoints that wrap each page in an object
+/// and return the items in one of its fields, e.g., the `items` of the search API.
+fn make_paginated_github_request_from_field<T>(
+    bootstrap: &Bootstrap,
+    page_size: u8,
+    url: &str,
+    retries: u8,
+    params: Option<&str>,
+    field: &str,
+) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    paginate(bootstrap, page_size, url, retries, params, Some(field))
+}
+
+fn paginate<T>(
+    bootstrap: &Bootstrap,
     page_size: u8,
     url: &str,
     retries: u8,
     params: Option<&str>,
-) -> Result<HashSet<T>, String>
+    field: Option<&str>,
+) -> Result<Vec<T>, Error>
 where
-    T: serde::de::DeserializeOwned + std::hash::Hash + std::cmp::Eq,
+    T: serde::de::DeserializeOwned,
 {
     let params = match params {
         Some(params) => format!("&{params}"),
         None => String::new(),
     };
+    let page_size = bootstrap.page_size.unwrap_or(page_size);
 
-    let mut page = 1;
-    let mut all_items = HashSet::new();
-    let mut tries = 0;
-    loop {
-        tries += 1;
-        let response = reqwest::blocking::Client::new()
-            .get(&format!(
-                "https://api.github.com{url}?per_page={page_size}&page={page}{params}",
-            ))
-            .header("User-Agent", "GitHub EC Audit")
-            .header("Accept", "application/vnd.github+json")
-            .header("X-GitHub-Api-Version", "2022-11-28")
-            .header("Authorization", format!("Bearer {}", gh_token))
-            .send()
-            .map(|response| response.text());
-
-        // Handle communication issues with GitHub
-        let content = match response {
-            Ok(Ok(content)) => content,
-            Ok(Err(e)) => {
-                if tries >= retries {
-                    println!("{}", "Retries exhausted".red());
-                    return Err(e.to_string());
-                }
-
-                println!(
-                    "{}: {}",
-                    "Going to retry because couldn't read response from GitHub:".yellow(),
-                    e.to_string().red()
-                );
+    // The first page is built by us, the following ones are given to us by GitHub
+    let mut page_url = Some(format!("{url}?per_page={page_size}{params}"));
+    let mut all_items = vec![];
+    while let Some(url) = page_url {
+        let response = get_with_retries(bootstrap, &url, retries)?;
+        all_items.extend(parse_page::<T>(&response.body, field)?);
 
-                continue;
-            }
-            Err(e) => {
-                if tries >= retries {
-                    println!("{}", "Retries exhausted".red());
-                    return Err(e.to_string());
-                }
-
-                println!(
-                    "{}: {}",
-                    "Going to retry because couldn't make request to GitHub:".yellow(),
-                    e.to_string().red()
-                );
-
-                continue;
-            }
-        };
-
-        // Handle GitHub errors
-        match serde_json::from_str::<GitHubResponse<T>>(content.as_str()) {
-            Ok(GitHubResponse::Data(data)) => {
-                // When we go past the end (an unneeded page), we'll get an empty response so we can break
-                if data.is_empty() {
-                    break;
-                }
-
-                // The page is full so we need to add all these users to our set and grab the next page
-                page += 1;
-                tries = 0;
-                all_items.extend(data);
-            }
-            Ok(GitHubResponse::Error(e)) => {
-                // GitHub threw an error and if it's a ratelimit we can wait and retry
-                if e.message.contains("API rate limit exceeded") {
-                    sleep(Duration::from_secs(60));
-                } else {
-                    // This doesn't look like the expected data or a ratelimit error
-
-                    // We're out of retries so we need to stop
-                    if tries >= retries {
-                        println!("{}", "Retries exhausted".red());
-                        return Err(e.to_string());
-                    }
+        // If GitHub doesn't give us a next page, we are done
+        page_url = next_page_url(&response.headers);
+    }
 
-                    // We have retries remaining so we'll try again
-                    println!(
-                        "{}: {}",
-                        "Going to retry because couldn't deserialize response from GitHub:"
-                            .yellow(),
-                        e.to_string().red()
-                    );
-                    tries += 1;
-                    println!("{}", content.yellow());
-                }
-            }
-            Err(e) => {
-                // This doesn't look like the expected data or an error
-                if tries >= retries {
-                    println!("{}", "Retries exhausted".red());
-                    return Err(e.to_string());
-                }
+    Ok(all_items)
+}
 
-                println!(
-                    "{}: {}",
-                    "Going to retry because couldn't deserialize response from GitHub:".yellow(),
-                    e.to_string().red()
-                );
+/// Extract the items from a page of results: either the response itself is an array,
+/// or the array is in the given field of the response.
+fn parse_page<T>(content: &str, field: Option<&str>) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let mut value = serde_json::from_str::<serde_json::Value>(content)?;
+    let items = match field {
+        Some(f) => value
+            .get_mut(f)
+            .map(serde_json::Value::take)
+            .ok_or_else(|| Error::Decode(format!("The response has no field [{f}]")))?,
+        None => value,
+    };
+    Ok(serde_json::from_value::<Vec<T>>(items)?)
+}
 
-                println!("{}", content.yellow());
-            }
+/// Find the URL of the next page in the `Link` header of a response, e.g.,
+/// `<https://api.github.com/orgs/my-org/repos?page=2>; rel="next", <https://api.github.com/orgs/my-org/repos?page=5>; rel="last"`
+fn next_page_url(headers: &HeaderMap) -> Option<String> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (target, rels) = part.split_once(';')?;
+        if !rels.split(';').any(|rel| rel.trim() == "rel=\"next\"") {
+            return None;
         }
-    }
-
-    Ok(all_items)
+        Some(
+            target
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string(),
+        )
+    })
 }
 
 fn make_paginated_github_request_with_index<T>(
-    gh_token: &str,
+    bootstrap: &Bootstrap,
     page_size: u8,
     url: &str,
     retries: u8,
     params: Option<&str>,
-) -> Result<HashMap<String, T>, String>
+) -> Result<HashMap<String, T>, Error>
 where
-    T: serde::de::DeserializeOwned + std::hash::Hash + std::cmp::Eq + GitHubIndex,
+    T: serde::de::DeserializeOwned + GitHubIndex,
 {
-    let results: HashSet<T> =
-        make_paginated_github_request(gh_token, page_size, url, retries, params)?;
+    let results: Vec<T> =
+        make_paginated_github_request(bootstrap, page_size, url, retries, params)?;
 
     Ok(results
         .into_iter()
@@ -339,38 +477,347 @@ where
         .collect::<HashMap<String, T>>())
 }
 
-pub struct Bootstrap {
-    token: String,
-    org: String,
+/// Fetch the collaborators of each repository with the configured backend, using up to
+/// `bootstrap.concurrency` workers. `sink` receives them in the same order as `repositories`.
+fn for_each_repo_collaborators<S>(
+    bootstrap: &Bootstrap,
+    repositories: Vec<Repository>,
+    progress: &ProgressBar,
+    sink: S,
+) where
+    S: FnMut(Repository, Result<Vec<Collaborator>, Error>),
+{
+    match bootstrap.backend {
+        Backend::Rest => pool::for_each_ordered(
+            repositories,
+            bootstrap.concurrency,
+            progress,
+            |repository| get_repo_collaborators(bootstrap, &repository.name),
+            sink,
+        ),
+        Backend::GraphQl => pool::for_each_ordered_batched(
+            repositories,
+            graphql::BATCH_SIZE,
+            bootstrap.concurrency,
+            progress,
+            |repositories| graphql::fetch_collaborators(bootstrap, repositories),
+            sink,
+        ),
+    }
 }
 
-impl Bootstrap {
-    pub fn new() -> Result<Self, String> {
-        println!(
+/// Base delay between two attempts at a request that failed for a transient reason
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// The GitHub API base URL used when nothing else is configured
+const DEFAULT_API_URL: &str = "https://api.github.com";
+
+/// Open the response cache. Not being able to do so is not a reason to stop the audit:
+/// we warn the user and carry on without it.
+fn open_cache(dir: Option<PathBuf>, ttl: Option<Duration>) -> Option<ResponseCache> {
+    let Some(dir) = dir.or_else(cache::default_dir) else {
+        info!(
             "{}",
-            "I'm checking there is a GitHub FPAT in the GH_TOKEN environment variable...".yellow()
+            "I couldn't find a cache directory, so I am not going to cache responses (use --cache-dir)"
+                .yellow()
         );
+        return None;
+    };
+    match ResponseCache::open(&dir, ttl.unwrap_or(cache::DEFAULT_TTL)) {
+        Ok(cache) => {
+            info!(
+                "{} {}",
+                "I am caching responses in:".green(),
+                cache.dir().display().to_string().white()
+            );
+            Some(cache)
+        }
+        Err(e) => {
+            warn!(
+                "{} {}: {}",
+                "I couldn't open the cache directory".yellow(),
+                dir.display().to_string().white(),
+                e.to_string().red()
+            );
+            None
+        }
+    }
+}
 
-        let token = match std::env::var("GH_TOKEN") {
-            Ok(token) => token,
+/// Set up the authentication as a GitHub App, from the options and the environment
+fn app_auth(app_id: String, options: &BootstrapOptions) -> Result<AppAuth, Error> {
+    info!(
+        "{} {}",
+        "I am going to authenticate as GitHub App:".green(),
+        app_id.white()
+    );
+
+    let key_path = options
+        .app_private_key
+        .clone()
+        .or_else(|| std::env::var_os("GH_APP_PRIVATE_KEY_PATH").map(PathBuf::from));
+    let key = match key_path {
+        Some(path) => std::fs::read(&path).map_err(|e| {
+            Error::Config(format!(
+                "Could not read the GitHub App private key at {}: {e}",
+                path.display()
+            ))
+        })?,
+        None => match std::env::var("GH_APP_PRIVATE_KEY") {
+            Ok(key) => key.into_bytes(),
             Err(_) => {
-                return Err("GH_TOKEN not found".to_string());
+                return Err(Error::Config(
+                    "GitHub App private key not found: use --app-private-key, GH_APP_PRIVATE_KEY_PATH or GH_APP_PRIVATE_KEY".to_string(),
+                ));
             }
+        },
+    };
+
+    let installation_id =
+        match options.app_installation_id {
+            Some(id) => Some(id),
+            None => match std::env::var("GH_APP_INSTALLATION_ID") {
+                Ok(id) => Some(id.trim().parse::<u64>().map_err(|_| {
+                    Error::Config(format!("Invalid GH_APP_INSTALLATION_ID [{id}]"))
+                })?),
+                Err(_) => None,
+            },
         };
-        println!("{} {}...", "I have token:".green(), &token[..20]);
 
-        let org = match std::env::var("GH_ORG") {
-            Ok(org) => org,
-            Err(_) => {
-                return Err("GH_ORG not found".to_string());
+    AppAuth::new(app_id, &key, installation_id)
+}
+
+/// Which GitHub API the audits use to fetch data about each repository
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// One or more REST requests per repository
+    #[default]
+    Rest,
+    /// GraphQL queries that fetch data about many repositories at once
+    #[value(name = "graphql")]
+    GraphQl,
+}
+
+/// Settings used to create a `Bootstrap`. Anything left to `None` falls back to
+/// the environment or to a sensible default.
+#[derive(Default)]
+pub struct BootstrapOptions {
+    /// The GitHub API base URL. If `None`, we look at the `GH_API_URL` environment variable,
+    /// then at the configuration file, and fall back to `https://api.github.com`
+    pub api_url: Option<String>,
+    /// How many repositories are scanned in parallel. If `None`, we use the configuration file,
+    /// then one at a time
+    pub concurrency: Option<usize>,
+    /// Where to cache GitHub responses. If `None`, we use the user's cache directory
+    pub cache_dir: Option<PathBuf>,
+    /// How long cached responses are kept. If `None`, we use `cache::DEFAULT_TTL`
+    pub cache_ttl: Option<Duration>,
+    /// Don't read from or write to the response cache at all
+    pub no_cache: bool,
+    /// Which API is used to fetch data about each repository, where an audit supports both.
+    /// If `None`, we use the configuration file, then REST
+    pub backend: Option<Backend>,
+    /// Authenticate as this GitHub App instead of using `GH_TOKEN`.
+    /// If `None`, we look at the `GH_APP_ID` environment variable
+    pub app_id: Option<String>,
+    /// The GitHub App's private key (PEM). If `None`, we look at the `GH_APP_PRIVATE_KEY_PATH`
+    /// environment variable, then at `GH_APP_PRIVATE_KEY` for the key itself
+    pub app_private_key: Option<PathBuf>,
+    /// The installation of the GitHub App on the organization. If `None`, we look at the
+    /// `GH_APP_INSTALLATION_ID` environment variable, then ask GitHub
+    pub app_installation_id: Option<u64>,
+    /// The configuration file, used for anything not set above or in the environment
+    pub config: Config,
+}
+
+pub struct Bootstrap {
+    auth: Auth,
+    org: String,
+    /// Base URL of the REST API, without a trailing slash.
+    /// E.g., `https://api.github.com` or `https://github.example.com/api/v3` for GitHub Enterprise Server
+    api_url: String,
+    /// Shared by all requests, so that every audit respects GitHub's rate limits
+    rate_limiter: RateLimiter,
+    /// Shared by all requests (and threads), so that connections to GitHub are reused
+    client: reqwest::blocking::Client,
+    /// How many repositories are scanned in parallel
+    concurrency: usize,
+    /// Responses from previous runs, revalidated with conditional requests. `None` if disabled
+    cache: Option<ResponseCache>,
+    backend: Backend,
+    /// Overrides the page size chosen by each audit, if set in the configuration file
+    page_size: Option<u8>,
+    /// Overrides the number of retries chosen by each audit, if set in the configuration file
+    retries: Option<u8>,
+    /// Which repositories are audited when we enumerate the whole organization
+    repo_filter: RepoFilter,
+    /// Organization-wide data shared by the audits of the same run
+    shared: SharedData,
+}
+
+/// Organization-wide data that several audits need. It is fetched the first time an audit
+/// asks for it, and reused by the following audits when running more than one
+#[derive(Default)]
+struct SharedData {
+    /// All the repositories of the organization, already filtered by the configuration file
+    repositories: OnceLock<Vec<Repository>>,
+    members: OnceLock<Vec<Member>>,
+    teams: OnceLock<Vec<Team>>,
+}
+
+/// Return the shared data in `cell`, calling `fetch` only if we don't have it yet
+fn shared<T: Clone>(
+    cell: &OnceLock<Vec<T>>,
+    fetch: impl FnOnce() -> Result<Vec<T>, Error>,
+) -> Result<Vec<T>, Error> {
+    if let Some(items) = cell.get() {
+        return Ok(items.clone());
+    }
+    let items = fetch()?;
+    Ok(cell.get_or_init(|| items).clone())
+}
+
+impl Bootstrap {
+    /// Create a new Bootstrap from the environment and the given options
+    pub fn new(options: BootstrapOptions) -> Result<Self, Error> {
+        let app_id = options
+            .app_id
+            .clone()
+            .or_else(|| std::env::var("GH_APP_ID").ok());
+        let auth = match app_id {
+            Some(app_id) => Auth::App(app_auth(app_id, &options)?),
+            None => {
+                info!(
+                    "{}",
+                    "I'm checking there is a GitHub FPAT in the GH_TOKEN environment variable..."
+                        .yellow()
+                );
+
+                let token = match std::env::var("GH_TOKEN") {
+                    Ok(token) => token,
+                    Err(_) => {
+                        return Err(Error::Config("GH_TOKEN not found".to_string()));
+                    }
+                };
+                info!(
+                    "{} {}...",
+                    "I have token:".green(),
+                    token.chars().take(20).collect::<String>()
+                );
+                Auth::Token(token)
             }
         };
-        println!("{} {}", "I have organization:".green(), org.white());
 
-        Ok(Self { token, org })
+        let config = options.config;
+        let org = match std::env::var("GH_ORG").ok().or(config.org) {
+            Some(org) => org,
+            None => {
+                return Err(Error::Config(
+                    "GH_ORG not found (nor org in the configuration file)".to_string(),
+                ));
+            }
+        };
+        info!("{} {}", "I have organization:".green(), org.white());
+
+        let api_url = normalize_api_url(
+            &options
+                .api_url
+                .or_else(|| std::env::var("GH_API_URL").ok())
+                .or(config.api_url)
+                .unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+        )?;
+        info!(
+            "{} {}",
+            "I am using the GitHub API at:".green(),
+            api_url.white()
+        );
+
+        let concurrency = options.concurrency.or(config.concurrency).unwrap_or(1);
+        if concurrency == 0 {
+            return Err(Error::Config(
+                "The concurrency must be at least 1".to_string(),
+            ));
+        }
+
+        let cache = if options.no_cache {
+            info!("{}", "I am not going to use the response cache".yellow());
+            None
+        } else {
+            open_cache(options.cache_dir, options.cache_ttl)
+        };
+
+        let bootstrap = Self {
+            auth,
+            org,
+            api_url,
+            rate_limiter: RateLimiter::default(),
+            client: reqwest::blocking::Client::new(),
+            concurrency,
+            cache,
+            backend: options.backend.or(config.backend).unwrap_or_default(),
+            page_size: config.requests.page_size,
+            retries: config.requests.retries,
+            repo_filter: config.repos,
+            shared: SharedData::default(),
+        };
+
+        // Get the first installation token right away, so a misconfigured app fails before any audit starts
+        if let Auth::App(app) = &bootstrap.auth {
+            let installation_id = app.installation_id(&bootstrap)?;
+            bootstrap.auth.authorization(&bootstrap)?;
+            info!(
+                "{} {}",
+                "I have an installation token for installation:".green(),
+                installation_id.to_string().white()
+            );
+        }
+
+        Ok(bootstrap)
+    }
+
+    /// The organization being audited
+    pub fn org(&self) -> &str {
+        &self.org
     }
 
-    /// Fetch all repositories in an org.
+    /// Turn a path (e.g., `/orgs/my-org/repos`) into a full URL on the configured API.
+    /// Absolute URLs (e.g., the ones GitHub returns in its responses) are returned unchanged.
+    fn api_endpoint(&self, path: &str) -> String {
+        if path.starts_with("http://") || path.starts_with("https://") {
+            path.to_string()
+        } else {
+            format!("{}{path}", self.api_url)
+        }
+    }
+
+    /// The URL of the GraphQL API. On GitHub Enterprise Server, it lives next to the REST API,
+    /// e.g., `https://github.example.com/api/graphql` for `https://github.example.com/api/v3`
+    fn graphql_endpoint(&self) -> String {
+        match self.api_url.strip_suffix("/api/v3") {
+            Some(host) => format!("{host}/api/graphql"),
+            None => format!("{}/graphql", self.api_url),
+        }
+    }
+
+    /// The URL of GitHub's web interface, for links to repositories, users and teams,
+    /// e.g., `https://github.com`, or `https://github.example.com` for `https://github.example.com/api/v3`
+    pub fn web_url(&self) -> String {
+        match self.api_url.strip_suffix("/api/v3") {
+            Some(host) => host.to_string(),
+            None if self.api_url == "https://api.github.com" => "https://github.com".to_string(),
+            // A mock server, most likely
+            None => self.api_url.clone(),
+        }
+    }
+
+    /// Turn an absolute URL returned by GitHub (e.g., in search results) into a path relative
+    /// to the configured API, so it can be passed to `make_github_request`.
+    fn api_path<'a>(&self, url: &'a str) -> &'a str {
+        url.strip_prefix(self.api_url.as_str()).unwrap_or(url)
+    }
+
+    /// Fetch all repositories in an org. They are fetched only once per run, and shared by all audits.
     /// Args:
     /// * `page_size` - The size of each page when making requests to the GH API
     /// * `active_only` - Consider only active repositories (non-archived and not disabled)
@@ -378,38 +825,41 @@ impl Bootstrap {
         &self,
         page_size: u8,
         active_only: bool,
-    ) -> Result<HashSet<Repository>, String> {
-        if active_only {
-            println!(
+    ) -> Result<Vec<Repository>, Error> {
+        if self.shared.repositories.get().is_some() {
+            info!(
+                "{}",
+                "I already have the repositories of the org from a previous audit".yellow()
+            );
+        } else if active_only {
+            info!(
                 "{}",
                 "I'm going to fetch all active repositories from the org".yellow()
             );
         } else {
-            println!(
+            info!(
                 "{}",
                 "I'm going to fetch all repositories from the org".yellow()
             );
         }
 
-        let repositories: HashSet<Repository> = match make_paginated_github_request(
-            &self.token,
-            page_size,
-            &format!("/orgs/{}/repos", &self.org),
-            3,
-            None,
-        ) {
-            Ok(repositories) => repositories,
-            Err(e) => {
-                return Err(format!(
-                    "{}: {}",
-                    "I couldn't fetch the repositories".red(),
-                    e
-                ));
+        let repositories = shared(&self.shared.repositories, || {
+            let repositories: Vec<Repository> = make_paginated_github_request(
+                self,
+                page_size,
+                &format!("/orgs/{}/repos", &self.org),
+                3,
+                None,
+            )
+            .inspect_err(|_| error!("{}", "I couldn't fetch the repositories".red()))?;
+            if !repositories.iter().any(|repo| repo.private) {
+                warn!("{}", "I didn't find any private repositories. Make sure you have permission to read private repositories.".red());
             }
-        };
+            Ok(self.filter_repositories(repositories, |r| &r.name))
+        })?;
 
         let total = repositories.len();
-        let repositories: HashSet<Repository> = if active_only {
+        let repositories: Vec<Repository> = if active_only {
             repositories
                 .into_iter()
                 .filter(|r| !r.archived && !r.disabled)
@@ -418,31 +868,57 @@ impl Bootstrap {
             repositories
         };
 
-        println!(
+        info!(
             "{} {} (active_only = {}, total = {})",
             "Success! I found: ".green(),
             repositories.len(),
             active_only,
             total
         );
-        if !repositories
-            .iter()
-            .fold(false, |acc, repo| acc || repo.private)
-        {
-            println!("{}", "I didn't find any private repositories. Make sure you have permission to read private repositories.".red());
+        Ok(repositories)
+    }
+
+    /// Keep only the repositories selected by the include/exclude lists of the configuration file
+    pub fn filter_repositories<T>(
+        &self,
+        repositories: Vec<T>,
+        name: impl Fn(&T) -> &str,
+    ) -> Vec<T> {
+        if self.repo_filter.is_empty() {
+            return repositories;
         }
+        let total = repositories.len();
+        let repositories = repositories
+            .into_iter()
+            .filter(|r| self.repo_filter.matches(name(r)))
+            .collect::<Vec<T>>();
+        info!(
+            "{} {} {}",
+            "The configuration file excludes".yellow(),
+            (total - repositories.len()).to_string().white(),
+            "of them".yellow()
+        );
+        repositories
+    }
+}
 
-        Ok(repositories)
+/// Validate a GitHub API base URL and strip any trailing slash.
+/// GitHub Enterprise Server serves the REST API under `/api/v3`, so the URL is expected to include it,
+/// e.g., `https://github.example.com/api/v3`.
+fn normalize_api_url(url: &str) -> Result<String, Error> {
+    let url = url.trim().trim_end_matches('/');
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(Error::Config(format!(
+            "The GitHub API URL must start with http:// or https://, got [{url}]"
+        )));
     }
+    Ok(url.to_string())
 }
 
 /// Get collaborators for a given repository
-fn get_repo_collaborators(
-    bootstrap: &Bootstrap,
-    repo: &str,
-) -> Result<HashSet<Collaborator>, String> {
+fn get_repo_collaborators(bootstrap: &Bootstrap, repo: &str) -> Result<Vec<Collaborator>, Error> {
     make_paginated_github_request(
-        &bootstrap.token,
+        bootstrap,
         25,
         &format!("/repos/{}/{}/collaborators", &bootstrap.org, repo),
         3,
@@ -451,12 +927,77 @@ fn get_repo_collaborators(
 }
 
 /// Get the teams that have access to the repo
-fn get_repo_teams(bootstrap: &Bootstrap, repo: &str) -> Result<HashSet<Team>, String> {
+fn get_repo_teams(bootstrap: &Bootstrap, repo: &str) -> Result<Vec<Team>, Error> {
     make_paginated_github_request(
-        &bootstrap.token,
+        bootstrap,
         25,
         &format!("/repos/{}/{}/teams", &bootstrap.org, repo),
         3,
         None,
     )
 }
+
+#[cfg(test)]
+impl Bootstrap {
+    /// A bootstrap for `my-org` that sends its requests to `api_url`, without cache nor retries
+    pub(crate) fn for_tests(api_url: &str) -> Self {
+        Self {
+            auth: Auth::Token("test-token".to_string()),
+            org: "my-org".to_string(),
+            api_url: api_url.to_string(),
+            rate_limiter: RateLimiter::default(),
+            client: reqwest::blocking::Client::new(),
+            concurrency: 1,
+            cache: None,
+            backend: Backend::default(),
+            page_size: None,
+            retries: Some(1),
+            repo_filter: RepoFilter::default(),
+            shared: SharedData::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(reqwest::header::LINK, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn next_page_url_follows_rel_next() {
+        let headers = link(
+            "<https://api.github.com/orgs/o/repos?page=1>; rel=\"prev\", \
+             <https://api.github.com/orgs/o/repos?page=3>; rel=\"next\", \
+             <https://api.github.com/orgs/o/repos?page=5>; rel=\"last\"",
+        );
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://api.github.com/orgs/o/repos?page=3")
+        );
+    }
+
+    #[test]
+    fn next_page_url_is_none_on_the_last_page() {
+        let headers = link(
+            "<https://api.github.com/orgs/o/repos?page=1>; rel=\"first\", \
+             <https://api.github.com/orgs/o/repos?page=4>; rel=\"prev\"",
+        );
+        assert_eq!(next_page_url(&headers), None);
+        assert_eq!(next_page_url(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn next_page_url_accepts_other_parameters() {
+        let headers =
+            link("<http://127.0.0.1:8787/repos?per_page=100&page=2>;rel=\"next\"; foo=\"bar\"");
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("http://127.0.0.1:8787/repos?per_page=100&page=2")
+        );
+    }
+}
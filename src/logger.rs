@@ -0,0 +1,77 @@
+use std::io::IsTerminal;
+
+use colored::Colorize;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Prints what we are doing, warnings and errors to stderr, so that stdout only has the data
+/// (e.g., the external collaborators CSV) and can be redirected to a file.
+/// Messages come already colored: colors are turned off altogether when stderr is not a terminal
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Our dependencies (e.g., reqwest) log too, but what they have to say is not for our users
+        metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+            && metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Error | Level::Warn | Level::Info => eprintln!("{}", record.args()),
+            Level::Debug | Level::Trace => eprintln!("{}", record.args().to_string().dimmed()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// How much we print to stderr
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Only warnings and errors
+    Quiet,
+    /// What we are doing, as we go
+    #[default]
+    Normal,
+    /// Also every request we send to GitHub
+    Verbose,
+    /// Also how each response was served (e.g., from the cache)
+    VeryVerbose,
+}
+
+impl Verbosity {
+    /// From the `-q` and `-v` flags. `-v` can be repeated
+    pub fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::VeryVerbose,
+        }
+    }
+
+    fn level(self) -> LevelFilter {
+        match self {
+            Verbosity::Quiet => LevelFilter::Warn,
+            Verbosity::Normal => LevelFilter::Info,
+            Verbosity::Verbose => LevelFilter::Debug,
+            Verbosity::VeryVerbose => LevelFilter::Trace,
+        }
+    }
+}
+
+/// Send the `log` macros to stderr. Call it once, before anything is logged
+pub fn init(verbosity: Verbosity) {
+    colored::control::set_override(use_colors(&std::io::stderr()));
+    // This only fails if a logger was already set, in which case we keep it
+    let _ = log::set_logger(&Logger);
+    log::set_max_level(verbosity.level());
+}
+
+/// Whether we should print colors on `stream`: only if it is a terminal, and `NO_COLOR` is not set (https://no-color.org)
+pub fn use_colors(stream: &impl IsTerminal) -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && stream.is_terminal()
+}
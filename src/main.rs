@@ -1,158 +1,624 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
 use colored::Colorize;
-use gh_ec_audit::bpr;
-use gh_ec_audit::compliance;
-use gh_ec_audit::deploy_key;
-use gh_ec_audit::external_collaborator;
+use gh_ec_audit::bpr::BprAudit;
+use gh_ec_audit::cache;
+use gh_ec_audit::compliance::{self, ComplianceAudit};
+use gh_ec_audit::deploy_key::DeployKeyAudit;
+use gh_ec_audit::external_collaborator::ExternalCollaboratorAudit;
+use log::{error, warn};
 
-use clap::{command, Parser};
-use gh_ec_audit::codeowners;
-use gh_ec_audit::members;
-use gh_ec_audit::teams;
-use gh_ec_audit::Bootstrap;
+use clap::{builder::PossibleValuesParser, CommandFactory, Parser, Subcommand};
+use gh_ec_audit::codeowners::{CodeownersAudit, TeamInCodeownersAudit};
+use gh_ec_audit::members::{AdminAudit, MembersAudit};
+use gh_ec_audit::teams::{EmptyTeamsAudit, TeamPermissionsAudit};
+use gh_ec_audit::{
+    audit::{self, Audit, ExitStatus, AUDIT_NAMES},
+    config::Config,
+    finding::Severity,
+    logger::{self, Verbosity},
+    output::{self, AuditResults, Format, Report},
+    preflight::{run_preflight, AuditKind, PreflightOptions},
+    Backend, Bootstrap, BootstrapOptions, Error,
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    global: GlobalArgs,
+
+    #[command(flatten)]
+    legacy: LegacyArgs,
+}
+
+/// Options shared by all audits. They can be given before or after the subcommand
+#[derive(clap::Args, Debug)]
+struct GlobalArgs {
+    /// A TOML configuration file with the org, request settings, repository allowlists and per-audit defaults.
+    /// Command line flags take precedence over environment variables, which take precedence over this file
+    #[arg(long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// Check what the token can do before running the audit, and stop if the audit won't work.
+    /// Without an audit, check all of them and exit
+    #[arg(long, global = true)]
+    preflight: bool,
+
+    /// Only print warnings and errors
+    #[arg(short, long, conflicts_with = "verbose", global = true)]
+    quiet: bool,
+
+    /// Print more about what is going on: -v for every request sent to GitHub (and the CODEOWNERS files
+    /// without problems), -vv for how each response was served too. Everything is printed to stderr
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Write the data (e.g., the ec CSV, the findings or the JSON document) to this file instead of stdout
+    #[arg(short, long, value_name = "FILE", global = true)]
+    output: Option<PathBuf>,
+
+    /// The GitHub API base URL (defaults to the GH_API_URL environment variable, then https://api.github.com).
+    /// For GitHub Enterprise Server, use https://HOSTNAME/api/v3
+    #[arg(long, value_name = "URL", global = true)]
+    api_url: Option<String>,
+
+    /// How many repositories to scan in parallel (defaults to 1).
+    /// Used by the ec, deploy-keys, admins, codeowners, team-in-codeowners and compliance audits
+    #[arg(long, value_name = "N", global = true)]
+    concurrency: Option<usize>,
+
+    /// How to write the results (defaults to text). json writes a single document and ndjson one line per finding,
+    /// both at the end of the run. See the README for the schema
+    #[arg(long, value_enum, global = true)]
+    format: Option<Format>,
+
+    /// Write a report for people at the end of the run instead of the data: html is a single file with a summary
+    /// of the findings, sortable tables and links to GitHub; markdown can be pasted in an issue or a wiki page.
+    /// Best used with --output
+    #[arg(long, value_enum, conflicts_with = "format", global = true)]
+    report: Option<Report>,
+
+    /// Exit with 3 when a finding is at least this severe, e.g., to block a pipeline. Without it, findings don't change
+    /// the exit code: 0 when everything went well, 1 on errors, 4 when some repositories or teams were skipped
+    #[arg(long, value_enum, value_name = "SEVERITY", global = true)]
+    fail_on: Option<Severity>,
+
+    /// Where to cache GitHub responses between runs (defaults to $XDG_CACHE_HOME/gh-ec-audit or ~/.cache/gh-ec-audit)
+    #[arg(long, value_name = "DIR", global = true)]
+    cache_dir: Option<PathBuf>,
+
+    /// How long cached responses are kept, e.g., 3600, 90m, 12h or 7d (defaults to 7d)
+    #[arg(long, value_name = "TTL", value_parser = cache::parse_ttl, global = true)]
+    cache_ttl: Option<Duration>,
+
+    /// Don't read from or write to the response cache
+    #[arg(long, conflicts_with_all = ["cache_dir", "cache_ttl"], global = true)]
+    no_cache: bool,
+
+    /// Which GitHub API to fetch per-repository data with (defaults to rest). GraphQL fetches many repositories
+    /// per request. Used by the ec, admins and compliance audits
+    #[arg(long, value_enum, global = true)]
+    backend: Option<Backend>,
+
+    /// Authenticate as this GitHub App instead of using GH_TOKEN (defaults to the GH_APP_ID environment variable)
+    #[arg(long, value_name = "ID", global = true)]
+    app_id: Option<String>,
+
+    /// The GitHub App's private key, in PEM format (defaults to the GH_APP_PRIVATE_KEY_PATH environment variable,
+    /// then to the key in GH_APP_PRIVATE_KEY)
+    #[arg(long, value_name = "FILE", global = true)]
+    app_private_key: Option<PathBuf>,
+
+    /// The installation of the GitHub App on the organization (defaults to the GH_APP_INSTALLATION_ID
+    /// environment variable, then it is looked up on GitHub)
+    #[arg(long, value_name = "ID", global = true)]
+    app_installation_id: Option<u64>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
     /// Run the external collaborator audit
-    #[arg(short, long)]
-    ec: bool,
+    #[command(visible_alias = "external-collaborators")]
+    Ec(EcArgs),
 
     /// Run the deploy key audit
-    #[arg(short, long)]
-    dk: bool,
+    #[command(visible_alias = "dk")]
+    DeployKeys(DeployKeyArgs),
 
     /// Run the members audit
-    #[arg(short, long)]
-    mem: bool,
+    Members,
 
-    /// Run the admin audit
-    #[arg(short, long)]
-    admin: bool,
+    /// Run the admin audit (admins that are neither org admins nor in an admin team)
+    Admins(ReposArgs),
 
     /// Run the BPR and rulesets audit
-    #[arg(short, long)]
-    bpr: bool,
+    Bpr(ReposArgs),
 
     /// Run the team permissions audit
-    #[arg(short, long)]
-    teamperm: bool,
+    TeamPerms(TeamArgs),
 
     /// Run the empty teams audit
-    #[arg(long)]
-    emptyteams: bool,
+    EmptyTeams,
 
     /// Run the CODEOWNERS audit
-    #[arg(short, long)]
-    codeowners: bool,
+    Codeowners(CodeownersArgs),
 
-    /// Run repository protection compliance scoring (0-8).
-    /// Tip: to run for all repositories, omit --repos
-    #[arg(long = "comp-check")]
-    comp: bool,
+    /// Run repository protection compliance scoring (0-8)
+    Compliance(ComplianceArgs),
 
-    /// Export compliance audit to a CSV file
-    #[arg(long = "comp-check-csv", value_name = "FILE")]
-    comp_check_csv: Option<String>,
+    /// Find occurrences of a team in CODEOWNERS files
+    TeamInCodeowners(TeamInCodeownersArgs),
 
-    /// Limit which compliance checks to run (comma-separated).
-    /// Available: pr_one_approval, pr_dismiss_stale, pr_require_code_owner, disable_force_push, disable_deletion, require_signed_commits, require_status_checks, codeowners_valid
-    #[arg(long = "comp-checks", value_delimiter = ',', value_name = "LIST")]
-    comp_checks: Option<Vec<String>>,
+    /// Run several audits (by default, all of them) one after the other, fetching the org's
+    /// repositories, members and teams only once
+    All(AllArgs),
+}
 
-    /// Consider only active repositories (non-archived and not disabled)
+#[derive(clap::Args, Debug)]
+struct EcArgs {
+    /// The previous run CSV file, to keep approvals and detect access changes
+    #[arg(short, long, value_name = "FILE")]
+    previous: Option<String>,
+
+    /// Write what changed since the previous run (granted, escalated, downgraded and revoked access) to this file,
+    /// for reviewers to sign off on: JSON if its name ends with .json, CSV otherwise. Needs --previous
+    #[arg(long, value_name = "FILE")]
+    diff: Option<String>,
+
+    /// Also write the CSV to this file, replacing it if it exists, to pass it with --previous to the next run.
+    /// Needed with --format or --report, which don't write the CSV to the output
+    #[arg(long, value_name = "FILE")]
+    csv: Option<String>,
+
+    /// Also list the pending invitations of non-members to each repository, and add them to the CSV
+    /// with the status Invited. Needs the administration: read permission
     #[arg(long)]
-    active_repo_only: bool,
+    include_invitations: bool,
+}
 
-    /// Find occurrences of a team in CODEOWNERS files
+#[derive(clap::Args, Debug)]
+struct DeployKeyArgs {
+    /// The previous run CSV file
+    #[arg(short, long, value_name = "FILE")]
+    previous: Option<String>,
+
+    /// Show all deploy keys, not only the ones added by non-members
     #[arg(long)]
-    team_in_codeowners: bool,
+    all: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReposArgs {
+    /// Limit the scanning to the given repos.
+    /// Omit this flag to scan all repositories
+    #[arg(short, long, value_delimiter = ',', num_args = 1.., value_name = "REPO")]
+    repos: Option<Vec<String>>,
+}
 
-    /// Also invoke the GH API to get extra info when auditing CODEOWNERS
+#[derive(clap::Args, Debug)]
+struct TeamArgs {
+    /// The slug of the team
+    #[arg(long)]
+    team: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct CodeownersArgs {
+    #[command(flatten)]
+    repos: ReposArgs,
+
+    /// Use GitHub's code search instead of enumerating repos (faster, but has its own rate limit)
+    #[arg(long, conflicts_with = "repos")]
+    search: bool,
+
+    /// Also ask GitHub for CODEOWNERS parsing errors
     #[arg(long)]
     also_gh_api: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ComplianceArgs {
+    #[command(flatten)]
+    repos: ReposArgs,
 
-    /// Focus the audit on a given GH team
+    /// Export the compliance audit to a CSV file. If the file exists, repositories already in it are skipped
+    #[arg(long, value_name = "FILE")]
+    csv: Option<String>,
+
+    /// Also write the result of every check to a JUnit XML file, for CI: each repository is a test suite,
+    /// and each check a test case that passes, fails or is skipped
+    #[arg(long, value_name = "FILE")]
+    junit: Option<String>,
+
+    /// Report repositories whose score is below this minimum (CMP010, high). Use with --fail-on high to block a pipeline
+    #[arg(long, value_name = "SCORE")]
+    min_score: Option<u32>,
+
+    /// Limit which compliance checks to run (comma-separated)
+    #[arg(long, value_delimiter = ',', value_name = "LIST", ignore_case = true,
+        value_parser = PossibleValuesParser::new(compliance::CHECK_NAMES))]
+    checks: Option<Vec<String>>,
+
+    /// Consider only active repositories (non-archived and not disabled)
     #[arg(long)]
-    team: Option<String>,
+    active_repo_only: bool,
+}
 
-    /// Disable filtering on specific audits
-    #[clap(long, default_value_t = false)]
-    all: bool,
+#[derive(clap::Args, Debug)]
+struct TeamInCodeownersArgs {
+    /// The slug of the team
+    #[arg(long)]
+    team: String,
+
+    #[command(flatten)]
+    repos: ReposArgs,
 
-    /// Use GH search API instead of enumerating repos (only for specific audits)
-    #[clap(long, default_value_t = false)]
+    /// Use GitHub's code search instead of enumerating repos (faster, but has its own rate limit)
+    #[arg(long, conflicts_with = "repos")]
     search: bool,
+}
 
-    /// Limit the scanning to the given repos.
-    /// Omit this flag to scan all repositories
-    #[clap(short, long, value_delimiter = ',', num_args = 1..)]
-    repos: Option<Vec<String>>,
+#[derive(clap::Args, Debug)]
+struct AllArgs {
+    /// The audits to run (defaults to all of them). Their options are taken from the configuration file
+    #[arg(value_name = "AUDIT", value_parser = PossibleValuesParser::new(AUDIT_NAMES))]
+    audits: Vec<String>,
 
-    /// The previous run CSV file
-    #[arg(short, long)]
+    /// Don't run these audits (comma-separated)
+    #[arg(long, value_delimiter = ',', value_name = "LIST",
+        value_parser = PossibleValuesParser::new(AUDIT_NAMES))]
+    skip: Vec<String>,
+
+    /// The slug of the team for team-perms and team-in-codeowners, which are left out without one
+    #[arg(long)]
+    team: Option<String>,
+
+    #[command(flatten)]
+    repos: ReposArgs,
+
+    /// List the audits, what they look for and the permissions they need, then exit
+    #[arg(long)]
+    list: bool,
+}
+
+/// The flags used before subcommands existed. They still work, but print a deprecation warning.
+/// Like before, the first audit flag wins and options that don't apply to it are ignored.
+#[derive(clap::Args, Debug, Default, PartialEq)]
+struct LegacyArgs {
+    #[arg(short, long, hide = true)]
+    ec: bool,
+    #[arg(short, long, hide = true)]
+    dk: bool,
+    #[arg(short, long, hide = true)]
+    mem: bool,
+    #[arg(short, long, hide = true)]
+    admin: bool,
+    #[arg(short, long, hide = true)]
+    bpr: bool,
+    #[arg(short, long, hide = true)]
+    teamperm: bool,
+    #[arg(long, hide = true)]
+    emptyteams: bool,
+    #[arg(short, long, hide = true)]
+    codeowners: bool,
+    #[arg(long = "comp-check", hide = true)]
+    comp: bool,
+    #[arg(long = "comp-check-csv", value_name = "FILE", hide = true)]
+    comp_check_csv: Option<String>,
+    #[arg(long = "comp-checks", value_delimiter = ',', value_name = "LIST", ignore_case = true,
+        value_parser = PossibleValuesParser::new(compliance::CHECK_NAMES), hide = true)]
+    comp_checks: Option<Vec<String>>,
+    #[arg(long, hide = true)]
+    active_repo_only: bool,
+    #[arg(long, hide = true)]
+    team_in_codeowners: bool,
+    #[arg(long, hide = true)]
+    also_gh_api: bool,
+    #[arg(long, hide = true)]
+    team: Option<String>,
+    #[arg(long, hide = true)]
+    all: bool,
+    #[arg(long, hide = true)]
+    search: bool,
+    #[arg(short, long, value_delimiter = ',', num_args = 1.., hide = true)]
+    repos: Option<Vec<String>>,
+    #[arg(short, long, hide = true)]
     previous: Option<String>,
+}
+
+impl LegacyArgs {
+    /// Turn the old flags into the matching subcommand, checking them in the same order as before
+    fn into_command(self) -> Result<Option<Command>, Error> {
+        let team = |team: Option<String>| {
+            team.ok_or_else(|| Error::Config("Please specify a team with --team".to_string()))
+        };
+        let repos = ReposArgs { repos: self.repos };
+
+        let (flag, name, command) = if self.ec {
+            let args = EcArgs {
+                previous: self.previous,
+                diff: None,
+                csv: None,
+                include_invitations: false,
+            };
+            ("--ec", "ec", Command::Ec(args))
+        } else if self.dk {
+            let args = DeployKeyArgs {
+                previous: self.previous,
+                all: self.all,
+            };
+            ("--dk", "deploy-keys", Command::DeployKeys(args))
+        } else if self.mem {
+            ("--mem", "members", Command::Members)
+        } else if self.admin {
+            ("--admin", "admins", Command::Admins(repos))
+        } else if self.bpr {
+            ("--bpr", "bpr", Command::Bpr(repos))
+        } else if self.teamperm {
+            let args = TeamArgs {
+                team: team(self.team)?,
+            };
+            ("--teamperm", "team-perms", Command::TeamPerms(args))
+        } else if self.emptyteams {
+            ("--emptyteams", "empty-teams", Command::EmptyTeams)
+        } else if self.codeowners {
+            let args = CodeownersArgs {
+                repos,
+                search: self.search,
+                also_gh_api: self.also_gh_api,
+            };
+            ("--codeowners", "codeowners", Command::Codeowners(args))
+        } else if self.comp || self.comp_check_csv.is_some() {
+            let args = ComplianceArgs {
+                repos,
+                csv: self.comp_check_csv,
+                junit: None,
+                min_score: None,
+                checks: self.comp_checks,
+                active_repo_only: self.active_repo_only,
+            };
+            ("--comp-check", "compliance", Command::Compliance(args))
+        } else if self.team_in_codeowners {
+            let args = TeamInCodeownersArgs {
+                team: team(self.team)?,
+                repos,
+                search: self.search,
+            };
+            (
+                "--team-in-codeowners",
+                "team-in-codeowners",
+                Command::TeamInCodeowners(args),
+            )
+        } else {
+            return Ok(None);
+        };
 
-    /// Increase verbosity
-    #[arg(short, long)]
-    verbose: bool,
+        warn!(
+            "{} {} {}",
+            format!("Warning! {flag} is deprecated and will be removed in a future release: use")
+                .yellow(),
+            format!("gh-ec-audit {name}").white(),
+            "instead (see --help)".yellow()
+        );
+        Ok(Some(command))
+    }
+}
+
+impl Command {
+    /// The audits to run. For `all`, they get their options from the configuration file
+    fn into_audits(self, config: &mut Config) -> Result<Vec<Box<dyn Audit>>, Error> {
+        let mut audit: Box<dyn Audit> = match self {
+            Command::Ec(args) => Box::new(ExternalCollaboratorAudit {
+                previous: args.previous,
+                diff: args.diff,
+                csv: args.csv,
+                include_invitations: args.include_invitations,
+                ..Default::default()
+            }),
+            Command::DeployKeys(args) => Box::new(DeployKeyAudit {
+                previous: args.previous,
+                all: args.all,
+            }),
+            Command::Members => Box::new(MembersAudit),
+            Command::Admins(args) => Box::new(AdminAudit { repos: args.repos }),
+            Command::Bpr(args) => Box::new(BprAudit { repos: args.repos }),
+            Command::TeamPerms(args) => Box::new(TeamPermissionsAudit { team: args.team }),
+            Command::EmptyTeams => Box::new(EmptyTeamsAudit),
+            Command::Codeowners(args) => Box::new(CodeownersAudit {
+                repos: args.repos.repos,
+                search: args.search,
+                also_gh_api: args.also_gh_api,
+                ..Default::default()
+            }),
+            Command::Compliance(args) => Box::new(ComplianceAudit {
+                repos: args.repos.repos,
+                csv: args.csv,
+                junit: args.junit,
+                min_score: args.min_score,
+                active_repo_only: args.active_repo_only,
+                checks: args.checks,
+                ..Default::default()
+            }),
+            Command::TeamInCodeowners(args) => Box::new(TeamInCodeownersAudit {
+                team: args.team,
+                repos: args.repos.repos,
+                search: args.search,
+            }),
+            Command::All(args) => return args.into_audits(config),
+        };
+        audit.apply_config(config);
+        Ok(vec![audit])
+    }
+}
+
+impl AllArgs {
+    fn into_audits(self, config: &mut Config) -> Result<Vec<Box<dyn Audit>>, Error> {
+        if self.team.is_none() {
+            if let Some(name) = self
+                .audits
+                .iter()
+                .find(|a| *a == "team-perms" || *a == "team-in-codeowners")
+            {
+                return Err(Error::Config(format!(
+                    "The {name} audit needs a team: please specify one with --team"
+                )));
+            }
+        }
+        let mut audits = audit::registry(self.repos.repos, self.team);
+        audits.retain(|a| {
+            (self.audits.is_empty() || self.audits.iter().any(|name| name == a.name()))
+                && !self.skip.iter().any(|name| name == a.name())
+        });
+        if audits.is_empty() {
+            return Err(Error::Config("There are no audits left to run".to_string()));
+        }
+        for audit in audits.iter_mut() {
+            audit.apply_config(config);
+        }
+        Ok(audits)
+    }
+}
+
+/// What the preflight needs to know for several audits: they share the repositories and the team,
+/// and an option that changes the endpoints of one audit is checked for all of them
+fn preflight_options(audits: &[Box<dyn Audit>]) -> PreflightOptions {
+    audits.iter().map(|a| a.preflight_options()).fold(
+        PreflightOptions::default(),
+        |merged, options| PreflightOptions {
+            repos: merged.repos.or(options.repos),
+            team: merged.team.or(options.team),
+            search: merged.search || options.search,
+            also_gh_api: merged.also_gh_api || options.also_gh_api,
+            invitations: merged.invitations || options.invitations,
+        },
+    )
+}
+
+/// Print each audit, what it looks for and the permissions it needs
+fn list_audits() {
+    for audit in audit::registry(None, Some(String::new())) {
+        println!(
+            "{:<20}{}\n{:<20}{} {}",
+            audit.name().white().bold(),
+            audit.description(),
+            "",
+            "Needs:".yellow(),
+            audit.required_permissions().join(", ")
+        );
+    }
+}
+
+fn exit_with_error(e: Error) -> ! {
+    error!("{}", e.to_string().bold().red());
+    std::process::exit(1);
 }
 
 fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    logger::init(Verbosity::from_flags(cli.global.quiet, cli.global.verbose));
 
-    let bootstrap = match Bootstrap::new() {
-        Ok(b) => b,
-        Err(e) => {
-            println!("{}", e.bold().red());
-            std::process::exit(1);
+    let command = match cli.command {
+        Some(_) if cli.legacy != LegacyArgs::default() => exit_with_error(Error::Config(
+            "The old audit flags (e.g., --ec, or --repos before the subcommand) can't be combined with a subcommand".to_string(),
+        )),
+        Some(Command::All(AllArgs { list: true, .. })) => {
+            list_audits();
+            return;
         }
+        Some(command) => Some(command),
+        None => cli
+            .legacy
+            .into_command()
+            .unwrap_or_else(|e| exit_with_error(e)),
+    };
+    if command.is_none() && !cli.global.preflight {
+        let _ = Cli::command().print_help();
+        return;
+    }
+
+    let global = cli.global;
+    let mut config = match &global.config {
+        Some(path) => Config::load(path).unwrap_or_else(|e| exit_with_error(e)),
+        None => Config::default(),
+    };
+    let audits = match command {
+        Some(command) => command
+            .into_audits(&mut config)
+            .unwrap_or_else(|e| exit_with_error(e)),
+        None => vec![],
+    };
+    let format = global.format.or(config.format).unwrap_or_default();
+    let fail_on = global.fail_on.or(config.fail_on);
+    if let Err(e) = output::open(format, global.report, global.output.as_deref()) {
+        exit_with_error(e);
+    }
+
+    let bootstrap = match Bootstrap::new(BootstrapOptions {
+        api_url: global.api_url,
+        concurrency: global.concurrency,
+        cache_dir: global.cache_dir,
+        cache_ttl: global.cache_ttl,
+        no_cache: global.no_cache,
+        backend: global.backend,
+        app_id: global.app_id,
+        app_private_key: global.app_private_key,
+        app_installation_id: global.app_installation_id,
+        config,
+    }) {
+        Ok(b) => b,
+        Err(e) => exit_with_error(e),
     };
 
-    if args.ec {
-        external_collaborator::run_audit(bootstrap, args.previous);
-    } else if args.dk {
-        deploy_key::run_audit(bootstrap, args.previous, args.all);
-    } else if args.mem {
-        members::run_audit(bootstrap);
-    } else if args.admin {
-        members::run_admin_audit(bootstrap, args.repos);
-    } else if args.bpr {
-        bpr::run_audit(bootstrap, args.repos);
-    } else if args.teamperm {
-        if let Some(team) = args.team {
-            teams::run_team_repo_audit(bootstrap, team);
+    if global.preflight {
+        let (kinds, options) = if audits.is_empty() {
+            (AuditKind::ALL.to_vec(), PreflightOptions::default())
         } else {
-            println!("Please specify a team with --team");
+            (
+                audits.iter().map(|a| a.kind()).collect(),
+                preflight_options(&audits),
+            )
+        };
+        match run_preflight(&bootstrap, &kinds, &options) {
+            Ok(false) if !audits.is_empty() => {
+                error!(
+                    "{}",
+                    "The token can't run this audit: fix what failed above and try again"
+                        .bold()
+                        .red()
+                );
+                std::process::exit(1);
+            }
+            // All good: go on with the audit, if there is one
+            Ok(_) => {}
+            Err(e) => exit_with_error(e),
         }
-    } else if args.emptyteams {
-        teams::run_empty_teams_audit(bootstrap);
-    } else if args.codeowners {
-        codeowners::run_codeowners_audit(
-            bootstrap,
-            args.repos,
-            args.search,
-            args.also_gh_api,
-            args.verbose,
-        );
-    } else if args.comp || args.comp_check_csv.is_some() {
-        let selected = args
-            .comp_checks
-            .as_ref()
-            .map(|v| v.iter().map(|s| s.to_string()).collect());
-        compliance::run_compliance_audit(
-            bootstrap,
-            args.repos,
-            args.comp_check_csv,
-            args.active_repo_only,
-            selected,
+    }
+
+    let results: AuditResults = match audits.as_slice() {
+        [] => return,
+        [audit] => vec![(audit.name(), audit.run(&bootstrap))],
+        audits => audit::run_audits(&bootstrap, audits),
+    };
+    if let Err(e) = output::write_results(&bootstrap, &results) {
+        exit_with_error(e);
+    }
+    // A single audit that fails is reported as a whole run that fails
+    if let [(_, Err(e))] = results.as_slice() {
+        exit_with_error(e.clone());
+    }
+    let status = ExitStatus::of(&results, fail_on);
+    if let (ExitStatus::Findings(count), Some(fail_on)) = (status, fail_on) {
+        error!(
+            "{}",
+            format!("Failing because {count} findings are {fail_on} or more severe (--fail-on {fail_on})")
+                .bold()
+                .red()
         );
-    } else if args.team_in_codeowners {
-        if let Some(team) = args.team {
-            codeowners::run_team_in_codeowners_audit(bootstrap, team, args.repos, args.search);
-        } else {
-            println!("Please specify a team with --team");
-        }
-    } else {
-        println!("No command specified");
     }
+    std::process::exit(status.code());
 }
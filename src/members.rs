@@ -1,68 +1,138 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::HashMap, sync::Mutex};
 
 use colored::Colorize;
+use log::error;
 
 use crate::{
-    get_repo_teams, make_paginated_github_request, make_paginated_github_request_with_index,
-    Bootstrap, Collaborator, Member, Permissions, Repository, Team,
+    audit::Audit,
+    finding::{self, Target},
+    get_repo_collaborators, get_repo_teams, graphql, make_paginated_github_request,
+    make_paginated_github_request_with_index, output,
+    pool::{for_each_ordered, for_each_ordered_batched, progress_bar},
+    preflight::{AuditKind, PreflightOptions},
+    shared, Backend, Bootstrap, Collaborator, Error, Finding, Findings, GitHubIndex, Member,
+    Permissions, Repository, Skipped, Team,
 };
 
-pub fn get_org_members(bootstrap: &Bootstrap) -> HashSet<Member> {
-    match make_paginated_github_request(
-        &bootstrap.token,
-        100,
-        &format!("/orgs/{}/members", &bootstrap.org),
-        3,
-        None,
-    ) {
-        Ok(mem) => mem,
-        Err(e) => {
-            panic!("{}: {e}", "I couldn't fetch the organization members".red());
-        }
+/// Get the members of the org. They are fetched only once per run, and shared by all audits
+pub fn get_org_members(bootstrap: &Bootstrap) -> Result<Vec<Member>, Error> {
+    shared(&bootstrap.shared.members, || {
+        make_paginated_github_request(
+            bootstrap,
+            100,
+            &format!("/orgs/{}/members", &bootstrap.org),
+            3,
+            None,
+        )
+    })
+    .inspect_err(|_| error!("{}", "I couldn't fetch the organization members".red()))
+}
+
+/// Get the members of the org, indexed by login
+pub fn get_indexed_org_members(bootstrap: &Bootstrap) -> Result<HashMap<String, Member>, Error> {
+    Ok(get_org_members(bootstrap)?
+        .into_iter()
+        .map(|m| (m.index(), m))
+        .collect())
+}
+
+/// The members of the org
+pub struct MembersAudit;
+
+impl Audit for MembersAudit {
+    fn name(&self) -> &'static str {
+        "members"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the members of the org"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["members: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::Members
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_audit(bootstrap)
     }
 }
 
-pub fn get_indexed_org_members(bootstrap: &Bootstrap) -> HashMap<String, Member> {
-    match make_paginated_github_request_with_index(
-        &bootstrap.token,
-        100,
-        &format!("/orgs/{}/members", &bootstrap.org),
-        3,
-        None,
-    ) {
-        Ok(mem) => mem,
-        Err(e) => {
-            panic!("{}: {e}", "I couldn't fetch the organization members".red());
+/// Repository admins that are neither org admins nor in an admin team
+pub struct AdminAudit {
+    /// Only these repositories. If `None`, all of them
+    pub repos: Option<Vec<String>>,
+}
+
+impl Audit for AdminAudit {
+    fn name(&self) -> &'static str {
+        "admins"
+    }
+
+    fn description(&self) -> &'static str {
+        "Repository admins that are neither org admins nor in an admin team"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["members: read", "metadata: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::Admins
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            repos: self.repos.clone(),
+            ..Default::default()
         }
     }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_admin_audit(bootstrap, self.repos.clone())
+    }
 }
 
-pub fn run_audit(bootstrap: Bootstrap) {
-    for member in get_org_members(&bootstrap) {
-        println!("{}", member.avatar_url);
+pub fn run_audit(bootstrap: &Bootstrap) -> Result<Findings, Error> {
+    let mut findings = Findings::default();
+    for member in get_org_members(bootstrap)? {
+        output::line(&member.avatar_url);
+        findings.record_quietly(
+            Finding::new(
+                &finding::ORG_MEMBER,
+                Target::User {
+                    login: member.login.clone(),
+                },
+                format!("{} is a member of the org", member.login),
+            )
+            .with("avatar_url", &member.avatar_url),
+        );
     }
+    Ok(findings)
 }
 
-pub fn run_admin_audit(bootstrap: Bootstrap, repos: Option<Vec<String>>) {
-    let organization_admins: HashMap<String, Member> =
-        match make_paginated_github_request_with_index(
-            &bootstrap.token,
-            100,
-            &format!("/orgs/{}/members", &bootstrap.org),
-            3,
-            Some("role=admin"),
-        ) {
-            Ok(org_admins) => org_admins,
-            Err(e) => {
-                panic!("{}: {e}", "I couldn't fetch the organization members".red());
-            }
-        };
+pub fn run_admin_audit(
+    bootstrap: &Bootstrap,
+    repos: Option<Vec<String>>,
+) -> Result<Findings, Error> {
+    let organization_admins: HashMap<String, Member> = make_paginated_github_request_with_index(
+        bootstrap,
+        100,
+        &format!("/orgs/{}/members", &bootstrap.org),
+        3,
+        Some("role=admin"),
+    )
+    .inspect_err(|_| error!("{}", "I couldn't fetch the organization admins".red()))?;
 
-    let repositories: HashSet<Repository> = match repos {
+    let repositories: Vec<Repository> = match repos {
         Some(repos) => repos
             .into_iter()
             .map(|r| Repository {
                 name: r,
+                id: None,
                 private: false,
                 archived: false,
                 disabled: false,
@@ -75,111 +145,159 @@ pub fn run_admin_audit(bootstrap: Bootstrap, repos: Option<Vec<String>>) {
                 },
             })
             .collect(),
-        None => bootstrap.fetch_all_repositories(75, false).unwrap(),
+        None => bootstrap.fetch_all_repositories(75, false)?,
     };
 
-    let mut team_cache: HashMap<String, HashMap<String, Member>> = HashMap::new();
-
-    let one_percent = (repositories.len() as f64 * 0.01).ceil() as usize;
-    let mut progress = 0;
-
-    for repository in repositories {
-        // Get the teams that have access to the repository
-        let repo_teams = match get_repo_teams(&bootstrap, &repository.name) {
-            Ok(rt) => rt,
-            Err(_) => {
-                println!(
-                    "{} {} {}",
-                    "I couldn't fetch teams with access to repository".yellow(),
-                    repository.name.white(),
-                    ". I will continue with other repositories.".yellow()
-                );
-                continue;
+    let team_cache = Mutex::new(HashMap::new());
+    let mut skipped = Skipped::default();
+    let mut findings = Findings::default();
+
+    let pb = progress_bar(repositories.len());
+    let sink = |repository: Repository, admins: Result<(Vec<String>, Vec<String>), Error>| {
+        let (admin_teams, admin_users) = match admins {
+            Ok(admins) => admins,
+            Err(e) => {
+                pb.suspend(|| skipped.record(repository.name, e));
+                return;
             }
         };
 
-        let repo_admin_teams = repo_teams
-            .iter()
-            .filter(|t| t.permissions.as_ref().unwrap().admin)
-            .collect::<Vec<&Team>>();
-
-        for repo_admin_team in &repo_admin_teams {
-            println!(
-                "{} {} {} {}",
-                "I found an admin team:".yellow(),
-                repo_admin_team.slug.white(),
-                "on".yellow(),
-                repository.name.white()
-            );
-        }
-
-        for team in &repo_teams {
-            if !team_cache.contains_key(&team.slug) {
-                let team_members: HashMap<String, Member> =
-                    match make_paginated_github_request_with_index(
-                        &bootstrap.token,
-                        25,
-                        &format!("/orgs/{}/teams/{}/members", &bootstrap.org, team.slug),
-                        3,
-                        None,
-                    ) {
-                        Ok(t) => t,
-                        Err(e) => {
-                            panic!(
-                                "{} {}: {e}",
-                                repository.name.white(),
-                                "I couldn't fetch the repository collaborators".red()
-                            );
-                        }
-                    };
-                team_cache.insert(team.slug.clone(), team_members);
+        pb.suspend(|| {
+            for team in admin_teams {
+                findings.record(Finding::new(
+                    &finding::ADMIN_TEAM,
+                    Target::RepositoryTeam {
+                        repo: repository.name.clone(),
+                        team: team.clone(),
+                    },
+                    format!("Team {team} has admin access to {}", repository.name),
+                ));
             }
-        }
+            for user in admin_users {
+                findings.record(Finding::new(
+                    &finding::ADMIN_USER,
+                    Target::RepositoryUser {
+                        repo: repository.name.clone(),
+                        login: user.clone(),
+                    },
+                    format!(
+                        "User {user} has admin access to {} outside of the admin teams",
+                        repository.name
+                    ),
+                ));
+            }
+        });
+    };
 
-        let collaborators: HashSet<Collaborator> = match make_paginated_github_request(
-            &bootstrap.token,
+    match bootstrap.backend {
+        Backend::Rest => for_each_ordered(
+            repositories,
+            bootstrap.concurrency,
+            &pb,
+            |repository| {
+                let collaborators = get_repo_collaborators(bootstrap, &repository.name);
+                find_repo_admins(
+                    bootstrap,
+                    repository,
+                    collaborators,
+                    &organization_admins,
+                    &team_cache,
+                )
+            },
+            sink,
+        ),
+        Backend::GraphQl => for_each_ordered_batched(
+            repositories,
+            graphql::BATCH_SIZE,
+            bootstrap.concurrency,
+            &pb,
+            // Teams and their permissions are only available in the REST API
+            |repositories| {
+                graphql::fetch_collaborators(bootstrap, repositories)
+                    .into_iter()
+                    .zip(repositories)
+                    .map(|(collaborators, repository)| {
+                        find_repo_admins(
+                            bootstrap,
+                            repository,
+                            collaborators,
+                            &organization_admins,
+                            &team_cache,
+                        )
+                    })
+                    .collect()
+            },
+            sink,
+        ),
+    }
+    pb.finish_and_clear();
+
+    skipped.report();
+    findings.report();
+    Ok(findings.with_skipped(skipped))
+}
+
+/// Members of the teams we have already fetched, indexed by team slug.
+/// Shared by all the workers so that each team is fetched (roughly) once.
+type TeamCache = Mutex<HashMap<String, HashMap<String, Member>>>;
+
+/// Find the teams with admin access to a repository, and the users that are admins
+/// without being organization admins or getting it from one of those teams.
+/// `collaborators` are the collaborators of the repository, fetched with the configured backend.
+/// Returns the slugs of the teams and the logins of the users.
+fn find_repo_admins(
+    bootstrap: &Bootstrap,
+    repository: &Repository,
+    collaborators: Result<Vec<Collaborator>, Error>,
+    organization_admins: &HashMap<String, Member>,
+    team_cache: &TeamCache,
+) -> Result<(Vec<String>, Vec<String>), Error> {
+    // Get the teams that have access to the repository
+    let repo_teams = get_repo_teams(bootstrap, &repository.name)?;
+
+    let repo_admin_teams = repo_teams
+        .iter()
+        .filter(|t| t.permissions.as_ref().unwrap().admin)
+        .collect::<Vec<&Team>>();
+
+    // Fill the cache with the members of the teams we haven't seen yet.
+    // Without the members of all admin teams, we could report admins that are legitimate.
+    for team in &repo_teams {
+        if team_cache.lock().unwrap().contains_key(&team.slug) {
+            continue;
+        }
+        // Don't hold the lock while talking to GitHub
+        let team_members = make_paginated_github_request_with_index(
+            bootstrap,
             25,
-            &format!(
-                "/repos/{}/{}/collaborators",
-                &bootstrap.org, repository.name
-            ),
+            &format!("/orgs/{}/teams/{}/members", &bootstrap.org, team.slug),
             3,
             None,
-        ) {
-            Ok(collaborators) => collaborators,
-            Err(e) => {
-                panic!(
-                    "{} {}: {e}",
-                    repository.name.white(),
-                    "I couldn't fetch the repository collaborators".red()
-                );
-            }
-        };
-
-        for collaborator in collaborators {
-            // If this person is a repository admin and not an organization admin
-            if collaborator.permissions.admin
-                && !organization_admins.contains_key(&collaborator.login)
-            {
-                // Check to see if they are a member of a team that gives them admin access
-                if repo_admin_teams.iter().fold(false, |acc, t| {
-                    acc || team_cache[&t.slug].contains_key(&collaborator.login)
-                }) {
-                    continue;
-                }
-
-                println!(
-                    "{} {} {} {}",
-                    "I found an admin user:".yellow(),
-                    collaborator.login.white(),
-                    "on".yellow(),
-                    repository.name.white()
-                );
-            }
-        }
-        progress += 1;
-        if progress % one_percent == 0 {
-            println!("Processed {} reposistories", progress.to_string().blue());
-        }
+        )?;
+        team_cache
+            .lock()
+            .unwrap()
+            .insert(team.slug.clone(), team_members);
     }
+
+    let collaborators = collaborators?;
+
+    let team_cache = team_cache.lock().unwrap();
+    let admin_users = collaborators
+        .into_iter()
+        // If this person is a repository admin and not an organization admin
+        .filter(|c| c.permissions.admin && !organization_admins.contains_key(&c.login))
+        // Check to see if they are a member of a team that gives them admin access
+        .filter(|c| {
+            !repo_admin_teams
+                .iter()
+                .any(|t| team_cache[&t.slug].contains_key(&c.login))
+        })
+        .map(|c| c.login)
+        .collect();
+
+    Ok((
+        repo_admin_teams.iter().map(|t| t.slug.clone()).collect(),
+        admin_users,
+    ))
 }
@@ -0,0 +1,100 @@
+//! A tiny HTTP server for the tests of the code that talks to GitHub. It answers the requests
+//! it gets with canned responses, in order, and records them so the tests can check what was sent.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    thread::{sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// We stop waiting for the next request after this long, so a test that sends fewer requests
+/// than expected fails on its assertions instead of hanging
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A request, as received by the server
+pub(crate) struct Request {
+    pub method: String,
+    /// With the query string, e.g., `/orgs/my-org/members?per_page=100&page=1`
+    pub path: String,
+    /// Lowercase names
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+pub(crate) struct MockServer {
+    /// Where the server listens, e.g., `http://127.0.0.1:41234`
+    pub url: String,
+    handle: JoinHandle<Vec<Request>>,
+}
+
+impl MockServer {
+    /// Answer one request per `(status, body)` in `responses`, then stop
+    pub fn start(responses: Vec<(u16, String)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        let handle = std::thread::spawn(move || {
+            let mut requests = vec![];
+            for (status, body) in responses {
+                let deadline = Instant::now() + ACCEPT_TIMEOUT;
+                let mut stream = loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => break stream,
+                        Err(_) if Instant::now() < deadline => sleep(Duration::from_millis(5)),
+                        Err(_) => return requests,
+                    }
+                };
+                stream.set_nonblocking(false).unwrap();
+
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut parts = line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_string();
+                let path = parts.next().unwrap_or_default().to_string();
+
+                let mut headers = HashMap::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => {
+                            headers.insert(name.to_lowercase(), value.trim().to_string())
+                        }
+                        None => break,
+                    };
+                }
+                let length = headers
+                    .get("content-length")
+                    .and_then(|l| l.parse().ok())
+                    .unwrap_or(0);
+                let mut request_body = vec![0; length];
+                reader.read_exact(&mut request_body).unwrap();
+                requests.push(Request {
+                    method,
+                    path,
+                    headers,
+                    body: String::from_utf8(request_body).unwrap(),
+                });
+
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+            requests
+        });
+
+        Self { url, handle }
+    }
+
+    /// Wait until all the responses were sent (or the server gave up) and return what it received
+    pub fn requests(self) -> Vec<Request> {
+        self.handle.join().unwrap()
+    }
+}
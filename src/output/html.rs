@@ -0,0 +1,560 @@
+//! A single, self-contained HTML file for the people who won't read terminal output:
+//! everything (styles and the script that sorts the tables) is inline, and it links to GitHub
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Write},
+};
+
+use crate::{
+    finding::{self, Finding, Rule, Severity, Target},
+    Error,
+};
+
+use super::{escape, metadata_columns, target_label, AuditResults, Links, SEVERITIES};
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2em auto; max-width: 1200px; color: #1f2328; padding: 0 1em; }
+h1 { margin-bottom: 0; }
+h2 { margin-top: 2em; border-bottom: 1px solid #d1d9e0; padding-bottom: .3em; }
+.meta { color: #59636e; }
+.cards { display: flex; gap: 1em; margin: 1.5em 0; }
+.card { border: 1px solid #d1d9e0; border-radius: 6px; padding: .8em 1.2em; min-width: 7em; }
+.card .count { font-size: 2em; font-weight: 600; }
+table { border-collapse: collapse; width: 100%; margin: 1em 0; font-size: 14px; }
+th, td { border: 1px solid #d1d9e0; padding: 6px 10px; text-align: left; vertical-align: top; }
+th { background: #f6f8fa; cursor: pointer; user-select: none; white-space: nowrap; }
+th.asc::after { content: " \25B2"; }
+th.desc::after { content: " \25BC"; }
+tr:nth-child(even) td { background: #fbfcfd; }
+.sev { font-weight: 600; text-transform: uppercase; font-size: 12px; }
+.critical, .high, .fail { color: #cf222e; }
+.medium { color: #9a6700; }
+.low { color: #0969da; }
+.info, .unknown { color: #59636e; }
+.pass { color: #1a7f37; }
+"#;
+
+/// Clicking a header sorts the table by that column. Cells with a `data-sort` attribute are sorted by it
+/// (e.g., severities, by rank), numbers as numbers and everything else as text
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.sortable th").forEach((th, column) => {
+  th.addEventListener("click", () => {
+    const table = th.closest("table");
+    const body = table.tBodies[0];
+    const ascending = !th.classList.contains("asc");
+    table.querySelectorAll("th").forEach(h => h.classList.remove("asc", "desc"));
+    th.classList.add(ascending ? "asc" : "desc");
+    const key = row => {
+      const cell = row.cells[th.cellIndex];
+      const value = cell.dataset.sort ?? cell.textContent.trim();
+      return value !== "" && !isNaN(value) ? Number(value) : value.toLowerCase();
+    };
+    const rows = Array.from(body.rows).sort((a, b) => {
+      const [x, y] = [key(a), key(b)];
+      return (x < y ? -1 : x > y ? 1 : 0) * (ascending ? 1 : -1);
+    });
+    rows.forEach(row => body.appendChild(row));
+  });
+});
+"#;
+
+/// The rules that have their own section in the report. Findings of other rules are in "Other findings"
+const OWN_SECTION: [&Rule; 12] = [
+    &finding::COMPLIANCE_SCORE,
+    &finding::EC_ACCESS,
+    &finding::EC_ACCESS_CHANGED,
+    &finding::EC_NEW_ACCESS,
+    &finding::DEPLOY_KEY_NON_MEMBER,
+    &finding::DEPLOY_KEY_MEMBER,
+    &finding::ADMIN_TEAM,
+    &finding::ADMIN_USER,
+    &finding::CODEOWNERS_USER_NOT_IN_ORG,
+    &finding::CODEOWNERS_TEAM_NOT_IN_ORG,
+    &finding::CODEOWNERS_EMPTY_TEAM,
+    &finding::CODEOWNERS_GITHUB_ERRORS,
+];
+
+/// `text`, linking to `url` if there is one
+fn link(text: &str, url: Option<String>) -> String {
+    match url {
+        Some(url) => format!(r#"<a href="{}">{}</a>"#, escape(&url), escape(text)),
+        None => escape(text),
+    }
+}
+
+fn severity_cell(severity: Severity) -> String {
+    format!(
+        r#"<td class="sev {severity}" data-sort="{}">{severity}</td>"#,
+        severity as u8
+    )
+}
+
+/// A compliance check, as exported to CSV: pass, fail, or 403 when we could not tell
+fn check_cell(value: &str) -> String {
+    let class = match value {
+        "pass" => "pass",
+        "fail" => "fail",
+        _ => "unknown",
+    };
+    format!(r#"<td class="{class}">{}</td>"#, escape(value))
+}
+
+/// The findings with one of `rules`, with the audit that found them, in the order they were found
+fn with_rules<'a>(
+    findings: &[(&'a str, &'a Finding)],
+    rules: &[&Rule],
+) -> Vec<(&'a str, &'a Finding)> {
+    findings
+        .iter()
+        .filter(|(_, f)| rules.iter().any(|rule| rule.id == f.rule.id))
+        .copied()
+        .collect()
+}
+
+/// Start a section with a sortable table, or say there is nothing to show
+fn table_start(html: &mut String, title: &str, headers: &[&str], empty: bool) -> fmt::Result {
+    writeln!(html, "<h2>{}</h2>", escape(title))?;
+    if empty {
+        return writeln!(html, "<p>Nothing to report.</p>");
+    }
+    write!(html, r#"<table class="sortable"><thead><tr>"#)?;
+    for header in headers {
+        write!(html, "<th>{}</th>", escape(header))?;
+    }
+    writeln!(html, "</tr></thead><tbody>")
+}
+
+fn table_end(html: &mut String, empty: bool) -> fmt::Result {
+    if empty {
+        return Ok(());
+    }
+    writeln!(html, "</tbody></table>")
+}
+
+fn summary(html: &mut String, results: &AuditResults) -> fmt::Result {
+    let mut totals = BTreeMap::new();
+    for (_, result) in results {
+        for finding in result.iter().flat_map(|findings| findings.iter()) {
+            *totals.entry(finding.severity()).or_insert(0) += 1;
+        }
+    }
+    writeln!(html, r#"<h2>Summary</h2><div class="cards">"#)?;
+    for severity in SEVERITIES {
+        writeln!(
+            html,
+            r#"<div class="card"><div class="count {severity}">{}</div><div class="sev">{severity}</div></div>"#,
+            totals.get(&severity).unwrap_or(&0)
+        )?;
+    }
+    writeln!(html, "</div>")?;
+
+    let mut headers = vec!["Audit", "Status"];
+    headers.extend(SEVERITIES.iter().map(|s| s.name()));
+    headers.push("Skipped");
+    table_start(html, "Findings per audit", &headers, results.is_empty())?;
+    for (audit, result) in results {
+        write!(html, "<tr><td>{}</td>", escape(audit))?;
+        match result {
+            Ok(findings) => {
+                let mut counts = HashMap::new();
+                for finding in findings.iter() {
+                    *counts.entry(finding.severity()).or_insert(0) += 1;
+                }
+                write!(html, r#"<td class="pass">done</td>"#)?;
+                for severity in SEVERITIES {
+                    write!(html, "<td>{}</td>", counts.get(&severity).unwrap_or(&0))?;
+                }
+                writeln!(html, "<td>{}</td></tr>", findings.skipped().len())?;
+            }
+            Err(e) => {
+                write!(
+                    html,
+                    r#"<td class="fail">{}</td>"#,
+                    escape(&format!("failed: {e}"))
+                )?;
+                for _ in SEVERITIES {
+                    write!(html, "<td></td>")?;
+                }
+                writeln!(html, "<td></td></tr>")?;
+            }
+        }
+    }
+    table_end(html, results.is_empty())?;
+
+    let skipped: Vec<(&str, &str, &Error)> = results
+        .iter()
+        .filter_map(|(audit, result)| result.as_ref().ok().map(|f| (audit, f)))
+        .flat_map(|(audit, findings)| {
+            findings
+                .skipped()
+                .iter()
+                .map(move |(target, error)| (*audit, target.as_str(), error))
+        })
+        .collect();
+    if !skipped.is_empty() {
+        table_start(
+            html,
+            "Skipped (the findings of these audits may be incomplete)",
+            &["Audit", "Target", "Error"],
+            false,
+        )?;
+        for (audit, target, error) in skipped {
+            writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(audit),
+                escape(target),
+                escape(&error.to_string())
+            )?;
+        }
+        table_end(html, false)?;
+    }
+    Ok(())
+}
+
+fn compliance(html: &mut String, findings: &[(&str, &Finding)], links: &Links) -> fmt::Result {
+    let scores = with_rules(findings, &[&finding::COMPLIANCE_SCORE]);
+    // The checks that were selected are the same for all repositories
+    let checks: Vec<&str> = crate::compliance::CHECK_NAMES
+        .iter()
+        .copied()
+        .filter(|check| {
+            scores
+                .first()
+                .is_some_and(|(_, f)| f.evidence.contains_key(*check))
+        })
+        .collect();
+    let mut headers = vec!["Repository", "Branch", "Visibility", "Score", "Max"];
+    headers.extend(&checks);
+    table_start(html, "Compliance scores", &headers, scores.is_empty())?;
+    for (_, finding) in &scores {
+        let repo = finding.target.repo().unwrap_or_default();
+        let evidence = |key: &str| finding.evidence.get(key).map_or("", |v| v.as_str());
+        write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>",
+            link(repo, Some(links.branches(repo))),
+            escape(evidence("branch")),
+            escape(evidence("visibility")),
+            escape(evidence("score")),
+            escape(evidence("max_score")),
+        )?;
+        for check in &checks {
+            write!(html, "{}", check_cell(evidence(check)))?;
+        }
+        writeln!(html, "</tr>")?;
+    }
+    table_end(html, scores.is_empty())
+}
+
+fn external_collaborators(
+    html: &mut String,
+    findings: &[(&str, &Finding)],
+    links: &Links,
+) -> fmt::Result {
+    let access = with_rules(findings, &[&finding::EC_ACCESS]);
+    // What changed since the previous run, for each user and repository
+    let changes: HashMap<&Target, &Finding> = with_rules(
+        findings,
+        &[&finding::EC_ACCESS_CHANGED, &finding::EC_NEW_ACCESS],
+    )
+    .into_iter()
+    .map(|(_, f)| (&f.target, f))
+    .collect();
+    // The accesses whose approval is missing, unrecognized or expired
+    let unapproved: HashSet<&Target> = with_rules(
+        findings,
+        &[
+            &finding::EC_APPROVAL_MISSING,
+            &finding::EC_APPROVAL_UNRECOGNIZED,
+            &finding::EC_APPROVAL_EXPIRED,
+        ],
+    )
+    .into_iter()
+    .map(|(_, f)| &f.target)
+    .collect();
+    let metadata = metadata_columns(access.iter().map(|(_, f)| *f));
+    let mut headers = vec![
+        "User",
+        "Repository",
+        "Access",
+        "Status",
+        "Approver",
+        "Expires",
+    ];
+    headers.extend(&metadata);
+    headers.push("Since the previous run");
+    table_start(html, "External collaborators", &headers, access.is_empty())?;
+    for (_, finding) in &access {
+        let Target::RepositoryUser { repo, login } = &finding.target else {
+            continue;
+        };
+        let evidence = |key: &str| escape(finding.evidence.get(key).map_or("", |v| v.as_str()));
+        let status = evidence("status");
+        let status = match status.as_str() {
+            "" => r#"<td class="fail">not approved</td>"#.to_string(),
+            _ if unapproved.contains(&finding.target) => {
+                format!(r#"<td class="fail">{status}</td>"#)
+            }
+            _ => format!(r#"<td class="pass">{status}</td>"#),
+        };
+        let change = match changes.get(&finding.target) {
+            Some(change) => format!(
+                r#"<td class="{}">{}</td>"#,
+                change.severity(),
+                escape(&change.message)
+            ),
+            None => "<td></td>".to_string(),
+        };
+        write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td>{status}<td>{}</td><td>{}</td>",
+            link(login, Some(links.user(login))),
+            link(repo, Some(links.collaborators(repo))),
+            evidence("access"),
+            evidence("approver"),
+            evidence("expiry_date"),
+        )?;
+        for column in &metadata {
+            write!(html, "<td>{}</td>", evidence(column))?;
+        }
+        writeln!(html, "{change}</tr>")?;
+    }
+    table_end(html, access.is_empty())
+}
+
+fn deploy_keys(html: &mut String, findings: &[(&str, &Finding)], links: &Links) -> fmt::Result {
+    let keys = with_rules(
+        findings,
+        &[&finding::DEPLOY_KEY_NON_MEMBER, &finding::DEPLOY_KEY_MEMBER],
+    );
+    table_start(
+        html,
+        "Deploy keys",
+        &[
+            "Severity",
+            "Repository",
+            "Key",
+            "Added by",
+            "Read only",
+            "Created",
+            "Last used",
+        ],
+        keys.is_empty(),
+    )?;
+    for (_, finding) in &keys {
+        let Target::DeployKey { repo, title, .. } = &finding.target else {
+            continue;
+        };
+        let evidence = |key: &str| escape(finding.evidence.get(key).map_or("", |v| v.as_str()));
+        writeln!(
+            html,
+            "<tr>{}<td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            severity_cell(finding.severity()),
+            link(repo, Some(links.repo(repo))),
+            link(title, Some(links.deploy_keys(repo))),
+            link(
+                &evidence("added_by"),
+                Some(links.user(finding.evidence.get("added_by").map_or("", |v| v.as_str())))
+            ),
+            evidence("read_only"),
+            evidence("created_at"),
+            evidence("last_used"),
+        )?;
+    }
+    table_end(html, keys.is_empty())
+}
+
+/// A table of findings with a severity, a target (linked to GitHub), the message and, optionally, the remediation
+fn findings_table(
+    html: &mut String,
+    title: &str,
+    findings: &[(&str, &Finding)],
+    links: &Links,
+    with_audit: bool,
+) -> fmt::Result {
+    let headers: &[&str] = if with_audit {
+        &[
+            "Severity",
+            "Rule",
+            "Audit",
+            "Target",
+            "Finding",
+            "Remediation",
+        ]
+    } else {
+        &["Severity", "Rule", "Target", "Finding"]
+    };
+    table_start(html, title, headers, findings.is_empty())?;
+    for (audit, finding) in findings {
+        write!(
+            html,
+            "<tr>{}<td>{}</td>",
+            severity_cell(finding.severity()),
+            escape(finding.rule_id())
+        )?;
+        if with_audit {
+            write!(html, "<td>{}</td>", escape(audit))?;
+        }
+        write!(
+            html,
+            "<td>{}</td><td>{}</td>",
+            link(
+                &target_label(&finding.target),
+                links.target(&finding.target)
+            ),
+            escape(&finding.message)
+        )?;
+        if with_audit {
+            write!(html, "<td>{}</td>", escape(finding.remediation()))?;
+        }
+        writeln!(html, "</tr>")?;
+    }
+    table_end(html, findings.is_empty())
+}
+
+/// Write the report. Sections are only shown for the audits that ran
+pub(super) fn report(
+    out: &mut impl std::io::Write,
+    org: &str,
+    generated_at: &str,
+    links: &Links,
+    results: &AuditResults,
+) -> Result<(), Error> {
+    let ran = |audit: &str| results.iter().any(|(name, _)| *name == audit);
+    let findings: Vec<(&str, &Finding)> = results
+        .iter()
+        .filter_map(|(audit, result)| result.as_ref().ok().map(|f| (*audit, f)))
+        .flat_map(|(audit, findings)| findings.iter().map(move |f| (audit, f)))
+        .collect();
+
+    let mut html = String::new();
+    let mut write = || -> fmt::Result {
+        writeln!(
+            html,
+            r#"<!DOCTYPE html><html lang="en"><head><meta charset="utf-8"><title>GitHub audit of {org}</title><style>{STYLE}</style></head><body>"#,
+            org = escape(org)
+        )?;
+        writeln!(
+            html,
+            r#"<h1>GitHub audit of {}</h1><p class="meta">Generated on {} by {} {}</p>"#,
+            link(org, Some(links.org())),
+            escape(generated_at),
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        summary(&mut html, results)?;
+        if ran("compliance") {
+            compliance(&mut html, &findings, links)?;
+        }
+        if ran("ec") {
+            external_collaborators(&mut html, &findings, links)?;
+        }
+        if ran("deploy-keys") {
+            deploy_keys(&mut html, &findings, links)?;
+        }
+        if ran("admins") {
+            let admins = with_rules(&findings, &[&finding::ADMIN_TEAM, &finding::ADMIN_USER]);
+            findings_table(&mut html, "Repository admins", &admins, links, false)?;
+        }
+        if ran("codeowners") {
+            let codeowners = with_rules(
+                &findings,
+                &[
+                    &finding::CODEOWNERS_USER_NOT_IN_ORG,
+                    &finding::CODEOWNERS_TEAM_NOT_IN_ORG,
+                    &finding::CODEOWNERS_EMPTY_TEAM,
+                    &finding::CODEOWNERS_GITHUB_ERRORS,
+                ],
+            );
+            findings_table(&mut html, "CODEOWNERS problems", &codeowners, links, false)?;
+        }
+        // Everything else, but what the audits only list
+        let others: Vec<(&str, &Finding)> = findings
+            .iter()
+            .filter(|(_, f)| {
+                f.severity() != Severity::Info && !OWN_SECTION.iter().any(|r| r.id == f.rule.id)
+            })
+            .copied()
+            .collect();
+        if !others.is_empty() {
+            findings_table(&mut html, "Other findings", &others, links, true)?;
+        }
+        writeln!(html, "<script>{SCRIPT}</script></body></html>")
+    };
+    write().map_err(|e| Error::Config(format!("Could not generate the HTML report: {e}")))?;
+    out.write_all(html.as_bytes())
+        .map_err(|e| Error::Config(format!("Could not write the HTML report: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Findings;
+
+    fn links() -> Links {
+        Links {
+            web: "https://github.com".to_string(),
+            org: "my-org".to_string(),
+        }
+    }
+
+    #[test]
+    fn escape_makes_text_safe_in_elements_and_attributes() {
+        assert_eq!(
+            escape(r#"<img src="x" onerror='alert(1)'> & co"#),
+            "&lt;img src=&quot;x&quot; onerror=&#39;alert(1)&#39;&gt; &amp; co"
+        );
+        assert_eq!(escape("plain-repo_1.0"), "plain-repo_1.0");
+    }
+
+    #[test]
+    fn links_escape_their_text_and_url() {
+        assert_eq!(
+            link(
+                "<b>",
+                Some(r#"https://github.com/o/r?a=1&b="2""#.to_string())
+            ),
+            r#"<a href="https://github.com/o/r?a=1&amp;b=&quot;2&quot;">&lt;b&gt;</a>"#
+        );
+        assert_eq!(link("a & b", None), "a &amp; b");
+    }
+
+    #[test]
+    fn report_escapes_what_comes_from_github() {
+        let mut findings = Findings::default();
+        findings.record_quietly(Finding::new(
+            &finding::ADMIN_USER,
+            Target::RepositoryUser {
+                repo: "<script>alert(1)</script>".to_string(),
+                login: r#"eve"onclick="x"#.to_string(),
+            },
+            "eve & co are <admins>",
+        ));
+        let results: AuditResults = vec![("admins", Ok(findings))];
+        let mut out = vec![];
+        report(
+            &mut out,
+            "my<org>",
+            "2026-10-17T00:00:00Z",
+            &links(),
+            &results,
+        )
+        .unwrap();
+        let html = String::from_utf8(out).unwrap();
+
+        assert!(!html.contains("<script>alert"), "{html}");
+        assert!(!html.contains(r#"eve"onclick"#), "{html}");
+        assert!(html.contains(
+            "user eve&quot;onclick=&quot;x on &lt;script&gt;alert(1)&lt;/script&gt;</a>"
+        ));
+        assert!(html.contains(
+            r#"href="https://github.com/my-org/&lt;script&gt;alert(1)&lt;/script&gt;/settings/access""#
+        ));
+        assert!(html.contains("<td>eve &amp; co are &lt;admins&gt;</td>"));
+        assert!(html.contains("<title>GitHub audit of my&lt;org&gt;</title>"));
+        // The only script is ours
+        assert_eq!(html.matches("<script>").count(), 1);
+    }
+}
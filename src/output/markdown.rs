@@ -0,0 +1,447 @@
+//! A Markdown report, to be pasted as is in a GitHub issue or a wiki page.
+//! It uses GitHub Flavored Markdown: tables, and `<details>` for long lists
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::{self, Write},
+};
+
+use crate::{
+    compliance::{check_from_csv_value, check_symbol, CHECK_NAMES},
+    finding::{self, Finding, Rule, Severity, Target},
+    Error,
+};
+
+use super::{metadata_columns, target_label, AuditResults, Links, SEVERITIES};
+
+/// Tables with more rows than this are collapsed, so the report stays readable
+const MAX_VISIBLE_ROWS: usize = 10;
+
+/// The rules whose findings are in the tables of the compliance, ec and deploy-keys sections.
+/// The other findings of these audits are listed after the table
+const TABLED: [&Rule; 14] = [
+    &finding::COMPLIANCE_PR_ONE_APPROVAL,
+    &finding::COMPLIANCE_PR_DISMISS_STALE,
+    &finding::COMPLIANCE_PR_REQUIRE_CODE_OWNER,
+    &finding::COMPLIANCE_DISABLE_FORCE_PUSH,
+    &finding::COMPLIANCE_DISABLE_DELETION,
+    &finding::COMPLIANCE_REQUIRE_SIGNED_COMMITS,
+    &finding::COMPLIANCE_REQUIRE_STATUS_CHECKS,
+    &finding::COMPLIANCE_CODEOWNERS_VALID,
+    &finding::COMPLIANCE_SCORE,
+    &finding::EC_ACCESS_CHANGED,
+    &finding::EC_NEW_ACCESS,
+    &finding::EC_ACCESS,
+    &finding::DEPLOY_KEY_NON_MEMBER,
+    &finding::DEPLOY_KEY_MEMBER,
+];
+
+/// Make text safe for a table cell: pipes would end the cell, new lines the row,
+/// and GitHub would render anything that looks like HTML
+fn cell(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace(['\r', '\n'], " ")
+}
+
+/// `text`, linking to `url` if there is one
+fn link(text: &str, url: Option<String>) -> String {
+    let text = cell(text).replace('[', "\\[").replace(']', "\\]");
+    match url {
+        Some(url) => format!("[{text}]({})", url.replace(' ', "%20").replace(')', "%29")),
+        None => text,
+    }
+}
+
+/// A table. Long ones are collapsed behind a summary
+fn table(md: &mut String, headers: &[&str], rows: &[Vec<String>]) -> fmt::Result {
+    if rows.is_empty() {
+        return writeln!(md, "Nothing to report.\n");
+    }
+    if rows.len() > MAX_VISIBLE_ROWS {
+        // The blank line after the summary is needed for GitHub to render the Markdown inside
+        writeln!(
+            md,
+            "<details><summary>Show all {} rows</summary>\n",
+            rows.len()
+        )?;
+    }
+    writeln!(md, "| {} |", headers.join(" | "))?;
+    writeln!(md, "|{}", " --- |".repeat(headers.len()))?;
+    for row in rows {
+        writeln!(md, "| {} |", row.join(" | "))?;
+    }
+    if rows.len() > MAX_VISIBLE_ROWS {
+        writeln!(md, "\n</details>")?;
+    }
+    writeln!(md)
+}
+
+fn severity_label(severity: Severity) -> String {
+    format!("**{}**", severity.name().to_uppercase())
+}
+
+fn evidence<'a>(finding: &'a Finding, key: &str) -> &'a str {
+    finding.evidence.get(key).map_or("", |v| v.as_str())
+}
+
+fn summary(md: &mut String, results: &AuditResults) -> fmt::Result {
+    writeln!(md, "## Summary\n")?;
+    let mut headers = vec!["Audit", "Status"];
+    headers.extend(SEVERITIES.iter().map(|s| s.name()));
+    headers.push("skipped");
+    let mut rows = vec![];
+    let mut totals = BTreeMap::new();
+    for (audit, result) in results {
+        let mut row = vec![format!("`{audit}`")];
+        match result {
+            Ok(findings) => {
+                let mut counts = HashMap::new();
+                for finding in findings.iter() {
+                    *counts.entry(finding.severity()).or_insert(0) += 1;
+                    *totals.entry(finding.severity()).or_insert(0) += 1;
+                }
+                row.push("✅ done".to_string());
+                row.extend(
+                    SEVERITIES
+                        .iter()
+                        .map(|s| counts.get(s).unwrap_or(&0).to_string()),
+                );
+                row.push(findings.skipped().len().to_string());
+            }
+            Err(e) => {
+                row.push(format!("❌ failed: {}", cell(&e.to_string())));
+                row.extend(std::iter::repeat_n(String::new(), SEVERITIES.len() + 1));
+            }
+        }
+        rows.push(row);
+    }
+    let totals: Vec<String> = SEVERITIES
+        .iter()
+        .map(|s| format!("{} {}", totals.get(s).unwrap_or(&0), s.name()))
+        .collect();
+    writeln!(md, "Findings: {}.\n", totals.join(", "))?;
+    table(md, &headers, &rows)?;
+
+    let skipped: Vec<Vec<String>> = results
+        .iter()
+        .filter_map(|(audit, result)| result.as_ref().ok().map(|f| (audit, f)))
+        .flat_map(|(audit, findings)| {
+            findings.skipped().iter().map(move |(target, error)| {
+                vec![format!("`{audit}`"), cell(target), cell(&error.to_string())]
+            })
+        })
+        .collect();
+    if !skipped.is_empty() {
+        writeln!(
+            md,
+            "<details><summary>{} targets were skipped, so the findings of their audits may be incomplete</summary>\n",
+            skipped.len()
+        )?;
+        table(md, &["Audit", "Target", "Error"], &skipped)?;
+        writeln!(md, "</details>\n")?;
+    }
+    Ok(())
+}
+
+fn compliance(md: &mut String, findings: &[&Finding], links: &Links) -> fmt::Result {
+    let scores: Vec<&&Finding> = findings
+        .iter()
+        .filter(|f| f.rule.id == finding::COMPLIANCE_SCORE.id)
+        .collect();
+    // The checks that were selected are the same for all repositories
+    let checks: Vec<&str> = CHECK_NAMES
+        .iter()
+        .copied()
+        .filter(|check| {
+            scores
+                .first()
+                .is_some_and(|f| f.evidence.contains_key(*check))
+        })
+        .collect();
+    let mut headers = vec!["Repository", "Branch", "Score"];
+    headers.extend(&checks);
+    let rows: Vec<Vec<String>> = scores
+        .iter()
+        .map(|finding| {
+            let repo = finding.target.repo().unwrap_or_default();
+            let mut row = vec![
+                link(repo, Some(links.branches(repo))),
+                cell(evidence(finding, "branch")),
+                format!(
+                    "{}/{}",
+                    evidence(finding, "score"),
+                    evidence(finding, "max_score")
+                ),
+            ];
+            row.extend(
+                checks
+                    .iter()
+                    .map(|check| check_symbol(check_from_csv_value(evidence(finding, check)))),
+            );
+            row
+        })
+        .collect();
+    table(md, &headers, &rows)
+}
+
+fn external_collaborators(md: &mut String, findings: &[&Finding], links: &Links) -> fmt::Result {
+    // What changed since the previous run, for each user and repository
+    let changes: HashMap<&Target, &Finding> = findings
+        .iter()
+        .filter(|f| {
+            [&finding::EC_ACCESS_CHANGED, &finding::EC_NEW_ACCESS]
+                .iter()
+                .any(|rule| rule.id == f.rule.id)
+        })
+        .map(|f| (&f.target, *f))
+        .collect();
+    // The accesses whose approval is missing, unrecognized or expired
+    let unapproved: HashSet<&Target> = findings
+        .iter()
+        .filter(|f| {
+            [
+                &finding::EC_APPROVAL_MISSING,
+                &finding::EC_APPROVAL_UNRECOGNIZED,
+                &finding::EC_APPROVAL_EXPIRED,
+            ]
+            .iter()
+            .any(|rule| rule.id == f.rule.id)
+        })
+        .map(|f| &f.target)
+        .collect();
+    let access: Vec<&Finding> = findings
+        .iter()
+        .filter(|f| f.rule.id == finding::EC_ACCESS.id)
+        .copied()
+        .collect();
+    let metadata = metadata_columns(access.iter().copied());
+    let rows: Vec<Vec<String>> = access
+        .iter()
+        .filter_map(|finding| match &finding.target {
+            Target::RepositoryUser { repo, login } => Some(
+                vec![
+                    link(login, Some(links.user(login))),
+                    link(repo, Some(links.collaborators(repo))),
+                    cell(evidence(finding, "access")),
+                    match evidence(finding, "status") {
+                        "" => "❌ not approved".to_string(),
+                        status if unapproved.contains(&finding.target) => {
+                            format!("❌ {}", cell(status))
+                        }
+                        status => format!("✅ {}", cell(status)),
+                    },
+                    cell(evidence(finding, "approver")),
+                    cell(evidence(finding, "expiry_date")),
+                ]
+                .into_iter()
+                .chain(
+                    metadata
+                        .iter()
+                        .map(|column| cell(evidence(finding, column))),
+                )
+                .chain([changes
+                    .get(&finding.target)
+                    .map_or(String::new(), |change| cell(&change.message))])
+                .collect(),
+            ),
+            _ => None,
+        })
+        .collect();
+    let mut headers = vec![
+        "User",
+        "Repository",
+        "Access",
+        "Status",
+        "Approver",
+        "Expires",
+    ];
+    headers.extend(&metadata);
+    headers.push("Since the previous run");
+    table(md, &headers, &rows)
+}
+
+fn deploy_keys(md: &mut String, findings: &[&Finding], links: &Links) -> fmt::Result {
+    let rows: Vec<Vec<String>> = findings
+        .iter()
+        .filter_map(|finding| match &finding.target {
+            Target::DeployKey { repo, title, .. } => {
+                let added_by = evidence(finding, "added_by");
+                Some(vec![
+                    severity_label(finding.severity()),
+                    link(repo, Some(links.repo(repo))),
+                    link(title, Some(links.deploy_keys(repo))),
+                    link(added_by, Some(links.user(added_by))),
+                    cell(evidence(finding, "read_only")),
+                    cell(evidence(finding, "last_used")),
+                ])
+            }
+            _ => None,
+        })
+        .collect();
+    table(
+        md,
+        &[
+            "Severity",
+            "Repository",
+            "Key",
+            "Added by",
+            "Read only",
+            "Last used",
+        ],
+        &rows,
+    )
+}
+
+/// The findings of an audit that has no table of its own. What it only lists (`info`) is collapsed
+fn findings_list(md: &mut String, findings: &[&Finding], links: &Links) -> fmt::Result {
+    let row = |finding: &&Finding| {
+        vec![
+            severity_label(finding.severity()),
+            format!("`{}`", finding.rule_id()),
+            link(
+                &target_label(&finding.target),
+                links.target(&finding.target),
+            ),
+            cell(&finding.message),
+        ]
+    };
+    let headers = ["Severity", "Rule", "Target", "Finding"];
+    let (listed, problems): (Vec<&Finding>, Vec<&Finding>) = findings
+        .iter()
+        .partition(|f| f.severity() == Severity::Info);
+    if !problems.is_empty() || listed.is_empty() {
+        let rows: Vec<Vec<String>> = problems.iter().map(row).collect();
+        table(md, &headers, &rows)?;
+    }
+    if !listed.is_empty() {
+        writeln!(
+            md,
+            "<details><summary>{} more, for information</summary>\n",
+            listed.len()
+        )?;
+        for finding in listed {
+            writeln!(
+                md,
+                "- {}: {}",
+                link(
+                    &target_label(&finding.target),
+                    links.target(&finding.target)
+                ),
+                cell(&finding.message)
+            )?;
+        }
+        writeln!(md, "\n</details>\n")?;
+    }
+    Ok(())
+}
+
+/// Write the report, with a section per audit that ran
+pub(super) fn report(
+    out: &mut impl std::io::Write,
+    org: &str,
+    generated_at: &str,
+    links: &Links,
+    results: &AuditResults,
+) -> Result<(), Error> {
+    let mut md = String::new();
+    let mut write = || -> fmt::Result {
+        writeln!(md, "# GitHub audit of {}\n", link(org, Some(links.org())))?;
+        writeln!(
+            md,
+            "_Generated on {generated_at} by {} {}._\n",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )?;
+        summary(&mut md, results)?;
+        for (audit, result) in results {
+            writeln!(md, "## `{audit}`\n")?;
+            let findings: Vec<&Finding> = match result {
+                Ok(findings) => findings.iter().collect(),
+                Err(e) => {
+                    writeln!(md, "❌ The audit failed: {}\n", cell(&e.to_string()))?;
+                    continue;
+                }
+            };
+            match *audit {
+                "compliance" => compliance(&mut md, &findings, links)?,
+                "ec" => external_collaborators(&mut md, &findings, links)?,
+                "deploy-keys" => deploy_keys(&mut md, &findings, links)?,
+                _ => {
+                    findings_list(&mut md, &findings, links)?;
+                    continue;
+                }
+            }
+            let others: Vec<&Finding> = findings
+                .into_iter()
+                .filter(|f| !TABLED.iter().any(|rule| rule.id == f.rule.id))
+                .collect();
+            if !others.is_empty() {
+                findings_list(&mut md, &others, links)?;
+            }
+        }
+        Ok(())
+    };
+    write().map_err(|e| Error::Config(format!("Could not generate the Markdown report: {e}")))?;
+    out.write_all(md.as_bytes())
+        .map_err(|e| Error::Config(format!("Could not write the Markdown report: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links() -> Links {
+        Links {
+            web: "https://github.com".to_string(),
+            org: "my-org".to_string(),
+        }
+    }
+
+    #[test]
+    fn cells_keep_pipes_new_lines_and_html_out_of_the_table() {
+        assert_eq!(
+            cell("a|b\nc\r\nd <img src=x>"),
+            "a\\|b c  d &lt;img src=x&gt;"
+        );
+        assert_eq!(cell("plain text"), "plain text");
+    }
+
+    #[test]
+    fn links_escape_their_text_and_url() {
+        assert_eq!(
+            link("[x|y]", Some("https://github.com/o/a b)".to_string())),
+            "[\\[x\\|y\\]](https://github.com/o/a%20b%29)"
+        );
+        assert_eq!(link("a|b", None), "a\\|b");
+    }
+
+    #[test]
+    fn table_rows_have_one_cell_per_column() {
+        let finding = Finding::new(
+            &finding::ADMIN_USER,
+            Target::RepositoryUser {
+                repo: "api".to_string(),
+                login: "eve|mallory".to_string(),
+            },
+            "admin of api | web\nand more",
+        );
+        let mut md = String::new();
+        findings_list(&mut md, &[&finding], &links()).unwrap();
+
+        assert_eq!(
+            md,
+            "\
+| Severity | Rule | Target | Finding |
+| --- | --- | --- | --- |
+| **MEDIUM** | `ADM002` | [user eve\\|mallory on api](https://github.com/my-org/api/settings/access) | admin of api \\| web and more |
+
+"
+        );
+        // Each row splits in as many cells as there are columns, once the escaped pipes are put aside
+        for line in md.lines().filter(|line| line.starts_with('|')) {
+            assert_eq!(line.replace("\\|", "").matches('|').count(), 5, "{line}");
+        }
+    }
+}
@@ -0,0 +1,577 @@
+mod html;
+mod markdown;
+mod sarif;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+use crate::{
+    external_collaborator::ACCESS_EVIDENCE,
+    finding::{Finding, Severity, Target},
+    logger, Bootstrap, Error, Findings,
+};
+
+/// The version of the JSON and NDJSON output. It changes only when a field is removed or changes meaning:
+/// new fields can be added without a new version, so consumers should ignore the fields they don't know
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How the results of the audits are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Colored lines, as the audits go
+    #[default]
+    Text,
+    /// A single JSON document at the end of the run
+    Json,
+    /// One JSON object per line: a `run` line, then one line per finding, skipped target and failed audit
+    Ndjson,
+    /// A SARIF 2.1.0 log at the end of the run, for GitHub code scanning and SARIF viewers
+    Sarif,
+}
+
+/// A report for people, written at the end of the run instead of the data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Report {
+    /// A single HTML file with a summary and sortable tables, which links to GitHub
+    Html,
+    /// A Markdown document with a section per audit, to paste in a GitHub issue or wiki page
+    Markdown,
+}
+
+/// Where the data goes: stdout, or the file given with `--output`. Diagnostics never go here, they are logged to stderr
+struct Destination {
+    format: Format,
+    report: Option<Report>,
+    out: Box<dyn Write + Send>,
+    colors: bool,
+}
+
+static DESTINATION: Mutex<Option<Destination>> = Mutex::new(None);
+
+/// Send the data to `path` instead of stdout, in `format`, or only a `report` at the end.
+/// Until this is called, text goes to stdout
+pub fn open(format: Format, report: Option<Report>, path: Option<&Path>) -> Result<(), Error> {
+    let destination = match path {
+        Some(path) => {
+            let file = File::create(path)
+                .map_err(|e| Error::Config(format!("Could not create {}: {e}", path.display())))?;
+            Destination {
+                format,
+                report,
+                out: Box::new(BufWriter::new(file)),
+                colors: false,
+            }
+        }
+        None => Destination {
+            format,
+            report,
+            out: Box::new(std::io::stdout()),
+            colors: logger::use_colors(&std::io::stdout()),
+        },
+    };
+    *DESTINATION.lock().unwrap() = Some(destination);
+    Ok(())
+}
+
+impl Destination {
+    fn writes_lines(&self) -> bool {
+        self.format == Format::Text && self.report.is_none()
+    }
+}
+
+/// Whether the lines of data given to [`line`] are written, rather than dropped for another format or a report
+pub fn writes_lines() -> bool {
+    with_destination(|destination| destination.writes_lines())
+}
+
+/// Run `f` with the destination of the data, opening stdout if nothing was opened yet
+fn with_destination<T>(f: impl FnOnce(&mut Destination) -> T) -> T {
+    let mut destination = DESTINATION.lock().unwrap();
+    let destination = destination.get_or_insert_with(|| Destination {
+        format: Format::Text,
+        report: None,
+        out: Box::new(std::io::stdout()),
+        colors: logger::use_colors(&std::io::stdout()),
+    });
+    f(destination)
+}
+
+/// Write a line of data (e.g., a finding or a CSV) in the text format. It is dropped when we write another format
+/// or a report, where the same data is in the findings. Colors are removed unless we are writing to a terminal
+pub fn line(line: impl Display) {
+    with_destination(|destination| {
+        if !destination.writes_lines() {
+            return;
+        }
+        let line = line.to_string();
+        let line = match destination.colors {
+            true => line,
+            false => strip_colors(&line),
+        };
+        if let Err(e) = writeln!(destination.out, "{line}") {
+            log::error!("{}", write_error(e));
+        }
+    })
+}
+
+/// Remove the ANSI escape sequences that `colored` adds
+fn strip_colors(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip everything up to the final letter of the sequence, e.g., `\x1b[1;31m`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+/// The outcome of each audit of a run, in the order they ran
+pub type AuditResults = Vec<(&'static str, Result<Findings, Error>)>;
+
+#[derive(Serialize)]
+struct Tool {
+    name: &'static str,
+    version: &'static str,
+}
+
+/// What is common to the whole run
+#[derive(Serialize)]
+struct Run<'a> {
+    tool: Tool,
+    org: &'a str,
+    /// RFC 3339, in UTC
+    generated_at: &'a str,
+    /// The audits that were run, including the ones that failed
+    audits: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct FindingRecord<'a> {
+    audit: &'a str,
+    rule_id: &'a str,
+    rule: &'a str,
+    severity: Severity,
+    target: &'a Target,
+    message: &'a str,
+    evidence: &'a BTreeMap<String, String>,
+    remediation: &'a str,
+}
+
+/// A target (e.g., a repository) the audit could not look at, so its findings may be incomplete
+#[derive(Serialize)]
+struct SkippedRecord<'a> {
+    audit: &'a str,
+    target: &'a str,
+    error: String,
+}
+
+/// An audit that could not run at all
+#[derive(Serialize)]
+struct FailedAuditRecord<'a> {
+    audit: &'a str,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    run: Run<'a>,
+    findings: Vec<FindingRecord<'a>>,
+    skipped: Vec<SkippedRecord<'a>>,
+    failed_audits: Vec<FailedAuditRecord<'a>>,
+}
+
+/// A line of NDJSON output. `type` tells which kind of record the rest of the line is
+#[derive(Serialize)]
+struct Line<T: Serialize> {
+    schema_version: u32,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    record: T,
+}
+
+fn records(
+    results: &AuditResults,
+) -> (
+    Vec<FindingRecord<'_>>,
+    Vec<SkippedRecord<'_>>,
+    Vec<FailedAuditRecord<'_>>,
+) {
+    let mut findings = vec![];
+    let mut skipped = vec![];
+    let mut failed = vec![];
+    for (audit, result) in results {
+        match result {
+            Ok(audit_findings) => {
+                findings.extend(audit_findings.iter().map(|f| FindingRecord {
+                    audit,
+                    rule_id: f.rule.id,
+                    rule: f.rule.name,
+                    severity: f.severity(),
+                    target: &f.target,
+                    message: &f.message,
+                    evidence: &f.evidence,
+                    remediation: f.remediation(),
+                }));
+                skipped.extend(audit_findings.skipped().iter().map(|(target, error)| {
+                    SkippedRecord {
+                        audit,
+                        target,
+                        error: error.to_string(),
+                    }
+                }));
+            }
+            Err(e) => failed.push(FailedAuditRecord {
+                audit,
+                error: e.to_string(),
+            }),
+        }
+    }
+    (findings, skipped, failed)
+}
+
+fn write_error(e: std::io::Error) -> Error {
+    Error::Config(format!("Could not write the results: {e}"))
+}
+
+/// Write a record as a line of NDJSON
+fn write_line(
+    out: &mut impl Write,
+    kind: &'static str,
+    record: impl Serialize,
+) -> Result<(), Error> {
+    let line = Line {
+        schema_version: SCHEMA_VERSION,
+        kind,
+        record,
+    };
+    serde_json::to_writer(&mut *out, &line)?;
+    writeln!(out).map_err(write_error)
+}
+
+/// The severities, most severe first, which is how they are shown
+const SEVERITIES: [Severity; 5] = [
+    Severity::Critical,
+    Severity::High,
+    Severity::Medium,
+    Severity::Low,
+    Severity::Info,
+];
+
+/// How a target is shown in a table. Files are shown by their path rather than by their (long) URL
+fn target_label(target: &Target) -> String {
+    match target {
+        Target::File {
+            repo,
+            path,
+            line: Some(line),
+            ..
+        } => format!("{repo}/{path}:{line}"),
+        Target::File { repo, path, .. } => format!("{repo}/{path}"),
+        target => target.to_string(),
+    }
+}
+
+/// The metadata columns of the ec CSV (e.g., a ticket), found in the evidence of the access findings
+fn metadata_columns<'a>(access: impl IntoIterator<Item = &'a Finding>) -> Vec<&'a str> {
+    let mut columns: Vec<&str> = access
+        .into_iter()
+        .flat_map(|finding| finding.evidence.keys())
+        .map(String::as_str)
+        .filter(|key| !ACCESS_EVIDENCE.contains(key))
+        .collect();
+    columns.sort_unstable();
+    columns.dedup();
+    columns
+}
+
+/// Links to the pages of the org on GitHub
+struct Links {
+    web: String,
+    org: String,
+}
+
+impl Links {
+    fn org(&self) -> String {
+        format!("{}/{}", self.web, self.org)
+    }
+
+    fn repo(&self, repo: &str) -> String {
+        format!("{}/{}/{repo}", self.web, self.org)
+    }
+
+    /// Where the protection of the branches is set
+    fn branches(&self, repo: &str) -> String {
+        format!("{}/settings/branches", self.repo(repo))
+    }
+
+    /// Where the access of collaborators and teams is managed
+    fn collaborators(&self, repo: &str) -> String {
+        format!("{}/settings/access", self.repo(repo))
+    }
+
+    fn deploy_keys(&self, repo: &str) -> String {
+        format!("{}/settings/keys", self.repo(repo))
+    }
+
+    fn user(&self, login: &str) -> String {
+        format!("{}/{login}", self.web)
+    }
+
+    fn team(&self, team: &str) -> String {
+        format!("{}/orgs/{}/teams/{team}", self.web, self.org)
+    }
+
+    /// The page where something can be done about a finding about `target`
+    fn target(&self, target: &Target) -> Option<String> {
+        Some(match target {
+            Target::Repository { repo } => self.repo(repo),
+            Target::User { login } => self.user(login),
+            Target::Team { team } => self.team(team),
+            Target::RepositoryUser { repo, .. } | Target::RepositoryTeam { repo, .. } => {
+                self.collaborators(repo)
+            }
+            Target::DeployKey { repo, .. } => self.deploy_keys(repo),
+            Target::File { url, line, .. } => match line {
+                Some(line) => format!("{url}#L{line}"),
+                None => url.clone(),
+            },
+        })
+    }
+}
+
+/// Write the results of a run in the format given to `open` (or the report), then flush the data.
+/// Nothing more is written for `Format::Text`, since the audits write their results as they go
+pub fn write_results(bootstrap: &Bootstrap, results: &AuditResults) -> Result<(), Error> {
+    let links = Links {
+        web: bootstrap.web_url(),
+        org: bootstrap.org().to_string(),
+    };
+    with_destination(|destination| match destination.report {
+        Some(report) => {
+            let write = match report {
+                Report::Html => html::report,
+                Report::Markdown => markdown::report,
+            };
+            write(
+                &mut destination.out,
+                bootstrap.org(),
+                &now(),
+                &links,
+                results,
+            )?;
+            destination.out.flush().map_err(write_error)
+        }
+        None => write_results_to(
+            destination.format,
+            &links,
+            &now(),
+            results,
+            &mut destination.out,
+        ),
+    })
+}
+
+/// Escape text to put it in HTML or XML, in elements or attributes
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// RFC 3339, in UTC
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+fn write_results_to(
+    format: Format,
+    links: &Links,
+    generated_at: &str,
+    results: &AuditResults,
+    out: &mut impl Write,
+) -> Result<(), Error> {
+    let run = Run {
+        tool: Tool {
+            name: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+        },
+        org: &links.org,
+        generated_at,
+        audits: results.iter().map(|(audit, _)| *audit).collect(),
+    };
+    let (findings, skipped, failed_audits) = records(results);
+
+    match format {
+        Format::Text => {}
+        Format::Json => {
+            let document = Document {
+                schema_version: SCHEMA_VERSION,
+                run,
+                findings,
+                skipped,
+                failed_audits,
+            };
+            serde_json::to_writer_pretty(&mut *out, &document)?;
+            writeln!(out).map_err(write_error)?;
+        }
+        Format::Ndjson => {
+            write_line(out, "run", run)?;
+            for finding in findings {
+                write_line(out, "finding", finding)?;
+            }
+            for target in skipped {
+                write_line(out, "skipped", target)?;
+            }
+            for audit in failed_audits {
+                write_line(out, "failed_audit", audit)?;
+            }
+        }
+        Format::Sarif => {
+            serde_json::to_writer_pretty(&mut *out, &sarif::log(results, links))?;
+            writeln!(out).map_err(write_error)?;
+        }
+    }
+    out.flush().map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{finding::EC_ACCESS, Findings, Skipped};
+
+    const GENERATED_AT: &str = "2024-05-01T12:00:00Z";
+
+    fn results() -> AuditResults {
+        let mut skipped = Skipped::default();
+        skipped.record(
+            "repository beta".to_string(),
+            Error::Config("no access".to_string()),
+        );
+        let mut findings = Findings::default().with_skipped(skipped);
+        findings.record_quietly(
+            Finding::new(
+                &EC_ACCESS,
+                Target::RepositoryUser {
+                    repo: "alpha".to_string(),
+                    login: "eve".to_string(),
+                },
+                "eve has push access to alpha",
+            )
+            .with("permission", "push"),
+        );
+        vec![
+            ("ec", Ok(findings)),
+            ("admins", Err(Error::Config("bad config".to_string()))),
+        ]
+    }
+
+    fn write(format: Format) -> String {
+        let links = Links {
+            web: "https://github.com".to_string(),
+            org: "my-org".to_string(),
+        };
+        let mut out = vec![];
+        write_results_to(format, &links, GENERATED_AT, &results(), &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .replace(env!("CARGO_PKG_VERSION"), "VERSION")
+    }
+
+    #[test]
+    fn json_output_is_stable() {
+        assert_eq!(
+            write(Format::Json),
+            r#"{
+  "schema_version": 1,
+  "tool": {
+    "name": "gh-ec-audit",
+    "version": "VERSION"
+  },
+  "org": "my-org",
+  "generated_at": "2024-05-01T12:00:00Z",
+  "audits": [
+    "ec",
+    "admins"
+  ],
+  "findings": [
+    {
+      "audit": "ec",
+      "rule_id": "EC004",
+      "rule": "external-collaborator-access",
+      "severity": "info",
+      "target": {
+        "kind": "repository_user",
+        "repo": "alpha",
+        "login": "eve"
+      },
+      "message": "eve has push access to alpha",
+      "evidence": {
+        "permission": "push"
+      },
+      "remediation": "Keep the approval of the access up to date in the CSV"
+    }
+  ],
+  "skipped": [
+    {
+      "audit": "ec",
+      "target": "repository beta",
+      "error": "no access"
+    }
+  ],
+  "failed_audits": [
+    {
+      "audit": "admins",
+      "error": "bad config"
+    }
+  ]
+}
+"#
+        );
+    }
+
+    #[test]
+    fn ndjson_output_is_stable() {
+        assert_eq!(
+            write(Format::Ndjson),
+            concat!(
+                r#"{"schema_version":1,"type":"run","tool":{"name":"gh-ec-audit","version":"VERSION"},"org":"my-org","generated_at":"2024-05-01T12:00:00Z","audits":["ec","admins"]}"#,
+                "\n",
+                r#"{"schema_version":1,"type":"finding","audit":"ec","rule_id":"EC004","rule":"external-collaborator-access","severity":"info","target":{"kind":"repository_user","repo":"alpha","login":"eve"},"message":"eve has push access to alpha","evidence":{"permission":"push"},"remediation":"Keep the approval of the access up to date in the CSV"}"#,
+                "\n",
+                r#"{"schema_version":1,"type":"skipped","audit":"ec","target":"repository beta","error":"no access"}"#,
+                "\n",
+                r#"{"schema_version":1,"type":"failed_audit","audit":"admins","error":"bad config"}"#,
+                "\n",
+            )
+        );
+    }
+}
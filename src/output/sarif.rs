@@ -0,0 +1,526 @@
+//! SARIF 2.1.0, for GitHub code scanning and SARIF viewers.
+//! See https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use std::{borrow::Cow, collections::BTreeMap};
+
+use serde::Serialize;
+
+use crate::finding::{Finding, Rule, Severity, Target, RULES};
+
+use super::{AuditResults, Links};
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const VERSION: &str = "2.1.0";
+
+#[derive(Serialize)]
+pub(super) struct Log<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run<'a> {
+    tool: Tool,
+    /// Where the files of each repository are, keyed by repository name: the `uriBaseId` of their locations
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    original_uri_base_ids: BTreeMap<&'a str, ArtifactLocation<'a>>,
+    invocations: Vec<Invocation>,
+    results: Vec<SarifResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportingDescriptor {
+    id: &'static str,
+    name: &'static str,
+    short_description: Text,
+    help: Text,
+    default_configuration: Configuration,
+    properties: RuleProperties,
+}
+
+#[derive(Serialize)]
+struct Text {
+    text: String,
+}
+
+#[derive(Serialize)]
+struct Configuration {
+    level: &'static str,
+}
+
+#[derive(Serialize)]
+struct RuleProperties {
+    tags: [&'static str; 1],
+    /// Used by GitHub code scanning to show a severity (critical, high, ...) for security rules
+    #[serde(rename = "security-severity")]
+    security_severity: &'static str,
+    /// Our own severity, which is more precise than the SARIF level
+    severity: Severity,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Invocation {
+    execution_successful: bool,
+    end_time_utc: String,
+    tool_execution_notifications: Vec<Notification>,
+}
+
+#[derive(Serialize)]
+struct Notification {
+    level: &'static str,
+    message: Text,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult<'a> {
+    rule_id: &'static str,
+    rule_index: usize,
+    level: &'static str,
+    message: Text,
+    locations: Vec<Location<'a>>,
+    properties: ResultProperties<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResultProperties<'a> {
+    audit: &'a str,
+    target: &'a Target,
+    evidence: &'a BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location<'a> {
+    physical_location: PhysicalLocation<'a>,
+    logical_locations: Vec<LogicalLocation>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation<'a> {
+    artifact_location: ArtifactLocation<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<Region>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactLocation<'a> {
+    uri: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uri_base_id: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LogicalLocation {
+    name: String,
+    fully_qualified_name: String,
+    kind: &'static str,
+}
+
+/// How SARIF viewers should show a finding of this severity
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low | Severity::Info => "note",
+    }
+}
+
+/// The scores GitHub code scanning expects: over 9.0 is critical, 7.0 to 8.9 high, 4.0 to 6.9 medium, and below is low
+fn security_severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "9.5",
+        Severity::High => "8.0",
+        Severity::Medium => "5.5",
+        Severity::Low => "3.0",
+        Severity::Info => "0.0",
+    }
+}
+
+fn descriptor(rule: &Rule) -> ReportingDescriptor {
+    ReportingDescriptor {
+        id: rule.id,
+        name: rule.name,
+        short_description: Text {
+            text: rule.description.to_string(),
+        },
+        help: Text {
+            text: rule.remediation.to_string(),
+        },
+        default_configuration: Configuration {
+            level: level(rule.severity),
+        },
+        properties: RuleProperties {
+            tags: ["security"],
+            security_severity: security_severity(rule.severity),
+            severity: rule.severity,
+        },
+    }
+}
+
+/// Where the finding is. A file is relative to the root of its repository when we know the URL of that root,
+/// in `original_uri_base_ids`. Anything else is the page on GitHub where the finding can be fixed
+fn location<'a>(
+    target: &'a Target,
+    links: &Links,
+    original_uri_base_ids: &BTreeMap<&str, ArtifactLocation>,
+) -> Location<'a> {
+    let physical_location = match target {
+        Target::File {
+            repo, path, line, ..
+        } if original_uri_base_ids.contains_key(repo.as_str()) => PhysicalLocation {
+            artifact_location: ArtifactLocation {
+                uri: Cow::Borrowed(path),
+                uri_base_id: Some(repo),
+            },
+            region: line.map(|start_line| Region { start_line }),
+        },
+        Target::File { url, line, .. } => PhysicalLocation {
+            artifact_location: ArtifactLocation {
+                uri: Cow::Borrowed(url),
+                uri_base_id: None,
+            },
+            region: line.map(|start_line| Region { start_line }),
+        },
+        _ => PhysicalLocation {
+            artifact_location: ArtifactLocation {
+                uri: Cow::Owned(links.target(target).unwrap_or_else(|| links.org())),
+                uri_base_id: None,
+            },
+            region: None,
+        },
+    };
+    let (kind, fully_qualified_name) = match target {
+        Target::Repository { repo } => ("repository", repo.clone()),
+        Target::User { login } => ("user", login.clone()),
+        Target::Team { team } => ("team", team.clone()),
+        Target::RepositoryUser { repo, login } => ("user", format!("{repo}/{login}")),
+        Target::RepositoryTeam { repo, team } => ("team", format!("{repo}/{team}")),
+        Target::DeployKey { repo, id, .. } => ("deployKey", format!("{repo}/{id}")),
+        Target::File { repo, path, .. } => ("file", format!("{repo}/{path}")),
+    };
+    Location {
+        physical_location,
+        logical_locations: vec![LogicalLocation {
+            name: target.to_string(),
+            fully_qualified_name,
+            kind,
+        }],
+    }
+}
+
+/// The URL of the root of the repository `finding` is in, on the branch the file was read from,
+/// e.g., `https://github.com/my-org/my-repo/blob/main/`
+fn repository_root(finding: &Finding) -> Option<(&str, &str)> {
+    match &finding.target {
+        Target::File {
+            repo, path, url, ..
+        } => url
+            .strip_suffix(path.as_str())
+            .filter(|root| root.ends_with('/'))
+            .map(|root| (repo.as_str(), root)),
+        _ => None,
+    }
+}
+
+/// Turn the results of a run into a SARIF log. Findings with the `info` severity only list what the audits
+/// looked at, so they are left out. Skipped targets and failed audits become notifications of the run
+pub(super) fn log<'a>(results: &'a AuditResults, links: &Links) -> Log<'a> {
+    let mut original_uri_base_ids = BTreeMap::new();
+    let mut sarif_results = vec![];
+    let mut notifications = vec![];
+
+    let findings = || {
+        results
+            .iter()
+            .filter_map(|(_, result)| result.as_ref().ok())
+            .flat_map(|findings| findings.iter())
+            .filter(|finding| finding.severity() != Severity::Info)
+    };
+    for (repo, root) in findings().filter_map(repository_root) {
+        original_uri_base_ids.insert(
+            repo,
+            ArtifactLocation {
+                uri: Cow::Borrowed(root),
+                uri_base_id: None,
+            },
+        );
+    }
+
+    for (audit, result) in results {
+        let findings = match result {
+            Ok(findings) => findings,
+            Err(e) => {
+                notifications.push(Notification {
+                    level: "error",
+                    message: Text {
+                        text: format!("The {audit} audit failed: {e}"),
+                    },
+                });
+                continue;
+            }
+        };
+        for (target, error) in findings.skipped().iter() {
+            notifications.push(Notification {
+                level: "warning",
+                message: Text {
+                    text: format!("The {audit} audit skipped {target}: {error}"),
+                },
+            });
+        }
+        for finding in findings.iter() {
+            if finding.severity() == Severity::Info {
+                continue;
+            }
+            sarif_results.push(SarifResult {
+                rule_id: finding.rule.id,
+                rule_index: RULES
+                    .iter()
+                    .position(|rule| rule.id == finding.rule.id)
+                    .expect("every rule is in RULES"),
+                level: level(finding.severity()),
+                message: Text {
+                    text: finding.message.clone(),
+                },
+                locations: vec![location(&finding.target, links, &original_uri_base_ids)],
+                properties: ResultProperties {
+                    audit,
+                    target: &finding.target,
+                    evidence: &finding.evidence,
+                },
+            });
+        }
+    }
+
+    Log {
+        schema: SCHEMA,
+        version: VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: env!("CARGO_PKG_NAME"),
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules: RULES.iter().map(|rule| descriptor(rule)).collect(),
+                },
+            },
+            original_uri_base_ids,
+            invocations: vec![Invocation {
+                execution_successful: results.iter().all(|(_, result)| result.is_ok()),
+                end_time_utc: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                tool_execution_notifications: notifications,
+            }],
+            results: sarif_results,
+        }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        finding::{CODEOWNERS_USER_NOT_IN_ORG, EC_ACCESS, EC_NEW_ACCESS},
+        Error, Findings, Skipped,
+    };
+
+    fn links() -> Links {
+        Links {
+            web: "https://github.com".to_string(),
+            org: "my-org".to_string(),
+        }
+    }
+
+    fn results() -> AuditResults {
+        let mut codeowners = Findings::default();
+        codeowners.record_quietly(Finding::new(
+            &CODEOWNERS_USER_NOT_IN_ORG,
+            Target::File {
+                repo: "api".to_string(),
+                path: ".github/CODEOWNERS".to_string(),
+                url: "https://github.com/my-org/api/blob/main/.github/CODEOWNERS".to_string(),
+                line: Some(3),
+            },
+            "eve is not a member of my-org",
+        ));
+        // A URL that doesn't end with the path, so we don't know where the root of the repository is
+        codeowners.record_quietly(Finding::new(
+            &CODEOWNERS_USER_NOT_IN_ORG,
+            Target::File {
+                repo: "web".to_string(),
+                path: "CODEOWNERS".to_string(),
+                url: "https://github.com/my-org/web/blob/main/docs/CODEOWNERS?plain=1".to_string(),
+                line: None,
+            },
+            "mallory is not a member of my-org",
+        ));
+        let mut ec = Findings::default();
+        let target = Target::RepositoryUser {
+            repo: "api".to_string(),
+            login: "eve".to_string(),
+        };
+        ec.record_quietly(Finding::new(
+            &EC_NEW_ACCESS,
+            target.clone(),
+            "eve got access to api",
+        ));
+        ec.record_quietly(Finding::new(
+            &EC_ACCESS,
+            target,
+            "eve has push access to api",
+        ));
+        let mut skipped = Skipped::default();
+        skipped.record("beta", Error::Config("nope".to_string()));
+        vec![
+            ("codeowners", Ok(codeowners)),
+            ("ec", Ok(ec.with_skipped(skipped))),
+            ("admins", Err(Error::Config("boom".to_string()))),
+        ]
+    }
+
+    fn sarif(results: &AuditResults) -> Value {
+        serde_json::to_value(log(results, &links())).unwrap()
+    }
+
+    /// The names of the properties of a JSON object
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .expect("an object")
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn log_has_the_shape_of_sarif_2_1_0() {
+        let results = results();
+        let log = sarif(&results);
+
+        assert_eq!(keys(&log), ["$schema", "runs", "version"]);
+        assert_eq!(log["version"], "2.1.0");
+        let runs = log["runs"].as_array().unwrap();
+        assert_eq!(runs.len(), 1);
+        let run = &runs[0];
+        assert_eq!(run["tool"]["driver"]["name"], "gh-ec-audit");
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        assert_eq!(rules.len(), RULES.len());
+        let levels = ["none", "note", "warning", "error"];
+        for rule in rules {
+            assert!(rule["shortDescription"]["text"].is_string());
+            assert!(levels.contains(&rule["defaultConfiguration"]["level"].as_str().unwrap()));
+        }
+
+        let base_ids = &run["originalUriBaseIds"];
+        let sarif_results = run["results"].as_array().unwrap();
+        // Info findings are left out
+        assert_eq!(sarif_results.len(), 3);
+        for result in sarif_results {
+            assert_eq!(
+                keys(result),
+                [
+                    "level",
+                    "locations",
+                    "message",
+                    "properties",
+                    "ruleId",
+                    "ruleIndex"
+                ]
+            );
+            let rule = &rules[result["ruleIndex"].as_u64().unwrap() as usize];
+            assert_eq!(rule["id"], result["ruleId"]);
+            assert!(levels.contains(&result["level"].as_str().unwrap()));
+            assert!(!result["message"]["text"].as_str().unwrap().is_empty());
+
+            // GitHub code scanning drops results without a physical location
+            let location = &result["locations"][0];
+            assert_eq!(keys(location), ["logicalLocations", "physicalLocation"]);
+            let artifact = &location["physicalLocation"]["artifactLocation"];
+            assert!(!artifact["uri"].as_str().unwrap().is_empty());
+            match artifact.get("uriBaseId") {
+                // A base is an absolute URI that ends with a slash
+                Some(base_id) => {
+                    let base = base_ids[base_id.as_str().unwrap()]["uri"].as_str().unwrap();
+                    assert!(
+                        base.starts_with("https://") && base.ends_with('/'),
+                        "{base}"
+                    );
+                }
+                None => assert!(artifact["uri"].as_str().unwrap().starts_with("https://")),
+            }
+            if let Some(region) = location["physicalLocation"].get("region") {
+                assert!(region["startLine"].as_u64().unwrap() >= 1);
+            }
+        }
+
+        let invocation = &run["invocations"][0];
+        assert_eq!(invocation["executionSuccessful"], false);
+        let notifications = invocation["toolExecutionNotifications"].as_array().unwrap();
+        let levels: Vec<&str> = notifications
+            .iter()
+            .map(|n| n["level"].as_str().unwrap())
+            .collect();
+        assert_eq!(levels, ["warning", "error"]);
+    }
+
+    #[test]
+    fn every_result_points_to_a_file_or_a_page() {
+        let results = results();
+        let log = sarif(&results);
+        let run = &log["runs"][0];
+        let artifacts: Vec<&Value> = run["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| &r["locations"][0]["physicalLocation"])
+            .collect();
+
+        assert_eq!(
+            artifacts[0]["artifactLocation"],
+            serde_json::json!({ "uri": ".github/CODEOWNERS", "uriBaseId": "api" })
+        );
+        assert_eq!(artifacts[0]["region"]["startLine"], 3);
+        assert_eq!(
+            run["originalUriBaseIds"],
+            serde_json::json!({ "api": { "uri": "https://github.com/my-org/api/blob/main/" } })
+        );
+        assert_eq!(
+            artifacts[1]["artifactLocation"],
+            serde_json::json!({ "uri": "https://github.com/my-org/web/blob/main/docs/CODEOWNERS?plain=1" })
+        );
+        assert_eq!(
+            artifacts[2]["artifactLocation"],
+            serde_json::json!({ "uri": "https://github.com/my-org/api/settings/access" })
+        );
+    }
+}
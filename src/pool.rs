@@ -0,0 +1,128 @@
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Create the progress bar we show while going through a list of repositories.
+/// Like our messages, it is drawn on stderr, and not at all with `-q`
+pub fn progress_bar(len: usize) -> ProgressBar {
+    if log::max_level() < log::LevelFilter::Info {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(len as u64);
+    pb.set_style(
+        ProgressStyle::with_template(
+            "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}",
+        )
+        .unwrap()
+        .progress_chars("=>-"),
+    );
+    pb
+}
+
+/// Process `items` with up to `concurrency` worker threads.
+///
+/// `work` runs on the workers (this is where requests to GitHub should be made), while `sink` runs
+/// on the calling thread and receives each item together with its result, **in the same order as `items`**,
+/// as soon as all the previous ones are done. This keeps the output deterministic no matter how many
+/// workers are used, and lets the caller print or write results as they come.
+pub fn for_each_ordered<T, R, F, S>(
+    items: Vec<T>,
+    concurrency: usize,
+    progress: &ProgressBar,
+    work: F,
+    mut sink: S,
+) where
+    T: Send,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    S: FnMut(T, R),
+{
+    run_ordered(items, concurrency, work, |item, result| {
+        sink(item, result);
+        progress.inc(1);
+    });
+}
+
+/// Like `for_each_ordered`, but `work` receives up to `batch_size` items at a time and must
+/// return one result per item, in the same order. This is useful when a single request to
+/// GitHub can fetch data about many items at once (e.g., with GraphQL).
+/// `sink` still receives one item at a time, in the same order as `items`.
+pub fn for_each_ordered_batched<T, R, F, S>(
+    items: Vec<T>,
+    batch_size: usize,
+    concurrency: usize,
+    progress: &ProgressBar,
+    work: F,
+    mut sink: S,
+) where
+    T: Send,
+    R: Send,
+    F: Fn(&[T]) -> Vec<R> + Sync,
+    S: FnMut(T, R),
+{
+    let mut batches = vec![];
+    let mut items = items.into_iter().peekable();
+    while items.peek().is_some() {
+        batches.push(items.by_ref().take(batch_size.max(1)).collect::<Vec<T>>());
+    }
+
+    run_ordered(
+        batches,
+        concurrency,
+        |batch| work(batch),
+        |batch, results| {
+            for (item, result) in batch.into_iter().zip(results) {
+                sink(item, result);
+                progress.inc(1);
+            }
+        },
+    );
+}
+
+fn run_ordered<T, R, F, S>(items: Vec<T>, concurrency: usize, work: F, mut sink: S)
+where
+    T: Send,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+    S: FnMut(T, R),
+{
+    let workers = concurrency.clamp(1, items.len().max(1));
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let (tx, rx) = mpsc::channel::<(usize, T, R)>();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let queue = &queue;
+            let work = &work;
+            scope.spawn(move || loop {
+                // Don't hold the lock while working on the item
+                let next = queue.lock().unwrap().next();
+                let Some((index, item)) = next else {
+                    break;
+                };
+                let result = work(&item);
+                if tx.send((index, item, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        // Only the workers hold a sender now: the loop below ends when all of them are done
+        drop(tx);
+
+        // Results can arrive out of order: keep them aside until it's their turn
+        let mut pending = BTreeMap::new();
+        let mut next_index = 0;
+        for (index, item, result) in rx {
+            pending.insert(index, (item, result));
+            while let Some((item, result)) = pending.remove(&next_index) {
+                sink(item, result);
+                next_index += 1;
+            }
+        }
+    });
+}
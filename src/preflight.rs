@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+
+use colored::Colorize;
+use log::{info, warn};
+
+use crate::{
+    auth::Auth, graphql, send_github_request, Backend, Bootstrap, Error, GitHubHttpResponse,
+};
+
+/// The audits the preflight knows what to check for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditKind {
+    ExternalCollaborators,
+    DeployKeys,
+    Members,
+    Admins,
+    BranchProtection,
+    TeamPermissions,
+    EmptyTeams,
+    Codeowners,
+    Compliance,
+    TeamInCodeowners,
+}
+
+impl AuditKind {
+    pub const ALL: [AuditKind; 10] = [
+        AuditKind::ExternalCollaborators,
+        AuditKind::DeployKeys,
+        AuditKind::Members,
+        AuditKind::Admins,
+        AuditKind::BranchProtection,
+        AuditKind::TeamPermissions,
+        AuditKind::EmptyTeams,
+        AuditKind::Codeowners,
+        AuditKind::Compliance,
+        AuditKind::TeamInCodeowners,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AuditKind::ExternalCollaborators => "External collaborators",
+            AuditKind::DeployKeys => "Deploy keys",
+            AuditKind::Members => "Members",
+            AuditKind::Admins => "Repository admins",
+            AuditKind::BranchProtection => "BPR and rulesets",
+            AuditKind::TeamPermissions => "Team permissions",
+            AuditKind::EmptyTeams => "Empty teams",
+            AuditKind::Codeowners => "CODEOWNERS",
+            AuditKind::Compliance => "Compliance",
+            AuditKind::TeamInCodeowners => "Team in CODEOWNERS",
+        }
+    }
+
+    /// Whether the audit can be limited to some repositories with `--repos`,
+    /// in which case it doesn't need to list the org's repositories
+    fn accepts_repos(self) -> bool {
+        matches!(
+            self,
+            AuditKind::Admins
+                | AuditKind::BranchProtection
+                | AuditKind::Codeowners
+                | AuditKind::Compliance
+                | AuditKind::TeamInCodeowners
+        )
+    }
+}
+
+/// The options of the audit(s) to check, since they change which endpoints are used
+#[derive(Default)]
+pub struct PreflightOptions {
+    pub repos: Option<Vec<String>>,
+    pub team: Option<String>,
+    pub search: bool,
+    pub also_gh_api: bool,
+    /// The ec audit also lists the pending invitations of each repository
+    pub invitations: bool,
+}
+
+/// An endpoint (or group of endpoints) that an audit needs. We send a single,
+/// cheap request to each of them to find out whether the token can use it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Probe {
+    OrgRepos,
+    PrivateRepos,
+    OrgMembers,
+    OrgAdmins,
+    OutsideCollaborators,
+    Teams,
+    TeamMembers,
+    TeamRepos,
+    RepoMetadata,
+    RepoCollaborators,
+    RepoInvitations,
+    RepoTeams,
+    DeployKeys,
+    BranchProtection,
+    Rulesets,
+    Contents,
+    CodeownersErrors,
+    CodeSearch,
+    GraphQl,
+}
+
+impl Probe {
+    fn description(self) -> &'static str {
+        match self {
+            Probe::OrgRepos => "List organization repositories",
+            Probe::PrivateRepos => "See private repositories",
+            Probe::OrgMembers => "List organization members",
+            Probe::OrgAdmins => "List organization admins",
+            Probe::OutsideCollaborators => "List outside collaborators",
+            Probe::Teams => "List teams",
+            Probe::TeamMembers => "List team members",
+            Probe::TeamRepos => "List team repositories",
+            Probe::RepoMetadata => "Read repository metadata",
+            Probe::RepoCollaborators => "List repository collaborators",
+            Probe::RepoInvitations => "List repository invitations",
+            Probe::RepoTeams => "List repository teams",
+            Probe::DeployKeys => "List deploy keys",
+            Probe::BranchProtection => "Read branch protection",
+            Probe::Rulesets => "Read branch rulesets",
+            Probe::Contents => "Read repository contents",
+            Probe::CodeownersErrors => "Read CODEOWNERS errors",
+            Probe::CodeSearch => "Search code",
+            Probe::GraphQl => "Query the GraphQL API",
+        }
+    }
+
+    /// For these, GitHub answers 404 when there is simply nothing to return
+    /// (e.g., the branch is not protected or there is no CODEOWNERS file)
+    fn not_found_is_ok(self) -> bool {
+        matches!(
+            self,
+            Probe::BranchProtection | Probe::Contents | Probe::CodeownersErrors
+        )
+    }
+}
+
+/// What each audit needs, given the options it will run with
+fn probes_for(audit: AuditKind, options: &PreflightOptions, backend: Backend) -> Vec<Probe> {
+    let mut probes = match audit {
+        AuditKind::ExternalCollaborators => vec![
+            Probe::OutsideCollaborators,
+            Probe::OrgRepos,
+            Probe::PrivateRepos,
+            Probe::RepoCollaborators,
+        ],
+        AuditKind::DeployKeys => vec![
+            Probe::OrgMembers,
+            Probe::OrgRepos,
+            Probe::PrivateRepos,
+            Probe::DeployKeys,
+        ],
+        AuditKind::Members => vec![Probe::OrgMembers],
+        AuditKind::Admins => vec![
+            Probe::OrgAdmins,
+            Probe::OrgRepos,
+            Probe::PrivateRepos,
+            Probe::RepoCollaborators,
+            Probe::RepoTeams,
+            Probe::TeamMembers,
+        ],
+        AuditKind::BranchProtection => vec![
+            Probe::OrgRepos,
+            Probe::PrivateRepos,
+            Probe::RepoMetadata,
+            Probe::BranchProtection,
+            Probe::Rulesets,
+        ],
+        AuditKind::TeamPermissions => vec![Probe::TeamRepos],
+        AuditKind::EmptyTeams => vec![Probe::Teams, Probe::TeamMembers],
+        AuditKind::Codeowners | AuditKind::TeamInCodeowners if options.search => {
+            vec![Probe::CodeSearch, Probe::Contents]
+        }
+        AuditKind::Codeowners | AuditKind::TeamInCodeowners => {
+            vec![Probe::OrgRepos, Probe::PrivateRepos, Probe::Contents]
+        }
+        AuditKind::Compliance => vec![
+            Probe::OrgRepos,
+            Probe::PrivateRepos,
+            Probe::RepoMetadata,
+            Probe::BranchProtection,
+            Probe::Rulesets,
+            Probe::Contents,
+            Probe::CodeownersErrors,
+        ],
+    };
+
+    if audit == AuditKind::Codeowners && options.also_gh_api {
+        probes.push(Probe::CodeownersErrors);
+    }
+    if audit == AuditKind::ExternalCollaborators && options.invitations {
+        probes.extend([Probe::OrgMembers, Probe::RepoInvitations]);
+    }
+    if options.repos.is_some() && audit.accepts_repos() {
+        probes.retain(|p| !matches!(p, Probe::OrgRepos | Probe::PrivateRepos));
+    }
+    let uses_graphql = matches!(
+        audit,
+        AuditKind::ExternalCollaborators | AuditKind::Admins | AuditKind::Compliance
+    );
+    if backend == Backend::GraphQl && uses_graphql {
+        probes.push(Probe::GraphQl);
+    }
+    probes
+}
+
+#[derive(Clone)]
+enum Outcome {
+    Ok,
+    /// The audit will run, but its results may be incomplete
+    Warning(String),
+    Failed(String),
+    /// We could not find anything to try the endpoint on
+    Skipped(String),
+}
+
+/// The repository and team we try the per-repository and per-team endpoints on
+struct Samples {
+    repo: Option<String>,
+    default_branch: Result<String, Outcome>,
+    team: Option<String>,
+}
+
+/// A repository or a team. Teams are found by their slug in URLs
+#[derive(serde::Deserialize)]
+struct Named {
+    name: String,
+    slug: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct RepositoryMetadata {
+    default_branch: String,
+}
+
+/// Explain why GitHub rejected a request and, when GitHub tells us, what the token is missing
+fn describe_failure(response: &GitHubHttpResponse) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(&response.body)
+        .ok()
+        .and_then(|v| {
+            v.get("message")
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string())
+        })
+        .unwrap_or_default();
+    let mut reason = format!("HTTP {} {message}", response.status.as_u16())
+        .trim_end()
+        .to_string();
+
+    let header = |name: &str| {
+        response
+            .headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.to_string())
+    };
+    // Fine-grained PATs and GitHub Apps
+    if let Some(permissions) = header("x-accepted-github-permissions") {
+        reason.push_str(&format!(" (needs permissions: {permissions})"));
+    }
+    // Classic PATs
+    if let Some(scopes) = header("x-accepted-oauth-scopes") {
+        reason.push_str(&format!(" (needs scopes: {scopes})"));
+    }
+    reason
+}
+
+/// Send a GET request and tell whether the token can use the endpoint
+fn get(bootstrap: &Bootstrap, path: &str, not_found_is_ok: bool) -> (Outcome, Option<String>) {
+    match send_github_request(bootstrap, path) {
+        Ok(response) if response.status.is_success() => (Outcome::Ok, Some(response.body)),
+        Ok(response) if not_found_is_ok && response.status == reqwest::StatusCode::NOT_FOUND => {
+            (Outcome::Ok, None)
+        }
+        Ok(response) => (Outcome::Failed(describe_failure(&response)), None),
+        Err(e) => (Outcome::Failed(e.to_string()), None),
+    }
+}
+
+/// The name of the first item of a list, if we can see any
+fn first_name(bootstrap: &Bootstrap, path: &str) -> Option<String> {
+    let (_, body) = get(bootstrap, path, false);
+    serde_json::from_str::<Vec<Named>>(&body?)
+        .ok()?
+        .into_iter()
+        .next()
+        .map(|n| n.slug.unwrap_or(n.name))
+}
+
+fn find_samples(bootstrap: &Bootstrap, options: &PreflightOptions) -> Samples {
+    let org = &bootstrap.org;
+    // We prefer a private repository, since they are the ones a token is most likely not to see
+    let repo = match &options.repos {
+        Some(repos) => repos.first().cloned(),
+        None => first_name(
+            bootstrap,
+            &format!("/orgs/{org}/repos?type=private&per_page=1"),
+        )
+        .or_else(|| first_name(bootstrap, &format!("/orgs/{org}/repos?per_page=1"))),
+    };
+    let default_branch = match &repo {
+        None => Err(Outcome::Skipped("no repository to try it on".to_string())),
+        Some(repo) => match get(bootstrap, &format!("/repos/{org}/{repo}"), false) {
+            (Outcome::Ok, Some(body)) => serde_json::from_str::<RepositoryMetadata>(&body)
+                .map(|m| m.default_branch)
+                .map_err(|e| Outcome::Failed(e.to_string())),
+            (outcome, _) => Err(outcome),
+        },
+    };
+    let team = match &options.team {
+        Some(team) => Some(team.clone()),
+        None => first_name(bootstrap, &format!("/orgs/{org}/teams?per_page=1")),
+    };
+    Samples {
+        repo,
+        default_branch,
+        team,
+    }
+}
+
+fn run_probe(bootstrap: &Bootstrap, probe: Probe, samples: &Samples) -> (Outcome, String) {
+    let org = &bootstrap.org;
+    let org_path = match probe {
+        Probe::OrgRepos => Some(format!("/orgs/{org}/repos?per_page=1")),
+        Probe::OrgMembers => Some(format!("/orgs/{org}/members?per_page=1")),
+        Probe::OrgAdmins => Some(format!("/orgs/{org}/members?role=admin&per_page=1")),
+        Probe::OutsideCollaborators => {
+            Some(format!("/orgs/{org}/outside_collaborators?per_page=1"))
+        }
+        Probe::Teams => Some(format!("/orgs/{org}/teams?per_page=1")),
+        Probe::CodeSearch => Some(format!(
+            "/search/code?q={}&per_page=1",
+            urlencoding::encode(&format!("org:{org} filename:CODEOWNERS"))
+        )),
+        _ => None,
+    };
+    if let Some(path) = org_path {
+        return (get(bootstrap, &path, false).0, String::new());
+    }
+
+    match probe {
+        Probe::PrivateRepos => {
+            let path = format!("/orgs/{org}/repos?type=private&per_page=1");
+            let outcome = match get(bootstrap, &path, false) {
+                (Outcome::Ok, Some(body)) if body.trim() == "[]" => Outcome::Warning(
+                    "no private repository is visible: make sure the token can read them"
+                        .to_string(),
+                ),
+                (outcome, _) => outcome,
+            };
+            (outcome, String::new())
+        }
+        Probe::GraphQl => match graphql::check_access(bootstrap) {
+            Ok(()) => (Outcome::Ok, String::new()),
+            Err(e) => (Outcome::Failed(e.to_string()), String::new()),
+        },
+        Probe::TeamMembers | Probe::TeamRepos => {
+            let Some(team) = &samples.team else {
+                let outcome = Outcome::Skipped("no team to try it on".to_string());
+                return (outcome, String::new());
+            };
+            let what = match probe {
+                Probe::TeamMembers => "members",
+                _ => "repos",
+            };
+            let path = format!("/orgs/{org}/teams/{team}/{what}?per_page=1");
+            (get(bootstrap, &path, false).0, team.clone())
+        }
+        _ => {
+            let Some(repo) = &samples.repo else {
+                let outcome = Outcome::Skipped("no repository to try it on".to_string());
+                return (outcome, String::new());
+            };
+            let branch = match (&samples.default_branch, probe) {
+                (Ok(branch), _) => branch,
+                (Err(outcome), Probe::RepoMetadata) => return (outcome.clone(), repo.clone()),
+                (Err(_), Probe::BranchProtection | Probe::Rulesets) => {
+                    let outcome = Outcome::Skipped("the default branch is unknown".to_string());
+                    return (outcome, repo.clone());
+                }
+                (Err(_), _) => "",
+            };
+            let path = match probe {
+                // Already fetched to find the default branch
+                Probe::RepoMetadata => return (Outcome::Ok, repo.clone()),
+                Probe::RepoCollaborators => {
+                    format!("/repos/{org}/{repo}/collaborators?per_page=1")
+                }
+                Probe::RepoInvitations => format!("/repos/{org}/{repo}/invitations?per_page=1"),
+                Probe::RepoTeams => format!("/repos/{org}/{repo}/teams?per_page=1"),
+                Probe::DeployKeys => format!("/repos/{org}/{repo}/keys?per_page=1"),
+                Probe::Contents => format!("/repos/{org}/{repo}/contents/.github/CODEOWNERS"),
+                Probe::CodeownersErrors => format!("/repos/{org}/{repo}/codeowners/errors"),
+                Probe::BranchProtection => {
+                    format!("/repos/{org}/{repo}/branches/{branch}/protection")
+                }
+                Probe::Rulesets => format!("/repos/{org}/{repo}/rules/branches/{branch}"),
+                _ => unreachable!("{probe:?} is not a per-repository probe"),
+            };
+            (
+                get(bootstrap, &path, probe.not_found_is_ok()).0,
+                repo.clone(),
+            )
+        }
+    }
+}
+
+/// Tell who the token belongs to and what it was granted, when GitHub lets us know
+fn describe_token(bootstrap: &Bootstrap) -> Result<(), Error> {
+    match &bootstrap.auth {
+        Auth::App(app) => {
+            info!(
+                "{} {} (installation {})",
+                "Token: installation token of GitHub App".green(),
+                app.app_id().white(),
+                app.installation_id(bootstrap)?.to_string().white()
+            );
+            let permissions = app
+                .permissions()
+                .iter()
+                .map(|(name, access)| format!("{name}={access}"))
+                .collect::<Vec<String>>();
+            info!(
+                "{} {}",
+                "Permissions:".green(),
+                permissions.join(", ").white()
+            );
+        }
+        Auth::Token(_) => {
+            let response = send_github_request(bootstrap, "/user")?;
+            if !response.status.is_success() {
+                return Err(Error::from_status(
+                    "/user",
+                    response.status,
+                    describe_failure(&response),
+                ));
+            }
+            let login = serde_json::from_str::<serde_json::Value>(&response.body)?
+                .get("login")
+                .and_then(|l| l.as_str())
+                .unwrap_or("unknown user")
+                .to_string();
+
+            // Only classic PATs tell us their scopes. For fine-grained PATs, the checks below are all we get
+            let Some(scopes) = response
+                .headers
+                .get("x-oauth-scopes")
+                .and_then(|v| v.to_str().ok())
+            else {
+                info!("{} {}", "Token: fine-grained PAT of".green(), login.white());
+                return Ok(());
+            };
+            info!("{} {}", "Token: classic PAT of".green(), login.white());
+            info!("{} {}", "Scopes:".green(), scopes.white());
+
+            let scopes = scopes.split(',').map(|s| s.trim()).collect::<Vec<&str>>();
+            if !scopes.contains(&"repo") {
+                warn!(
+                    "{}",
+                    "Warning! Without the repo scope, private repositories are not visible".red()
+                );
+            }
+            if !["read:org", "write:org", "admin:org"]
+                .iter()
+                .any(|s| scopes.contains(s))
+            {
+                warn!(
+                    "{}",
+                    "Warning! Without the read:org scope, some members and teams are not visible"
+                        .red()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that the token can do what the given audits need, and print a table of what will and won't work.
+/// Returns whether all of them will work.
+pub fn run_preflight(
+    bootstrap: &Bootstrap,
+    audits: &[AuditKind],
+    options: &PreflightOptions,
+) -> Result<bool, Error> {
+    info!(
+        "{}",
+        "Preflight: I'm checking what the token can do...".yellow()
+    );
+    describe_token(bootstrap)?;
+
+    let samples = find_samples(bootstrap, options);
+    if let Some(repo) = &samples.repo {
+        info!(
+            "{} {}",
+            "I'm trying repository endpoints on:".green(),
+            repo.white()
+        );
+    }
+
+    // Most probes are needed by several audits: we only send each of them once
+    let mut results: HashMap<Probe, (Outcome, String)> = HashMap::new();
+    let mut all_ok = true;
+
+    info!("");
+    info!("{:<24} {:<46} RESULT", "AUDIT", "CHECK");
+    for audit in audits {
+        let probes = probes_for(*audit, options, bootstrap.backend);
+        let mut rows = vec![];
+        let mut audit_ok = true;
+        for probe in probes {
+            let (outcome, target) = results
+                .entry(probe)
+                .or_insert_with(|| run_probe(bootstrap, probe, &samples))
+                .clone();
+            if matches!(outcome, Outcome::Failed(_)) {
+                audit_ok = false;
+            }
+            let check = match target.is_empty() {
+                true => probe.description().to_string(),
+                false => format!("{} ({target})", probe.description()),
+            };
+            rows.push((check, outcome));
+        }
+        all_ok &= audit_ok;
+
+        let verdict = match audit_ok {
+            true => "will work".green(),
+            false => "won't work".red(),
+        };
+        info!("{:<24} {}", audit.name().white(), verdict);
+        for (check, outcome) in rows {
+            let result = match outcome {
+                Outcome::Ok => "ok".green(),
+                Outcome::Warning(w) => format!("warning: {w}").yellow(),
+                Outcome::Failed(f) => format!("failed: {f}").red(),
+                Outcome::Skipped(s) => format!("not checked: {s}").normal(),
+            };
+            info!("{:<24} {:<46} {}", "", check, result);
+        }
+    }
+    info!("");
+
+    Ok(all_ok)
+}
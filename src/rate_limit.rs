@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    thread::sleep,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use colored::Colorize;
+use log::info;
+use reqwest::{header::HeaderMap, StatusCode};
+
+/// When GitHub tells us we hit a secondary rate limit without saying for how long,
+/// its docs say to wait at least one minute.
+/// See https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api#exceeding-the-rate-limit
+const SECONDARY_RATE_LIMIT_WAIT: Duration = Duration::from_secs(60);
+
+/// Extra time we wait after a bucket is supposed to have been reset, to absorb clock skew.
+const RESET_GRACE: Duration = Duration::from_secs(1);
+
+/// Start spreading requests over time when less than this fraction (1/N) of a bucket is left.
+const PACING_FRACTION: u32 = 10;
+
+/// What we know about a rate limit bucket (e.g., `core` or `search`), from the last response we got.
+struct Bucket {
+    limit: u32,
+    remaining: u32,
+    /// Unix timestamp (in seconds) at which the bucket is refilled
+    reset: u64,
+}
+
+#[derive(Default)]
+struct State {
+    buckets: HashMap<String, Bucket>,
+    /// Set when we hit a secondary rate limit: these apply to all requests, regardless of the bucket
+    paused_until: Option<Instant>,
+}
+
+/// Schedules requests to the GitHub API according to the rate limit headers GitHub sends back.
+/// GitHub keeps separate buckets for different kinds of requests (e.g., the search API has its own,
+/// much smaller, one), so we track each of them independently.
+/// For more info, see https://docs.github.com/en/rest/using-the-rest-api/rate-limits-for-the-rest-api
+#[derive(Default)]
+pub struct RateLimiter {
+    state: Mutex<State>,
+}
+
+/// Return the name of the bucket a request to the given API path is counted against.
+/// This matches the values GitHub returns in the `X-RateLimit-Resource` header.
+pub fn resource_for_path(path: &str) -> &'static str {
+    if path.starts_with("/search/code") {
+        "code_search"
+    } else if path.starts_with("/search/") {
+        "search"
+    } else if path.starts_with("/graphql") {
+        "graphql"
+    } else {
+        "core"
+    }
+}
+
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+}
+
+impl RateLimiter {
+    /// Block until it is reasonable to send a request counted against the given bucket, which is then sent.
+    pub fn wait(&self, resource: &str) {
+        let delay = self.delay_for(resource, now_unix());
+        if !delay.is_zero() {
+            if delay >= Duration::from_secs(5) {
+                info!(
+                    "{} {} {}",
+                    "I am close to GitHub's rate limit, so I am going to wait".yellow(),
+                    delay.as_secs().to_string().white(),
+                    format!("seconds ({resource})").yellow()
+                );
+            }
+            sleep(delay);
+        }
+    }
+
+    /// Compute how long we should wait before sending a request, `now` being a Unix timestamp in seconds,
+    /// and count the request against its bucket right away: concurrent requests are only counted by GitHub
+    /// once they are answered, and they would all be sent on the last request left otherwise.
+    /// We don't sleep while holding the lock.
+    fn delay_for(&self, resource: &str, now: u64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+
+        // A secondary rate limit pauses everything
+        if let Some(until) = state.paused_until {
+            let now = Instant::now();
+            if until > now {
+                return until - now;
+            }
+        }
+
+        let bucket = match state.buckets.get_mut(resource) {
+            Some(b) => b,
+            // We haven't heard from GitHub about this bucket yet
+            None => return Duration::ZERO,
+        };
+        if bucket.reset <= now {
+            // The bucket has been refilled in the meantime
+            return Duration::ZERO;
+        }
+        let until_reset = Duration::from_secs(bucket.reset - now);
+
+        if bucket.remaining == 0 {
+            // Nothing left: wait for the bucket to be refilled
+            return until_reset + RESET_GRACE;
+        }
+        let remaining = bucket.remaining;
+        bucket.remaining -= 1;
+        if remaining < (bucket.limit / PACING_FRACTION).max(1) {
+            // Running low: spread the remaining requests over the time left so we don't stall abruptly
+            until_reset / remaining
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    /// Record the rate limit information of a response.
+    /// Returns `Some(wait)` if the response tells us that the request was rejected because of a
+    /// (primary or secondary) rate limit: in this case, the request should be sent again and
+    /// `wait` reports how long we are going to pause.
+    pub fn update(
+        &self,
+        path: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &str,
+    ) -> Option<Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        let resource = headers
+            .get("x-ratelimit-resource")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_else(|| resource_for_path(path))
+            .to_string();
+        let remaining = header_u64(headers, "x-ratelimit-remaining");
+        let reset = header_u64(headers, "x-ratelimit-reset");
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            let limit = header_u64(headers, "x-ratelimit-limit").unwrap_or(remaining);
+            let mut remaining = remaining as u32;
+            // In the same window, the requests we sent since this one are not counted by GitHub yet
+            if let Some(bucket) = state.buckets.get(&resource) {
+                if bucket.reset == reset {
+                    remaining = remaining.min(bucket.remaining);
+                }
+            }
+            state.buckets.insert(
+                resource,
+                Bucket {
+                    limit: limit as u32,
+                    remaining,
+                    reset,
+                },
+            );
+        }
+
+        if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+            return None;
+        }
+
+        // If GitHub tells us how long to wait, we do exactly that
+        if let Some(retry_after) = header_u64(headers, "retry-after") {
+            let wait = Duration::from_secs(retry_after);
+            state.paused_until = Some(Instant::now() + wait);
+            return Some(wait);
+        }
+
+        // Primary rate limit: the bucket is empty and we wait until it is refilled
+        if remaining == Some(0) {
+            let wait =
+                Duration::from_secs(reset.unwrap_or(0).saturating_sub(now_unix())) + RESET_GRACE;
+            return Some(wait);
+        }
+
+        // Secondary rate limit without a Retry-After header
+        if body.to_lowercase().contains("secondary rate limit") {
+            state.paused_until = Some(Instant::now() + SECONDARY_RATE_LIMIT_WAIT);
+            return Some(SECONDARY_RATE_LIMIT_WAIT);
+        }
+
+        // This is a regular 403/429
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The headers of a response with what is left of the `core` bucket, which is reset in `reset_in` seconds
+    fn headers(limit: u64, remaining: u64, reset_in: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-resource", "core".parse().unwrap());
+        headers.insert("x-ratelimit-limit", limit.into());
+        headers.insert("x-ratelimit-remaining", remaining.into());
+        headers.insert("x-ratelimit-reset", (now_unix() + reset_in).into());
+        headers
+    }
+
+    /// A limiter that got a response with these headers
+    fn limiter(headers: &HeaderMap) -> RateLimiter {
+        let limiter = RateLimiter::default();
+        assert_eq!(
+            limiter.update("/orgs/o/repos", StatusCode::OK, headers, ""),
+            None
+        );
+        limiter
+    }
+
+    fn reset(headers: &HeaderMap) -> u64 {
+        header_u64(headers, "x-ratelimit-reset").unwrap()
+    }
+
+    #[test]
+    fn no_delay_with_plenty_left_or_an_unknown_bucket() {
+        let headers = headers(5000, 4000, 100);
+        let limiter = limiter(&headers);
+        let now = reset(&headers) - 100;
+        assert_eq!(limiter.delay_for("core", now), Duration::ZERO);
+        assert_eq!(limiter.delay_for("search", now), Duration::ZERO);
+        // Once the bucket is reset, there is no reason to wait
+        assert_eq!(limiter.delay_for("core", reset(&headers)), Duration::ZERO);
+    }
+
+    #[test]
+    fn requests_are_paced_below_a_tenth_of_the_limit() {
+        let headers = headers(5000, 400, 100);
+        let limiter = limiter(&headers);
+        let now = reset(&headers) - 100;
+        // 100 seconds for 400 requests, then 399
+        assert_eq!(limiter.delay_for("core", now), Duration::from_millis(250));
+        assert_eq!(
+            limiter.delay_for("core", now),
+            Duration::from_secs(100) / 399
+        );
+        // Not at exactly a tenth of the limit
+        let limiter = self::limiter(&self::headers(5000, 500, 100));
+        assert_eq!(limiter.delay_for("core", now), Duration::ZERO);
+    }
+
+    #[test]
+    fn concurrent_requests_dont_overshoot_the_limit() {
+        let headers = headers(2, 2, 100);
+        let limiter = limiter(&headers);
+        let now = reset(&headers) - 100;
+        assert_eq!(limiter.delay_for("core", now), Duration::ZERO);
+        assert_eq!(limiter.delay_for("core", now), Duration::ZERO);
+        // Both were handed out before GitHub answered either of them
+        assert_eq!(
+            limiter.delay_for("core", now),
+            Duration::from_secs(100) + RESET_GRACE
+        );
+        // An answer to the first one doesn't give back what the second one took
+        assert_eq!(
+            limiter.update(
+                "/orgs/o/repos",
+                StatusCode::OK,
+                &self::headers(2, 1, 100),
+                ""
+            ),
+            None
+        );
+        assert_eq!(
+            limiter.delay_for("core", now),
+            Duration::from_secs(100) + RESET_GRACE
+        );
+    }
+
+    #[test]
+    fn nothing_left_waits_for_the_reset() {
+        let headers = headers(5000, 0, 100);
+        let limiter = limiter(&headers);
+        assert_eq!(
+            limiter.delay_for("core", reset(&headers) - 40),
+            Duration::from_secs(40) + RESET_GRACE
+        );
+
+        // GitHub rejected the request because of it
+        let wait = limiter
+            .update("/orgs/o/repos", StatusCode::FORBIDDEN, &headers, "")
+            .unwrap();
+        assert!(
+            wait > Duration::from_secs(99) && wait <= Duration::from_secs(101),
+            "{wait:?}"
+        );
+    }
+
+    #[test]
+    fn retry_after_pauses_every_bucket() {
+        let limiter = RateLimiter::default();
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", 30.into());
+        assert_eq!(
+            limiter.update("/search/code", StatusCode::TOO_MANY_REQUESTS, &headers, ""),
+            Some(Duration::from_secs(30))
+        );
+        for resource in ["core", "code_search"] {
+            let delay = limiter.delay_for(resource, now_unix());
+            assert!(
+                delay > Duration::from_secs(29) && delay <= Duration::from_secs(30),
+                "{delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn secondary_rate_limit_pauses_for_a_minute() {
+        let limiter = limiter(&headers(5000, 4000, 100));
+        let body = r#"{"message": "You have exceeded a secondary rate limit. Please wait a few minutes before you try again."}"#;
+        assert_eq!(
+            limiter.update(
+                "/orgs/o/repos",
+                StatusCode::FORBIDDEN,
+                &headers(5000, 3999, 100),
+                body
+            ),
+            Some(SECONDARY_RATE_LIMIT_WAIT)
+        );
+        let delay = limiter.delay_for("core", now_unix());
+        assert!(
+            delay > Duration::from_secs(59) && delay <= SECONDARY_RATE_LIMIT_WAIT,
+            "{delay:?}"
+        );
+    }
+
+    #[test]
+    fn other_forbidden_responses_are_not_rate_limits() {
+        let limiter = RateLimiter::default();
+        let body = r#"{"message": "Resource not accessible by integration"}"#;
+        assert_eq!(
+            limiter.update(
+                "/orgs/o/repos",
+                StatusCode::FORBIDDEN,
+                &headers(5000, 4000, 100),
+                body
+            ),
+            None
+        );
+        assert_eq!(limiter.delay_for("core", now_unix()), Duration::ZERO);
+    }
+}
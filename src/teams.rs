@@ -1,123 +1,192 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 use colored::Colorize;
+use log::{error, info};
 
 use crate::{
-    make_paginated_github_request, make_paginated_github_request_with_index, Bootstrap, Repository,
-    Team,
+    audit::Audit,
+    finding::{self, Target},
+    make_paginated_github_request, output,
+    preflight::{AuditKind, PreflightOptions},
+    shared, Bootstrap, Error, Finding, Findings, GitHubIndex, Repository, Skipped, Team,
 };
 
 /// Returns the repos that a team has access to
-fn get_team_repos(bootstrap: &Bootstrap, team: String) -> HashSet<Repository> {
-    let team_repos: HashSet<Repository> = match make_paginated_github_request(
-        &bootstrap.token,
+fn get_team_repos(bootstrap: &Bootstrap, team: &str) -> Result<Vec<Repository>, Error> {
+    make_paginated_github_request(
+        bootstrap,
         25,
         &format!("/orgs/{}/teams/{}/repos", bootstrap.org, team),
         3,
         None,
-    ) {
-        Ok(t) => t,
-        Err(e) => {
-            panic!(
-                "{} {}: {e}",
-                team.white(),
-                "I couldn't fetch the team's repositories".red()
-            );
+    )
+}
+
+/// The repositories a team can access
+pub struct TeamPermissionsAudit {
+    /// The slug of the team
+    pub team: String,
+}
+
+impl Audit for TeamPermissionsAudit {
+    fn name(&self) -> &'static str {
+        "team-perms"
+    }
+
+    fn description(&self) -> &'static str {
+        "List the repositories a team can access, and with which permission"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["members: read", "metadata: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::TeamPermissions
+    }
+
+    fn preflight_options(&self) -> PreflightOptions {
+        PreflightOptions {
+            team: Some(self.team.clone()),
+            ..Default::default()
         }
-    };
-    team_repos
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_team_repo_audit(bootstrap, self.team.clone())
+    }
+}
+
+/// Teams without members
+pub struct EmptyTeamsAudit;
+
+impl Audit for EmptyTeamsAudit {
+    fn name(&self) -> &'static str {
+        "empty-teams"
+    }
+
+    fn description(&self) -> &'static str {
+        "Teams without members, and how many repositories they can access"
+    }
+
+    fn required_permissions(&self) -> &'static [&'static str] {
+        &["members: read"]
+    }
+
+    fn kind(&self) -> AuditKind {
+        AuditKind::EmptyTeams
+    }
+
+    fn run(&self, bootstrap: &Bootstrap) -> Result<Findings, Error> {
+        run_empty_teams_audit(bootstrap)
+    }
 }
 
 /// Fetch all the repos for a given team and the permission it confers
-pub fn run_team_repo_audit(bootstrap: Bootstrap, team: String) {
-    let team_repos = get_team_repos(&bootstrap, team);
+pub fn run_team_repo_audit(bootstrap: &Bootstrap, team: String) -> Result<Findings, Error> {
+    let team_repos = get_team_repos(bootstrap, &team).inspect_err(|_| {
+        error!(
+            "{} {}",
+            team.white(),
+            "I couldn't fetch the team's repositories".red()
+        )
+    })?;
 
-    println!(
+    info!(
         "{} {} {}",
         "I found".green(),
         team_repos.len(),
         "repositories".green()
     );
 
+    let mut findings = Findings::default();
     for repo in team_repos {
-        println!("{}: {}", repo.name, repo.permissions.highest_perm());
+        let permission = repo.permissions.highest_perm();
+        output::line(format!("{}: {}", repo.name, permission));
+        findings.record_quietly(
+            Finding::new(
+                &finding::TEAM_REPOSITORY_ACCESS,
+                Target::RepositoryTeam {
+                    repo: repo.name.clone(),
+                    team: team.clone(),
+                },
+                format!("Team {team} has {permission} access to {}", repo.name),
+            )
+            .with("permission", permission),
+        );
     }
+    Ok(findings)
 }
 
-/// Get a list of all teams in the org
-pub fn get_org_teams(bootstrap: &Bootstrap) -> HashSet<Team> {
-    match make_paginated_github_request(
-        &bootstrap.token,
-        25,
-        &format!("/orgs/{}/teams", &bootstrap.org),
-        3,
-        None,
-    ) {
-        Ok(t) => t,
-        Err(e) => {
-            panic!(
-                "{}: {e}",
-                "I couldn't fetch the list of teams in the org".red()
-            );
-        }
-    }
+/// Get a list of all teams in the org. They are fetched only once per run, and shared by all audits
+pub fn get_org_teams(bootstrap: &Bootstrap) -> Result<Vec<Team>, Error> {
+    shared(&bootstrap.shared.teams, || {
+        make_paginated_github_request(
+            bootstrap,
+            25,
+            &format!("/orgs/{}/teams", &bootstrap.org),
+            3,
+            None,
+        )
+    })
+    .inspect_err(|_| error!("{}", "I couldn't fetch the list of teams in the org".red()))
 }
 
 /// Get a list of all teams in the org, indexed by the team slug
-pub fn get_indexed_org_teams(bootstrap: &Bootstrap) -> HashMap<String, Team> {
-    match make_paginated_github_request_with_index(
-        &bootstrap.token,
-        25,
-        &format!("/orgs/{}/teams", &bootstrap.org),
-        3,
-        None,
-    ) {
-        Ok(t) => t,
-        Err(e) => {
-            panic!(
-                "{}: {e}",
-                "I couldn't fetch the list of teams in the org".red()
-            );
-        }
-    }
+pub fn get_indexed_org_teams(bootstrap: &Bootstrap) -> Result<HashMap<String, Team>, Error> {
+    Ok(get_org_teams(bootstrap)?
+        .into_iter()
+        .map(|t| (t.index(), t))
+        .collect())
 }
 
 /// Fetch all empty teams, i.e., teams with no members
-pub fn run_empty_teams_audit(bootstrap: Bootstrap) {
-    println!(
+pub fn run_empty_teams_audit(bootstrap: &Bootstrap) -> Result<Findings, Error> {
+    info!(
         "{}",
         "I am going to fetch all teams from the org...".yellow()
     );
-    let teams = get_org_teams(&bootstrap);
+    let teams = get_org_teams(bootstrap)?;
 
-    println!(
+    info!(
         "{} {} {}",
         "Done: I found".green(),
         teams.len().to_string().white(),
         "teams".green()
     );
-    println!("{}", "Now I will check for empty teams...".yellow());
+    info!("{}", "Now I will check for empty teams...".yellow());
 
     // For each team, see if it's empty.
+    let mut skipped = Skipped::default();
+    let mut findings = Findings::default();
     for team in teams {
-        if let Ok(true) = team.is_empty(&bootstrap) {
-            // The team is empty: we want to see to how many repos it has access
-            let team_repos = get_team_repos(&bootstrap, team.slug);
-            println!(
-                "{}: {}. {} {} {}",
-                "Found an empty GH team".yellow(),
-                team.name.white(),
-                "This team has access to".yellow(),
-                team_repos.len().to_string().white(),
-                "repositories".yellow()
-            );
-        } else {
-            println!(
-                "{} {} {}",
-                "Warning! I could not determine if team".yellow(),
-                team.slug.white(),
-                "is empty. I will continue with other teams.".yellow()
-            );
+        match team.is_empty(bootstrap) {
+            Ok(true) => {
+                // The team is empty: we want to see to how many repos it has access
+                match get_team_repos(bootstrap, &team.slug) {
+                    Ok(team_repos) => findings.record(
+                        Finding::new(
+                            &finding::EMPTY_TEAM,
+                            Target::Team {
+                                team: team.slug.clone(),
+                            },
+                            format!(
+                                "Found an empty GH team: {}. This team has access to {} repositories",
+                                team.name,
+                                team_repos.len()
+                            ),
+                        )
+                        .with("repositories", team_repos.len()),
+                    ),
+                    Err(e) => skipped.record(team.slug, e),
+                }
+            }
+            Ok(false) => {}
+            Err(e) => skipped.record(team.slug, e),
         }
     }
+
+    skipped.report();
+    findings.report();
+    Ok(findings.with_skipped(skipped))
 }